@@ -71,6 +71,7 @@
 #include "circuitstats.h"
 #include "compress.h"
 #include "config.h"
+#include "config_rust.h"
 #include "connection.h"
 #include "connection_edge.h"
 #include "connection_or.h"
@@ -104,6 +105,7 @@
 #include "statefile.h"
 #include "transports.h"
 #include "ext_orport.h"
+#include "protover.h"
 #ifdef _WIN32
 #include <shlobj.h>
 #endif
@@ -672,6 +674,9 @@ static config_var_t option_vars_[] = {
   V(ChannelType,    UINT,   "1"),                   //  TLS=1  DUAL=2  PCTCP=3  IMUX=4
   V(UsingQuic,      UINT,   "0"),
 
+  /* for experiments with disabling protocol versions on a single relay */
+  V(SupportedProtocolsOverride, STRING, NULL),
+
   //Lamiaa: adding config value to define malicious node
     V(NodeType, UINT, "0"), // Malicious =1	Bengien =0
 
@@ -2344,6 +2349,24 @@ options_act(const or_options_t *old_options)
       !options->BridgeAuthoritativeDir)
     rep_hist_desc_stats_term();
 
+  /* Setting the override invalidates the old supported protocols string, so
+   * only do it when the override has changed.  We checked it in
+   * options_validate(), so it can't fail. */
+  if (!old_options ||
+      !opt_streq(old_options->SupportedProtocolsOverride,
+                 options->SupportedProtocolsOverride)) {
+    if (protover_set_supported_protocols_override(
+                                   options->SupportedProtocolsOverride) < 0) {
+      log_warn(LD_BUG, "Couldn't set SupportedProtocolsOverride.");
+      return -1;
+    }
+  }
+
+#ifdef HAVE_RUST
+  /* Let the Rust code see our new options. */
+  tor_options_changed();
+#endif /* defined(HAVE_RUST) */
+
   /* Since our options changed, we might need to regenerate and upload our
    * server descriptor.
    */
@@ -3462,6 +3485,11 @@ options_validate(or_options_t *old_options, or_options_t *options,
     routerset_union(options->ExcludeExitNodesUnion_,options->ExcludeNodes);
   }
 
+  if (options->ChannelType < CHANNEL_TYPE_TLS
+      || options->ChannelType > CHANNEL_TYPE_IMUX) {
+    REJECT("ChannelType must be between 1 and 4 inclusive.");
+  }
+
   if (options->UsingQuic) {
     const char *cert_path = getenv("QUICTOR_CERT_PATH");
     const char *key_path = getenv("QUICTOR_KEY_PATH");
@@ -3476,6 +3504,16 @@ options_validate(or_options_t *old_options, or_options_t *options,
     qs_init(cert_path, key_path);
   }
 
+  if (options->SupportedProtocolsOverride) {
+    char *err = protover_check_supported_protocols_override(
+                                        options->SupportedProtocolsOverride);
+    if (err) {
+      tor_asprintf(msg, "SupportedProtocolsOverride is invalid: %s", err);
+      tor_free(err);
+      return -1;
+    }
+  }
+
   if (options->NodeFamilies) {
     options->NodeFamilySets = smartlist_new();
     for (cl = options->NodeFamilies; cl; cl = cl->next) {
@@ -4837,6 +4875,9 @@ options_transition_affects_descriptor(const or_options_t *old_options,
   YES_IF_CHANGED_INT(AccountingRule);
   YES_IF_CHANGED_BOOL(DirCache);
   YES_IF_CHANGED_BOOL(AssumeReachable);
+  YES_IF_CHANGED_INT(ChannelType);
+  YES_IF_CHANGED_INT(UsingQuic);
+  YES_IF_CHANGED_STRING(SupportedProtocolsOverride);
 
   if (get_effective_bwrate(old_options) != get_effective_bwrate(new_options) ||
       get_effective_bwburst(old_options) !=
@@ -0,0 +1,56 @@
+/* Copyright (c) 2018, The Tor Project, Inc. */
+/* See LICENSE for licensing information */
+
+/**
+ * \file config_rust.c
+ * \brief Give the Rust code in src/rust/tor_options read-only access to the
+ * options it uses.
+ *
+ * Rust can't see inside or_options_t, so whenever options_act() calls
+ * tor_options_changed(), Rust calls config_get_rust_options() to copy the
+ * options it needs into a rust_options_t.
+ **/
+
+#include "or.h"
+#include "config.h"
+#include "config_rust.h"
+
+#ifdef HAVE_RUST
+
+/** Copy the options which Rust code uses from our current options into
+ * <b>out</b>.  Our options must already be set. */
+void
+config_get_rust_options(rust_options_t *out)
+{
+  const or_options_t *options = get_options();
+
+  tor_assert(out);
+  memset(out, 0, sizeof(*out));
+
+#define COPY(opt) out->opt = options->opt
+  COPY(ChannelType);
+  COPY(UsingQuic);
+  COPY(GlobalSchedulerUSec);
+  COPY(AutotuneWriteUSec);
+  COPY(AutotuneRefillUSec);
+  COPY(AutotuneFillLimitUSec);
+  COPY(AutotuneWriteBWOverride);
+  COPY(DualSwitchAtExit);
+  COPY(DualEwmaAlpha);
+  COPY(DualEwmaBeta);
+  COPY(DualThresholdLight);
+  COPY(DualThresholdHeavy);
+  COPY(DualThresholdInactive);
+  COPY(DualUseTrafficTracker);
+  COPY(IMUXScheduleType);
+  COPY(IMUXInitConnections);
+  COPY(IMUXMaxConnections);
+  COPY(IMUXSeparateBulkConnection);
+  COPY(IMUXSeparateWebConnection);
+  COPY(IMUXConnLimitThreshold);
+  COPY(IMUXTrafficType);
+#undef COPY
+}
+
+#endif /* defined(HAVE_RUST) */
+
@@ -0,0 +1,50 @@
+/* Copyright (c) 2018, The Tor Project, Inc. */
+/* See LICENSE for licensing information */
+
+/**
+ * \file config_rust.h
+ * \brief Headers for config_rust.c, and for the options hook in
+ * src/rust/tor_options.
+ **/
+
+#ifndef TOR_CONFIG_RUST_H
+#define TOR_CONFIG_RUST_H
+
+#ifdef HAVE_RUST
+
+/** The options which Rust code uses, copied out of or_options_t.  Each
+ * field is the option of the same name. */
+/// C_RUST_COUPLED: src/rust/tor_options/ffi.rs `RawOptions`
+typedef struct rust_options_t {
+  int ChannelType;
+  int UsingQuic;
+  int GlobalSchedulerUSec;
+  int AutotuneWriteUSec;
+  int AutotuneRefillUSec;
+  int AutotuneFillLimitUSec;
+  uint64_t AutotuneWriteBWOverride;
+  int DualSwitchAtExit;
+  double DualEwmaAlpha;
+  double DualEwmaBeta;
+  double DualThresholdLight;
+  double DualThresholdHeavy;
+  double DualThresholdInactive;
+  int DualUseTrafficTracker;
+  int IMUXScheduleType;
+  int IMUXInitConnections;
+  int IMUXMaxConnections;
+  int IMUXSeparateBulkConnection;
+  int IMUXSeparateWebConnection;
+  double IMUXConnLimitThreshold;
+  int IMUXTrafficType;
+} rust_options_t;
+
+void config_get_rust_options(rust_options_t *out);
+
+/* Defined in Rust, in src/rust/tor_options/ffi.rs */
+void tor_options_changed(void);
+
+#endif /* defined(HAVE_RUST) */
+
+#endif /* !defined(TOR_CONFIG_RUST_H) */
+
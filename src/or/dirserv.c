@@ -292,6 +292,19 @@ dirserv_router_get_status(const routerinfo_t *router, const char **msg,
   if (r)
     return r;
 
+  /* We don't reject routers with unparseable protocol lists (they just won't
+   * be counted as supporting anything), but say why, so that operators and
+   * monitoring can tell what is wrong with them. */
+  if (router->protocol_list) {
+    char *proto_error = protover_describe_parse_error(router->protocol_list);
+    if (proto_error) {
+      log_fn(severity, LD_DIRSERV,
+             "Router '%s' published an unparseable proto line %s: %s",
+             router->nickname, escaped(router->protocol_list), proto_error);
+      tor_free(proto_error);
+    }
+  }
+
   /* dirserv_get_status_impl already rejects versions older than 0.2.4.18-rc,
    * and onion_curve25519_pkey was introduced in 0.2.4.8-alpha.
    * But just in case a relay doesn't provide or lies about its version, or
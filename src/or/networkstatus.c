@@ -2595,43 +2595,11 @@ networkstatus_check_required_protocols(const networkstatus_t *ns,
                                        int client_mode,
                                        char **warning_out)
 {
-  const char *func = client_mode ? "client" : "relay";
-  const char *required, *recommended;
-  char *missing = NULL;
-
-  tor_assert(warning_out);
-
-  if (client_mode) {
-    required = ns->required_client_protocols;
-    recommended = ns->recommended_client_protocols;
-  } else {
-    required = ns->required_relay_protocols;
-    recommended = ns->recommended_relay_protocols;
-  }
-
-  if (!protover_all_supported(required, &missing)) {
-    tor_asprintf(warning_out, "At least one protocol listed as required in "
-                 "the consensus is not supported by this version of Tor. "
-                 "You should upgrade. This version of Tor will not work as a "
-                 "%s on the Tor network. The missing protocols are: %s",
-                 func, missing);
-    tor_free(missing);
-    return 1;
-  }
-
-  if (! protover_all_supported(recommended, &missing)) {
-    tor_asprintf(warning_out, "At least one protocol listed as recommended in "
-                 "the consensus is not supported by this version of Tor. "
-                 "You should upgrade. This version of Tor will eventually "
-                 "stop working as a %s on the Tor network. The missing "
-                 "protocols are: %s",
-                 func, missing);
-    tor_free(missing);
-  }
-
-  tor_assert_nonfatal(missing == NULL);
-
-  return 0;
+  return protover_check_requirements(ns->required_client_protocols,
+                                     ns->recommended_client_protocols,
+                                     ns->required_relay_protocols,
+                                     ns->recommended_relay_protocols,
+                                     client_mode, warning_out);
 }
 
 /** Free all storage held locally in this module. */
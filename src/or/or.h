@@ -4785,6 +4785,10 @@ typedef struct {
 
   /** whether or not to use Quic sockets */
   int UsingQuic;
+
+  /** If set, the protocol versions to support and advertise instead of the
+   * compiled-in ones.  May only list versions we were compiled to support. */
+  char *SupportedProtocolsOverride;
   /**
      * Whether or not this node is malicious
      */
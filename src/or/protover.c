@@ -27,14 +27,19 @@
 #include "protover.h"
 #include "routerparse.h"
 
-#ifndef HAVE_RUST
+/* src/test/test-protover-parity.c builds this implementation alongside the
+ * Rust one, under other names, to check that they agree. */
+#if !defined(HAVE_RUST) || defined(PROTOVER_PARITY_TEST)
 
 static const smartlist_t *get_supported_protocol_list(void);
 static int protocol_list_contains(const smartlist_t *protos,
                                   protocol_type_t pr, uint32_t ver);
+static int protocol_list_contains_range(const smartlist_t *protos,
+                                        protocol_type_t pr,
+                                        uint32_t low, uint32_t high);
 
 /** Mapping between protocol type string and protocol type. */
-/// C_RUST_COUPLED: src/rust/protover/protover.rs `PROTOCOL_NAMES`
+/// C_RUST_COUPLED: src/rust/protover/protover.rs `protocols!`
 static const struct {
   protocol_type_t protover_type;
   const char *name;
@@ -48,7 +53,12 @@ static const struct {
   { PRT_HSREND, "HSRend" },
   { PRT_DESC, "Desc" },
   { PRT_MICRODESC, "Microdesc"},
-  { PRT_CONS, "Cons" }
+  { PRT_CONS, "Cons" },
+  { PRT_PADDING, "Padding" },
+  { PRT_FLOWCTRL, "FlowCtrl" },
+  { PRT_QUIC, "Quic" },
+  { PRT_IMUX, "Imux" },
+  { PRT_CHANTYPE, "ChanType" }
 };
 
 #define N_PROTOCOL_NAMES ARRAY_LENGTH(PROTOCOL_NAMES)
@@ -331,11 +341,46 @@ protocol_list_supports_protocol_or_later(const char *list,
   return contains;
 }
 
+/**
+ * Return true iff "list" encodes a protocol list for a relay that speaks
+ * channels of type <b>channel_type</b> (a channel_type_t).  A relay with no
+ * list, or whose list doesn't mention ChanType at all, speaks only TLS.
+ */
+/// C_RUST_COUPLED: src/rust/protover/transport.rs `supports_channel_type`
+int
+protocol_list_supports_channel_type(const char *list, int channel_type)
+{
+  const char *chantype_name = protocol_type_to_str(PRT_CHANTYPE);
+  int advertised = 0;
+
+  if (channel_type < CHANNEL_TYPE_TLS || channel_type > CHANNEL_TYPE_IMUX)
+    return 0;
+  if (!list)
+    return channel_type == CHANNEL_TYPE_TLS;
+
+  smartlist_t *protocols = parse_protocol_list(list);
+  if (!protocols) {
+    return 0;
+  }
+  SMARTLIST_FOREACH(protocols, const proto_entry_t *, ent,
+                    advertised |= !strcasecmp(ent->name, chantype_name));
+  int contains = protocol_list_contains(protocols, PRT_CHANTYPE,
+                                        (uint32_t) channel_type);
+
+  SMARTLIST_FOREACH(protocols, proto_entry_t *, ent, proto_entry_free(ent));
+  smartlist_free(protocols);
+  return advertised ? contains : channel_type == CHANNEL_TYPE_TLS;
+}
+
+/** The override for the protocols we support, as set by
+ * protover_set_supported_protocols_override(), or NULL if there is none. */
+static char *supported_protocols_override = NULL;
+
 /** Return the canonical string containing the list of protocols
- * that we support. */
-/// C_RUST_COUPLED: src/rust/protover/protover.rs `SUPPORTED_PROTOCOLS`
-const char *
-protover_get_supported_protocols(void)
+ * that we were compiled to support. */
+/// C_RUST_COUPLED: src/rust/protover/protover.rs `protocols!`
+static const char *
+get_compiled_in_protocols(void)
 {
   return
     "Cons=1-2 "
@@ -350,6 +395,51 @@ protover_get_supported_protocols(void)
     "Relay=1-2";
 }
 
+/** Return the canonical string containing the list of protocols
+ * that we support: those we were compiled to support, unless they have been
+ * overridden with protover_set_supported_protocols_override(). */
+/// C_RUST_COUPLED: src/rust/protover/protover.rs `get_supported_protocols`
+const char *
+protover_get_supported_protocols(void)
+{
+  if (supported_protocols_override)
+    return supported_protocols_override;
+  return get_compiled_in_protocols();
+}
+
+/** Return a newly allocated string listing the protocols we support when
+ * configured to use channels of type <b>channel_type</b> (a channel_type_t),
+ * and to use QUIC sockets iff <b>using_quic</b> is set.  These are the
+ * protocols from protover_get_supported_protocols(), along with a ChanType
+ * and a Quic entry as appropriate, in canonical order.  We accept channels
+ * of every type, whichever one we open, so the ChanType entry lists them
+ * all. */
+/// C_RUST_COUPLED: src/rust/protover/transport.rs `supported_protocols_for`
+char *
+protover_get_supported_protocols_for_transport(int channel_type,
+                                               int using_quic)
+{
+  smartlist_t *entries = smartlist_new();
+  char *result;
+
+  smartlist_split_string(entries, protover_get_supported_protocols(), " ",
+                         SPLIT_IGNORE_BLANK, 0);
+  if (channel_type >= CHANNEL_TYPE_TLS && channel_type <= CHANNEL_TYPE_IMUX)
+    smartlist_add_asprintf(entries, "ChanType=%d-%d",
+                           CHANNEL_TYPE_TLS, CHANNEL_TYPE_IMUX);
+  if (using_quic)
+    smartlist_add_strdup(entries, "Quic=1");
+
+  /* No protocol name we know is another one followed by a digit, so sorting
+   * the entries as strings sorts them by protocol name. */
+  smartlist_sort_strings(entries);
+  result = smartlist_join_strings(entries, " ", 0, NULL);
+
+  SMARTLIST_FOREACH(entries, char *, cp, tor_free(cp));
+  smartlist_free(entries);
+  return result;
+}
+
 /** The protocols from protover_get_supported_protocols(), as parsed into a
  * list of proto_entry_t values. Access this via
  * get_supported_protocol_list. */
@@ -367,6 +457,95 @@ get_supported_protocol_list(void)
   return supported_protocol_list;
 }
 
+/** Helper: free the list of proto_entry_t <b>protos</b>. */
+static void
+proto_entry_list_free(smartlist_t *protos)
+{
+  if (!protos)
+    return;
+  SMARTLIST_FOREACH(protos, proto_entry_t *, ent, proto_entry_free(ent));
+  smartlist_free(protos);
+}
+
+/** Return NULL if <b>s</b> is acceptable as an override for the protocols
+ * we support: that is, if it parses, and lists only protocol versions that
+ * we were compiled to support.  Otherwise, return a newly allocated string
+ * describing what is wrong with it.
+ *
+ * The Rust implementation of this function also names the protocol in
+ * which the problem occurred.
+ */
+/// C_RUST_DIFFERS: src/rust/protover/ffi.rs
+///                 `protover_check_supported_protocols_override`
+char *
+protover_check_supported_protocols_override(const char *s)
+{
+  smartlist_t *requested = NULL, *compiled_in = NULL;
+  char *result = NULL;
+
+  if (!s)
+    return NULL;
+
+  requested = parse_protocol_list(s);
+  if (!requested)
+    return tor_strdup("The protover string was unparseable.");
+  compiled_in = parse_protocol_list(get_compiled_in_protocols());
+
+  SMARTLIST_FOREACH_BEGIN(requested, const proto_entry_t *, ent) {
+    protocol_type_t pr;
+    if (str_to_protocol_type(ent->name, &pr) < 0) {
+      result = tor_strdup("A protocol in the protover string we attempted "
+                          "to parse is unknown.");
+      break;
+    }
+    /* The compiled-in protocols are canonical, so any range we support lies
+     * within a single one of their ranges. */
+    SMARTLIST_FOREACH_BEGIN(ent->ranges, const proto_range_t *, range) {
+      if (!protocol_list_contains_range(compiled_in, pr,
+                                        range->low, range->high)) {
+        result = tor_strdup("The protover string listed protocol versions "
+                            "which we do not support.");
+        break;
+      }
+    } SMARTLIST_FOREACH_END(range);
+    if (result)
+      break;
+  } SMARTLIST_FOREACH_END(ent);
+
+  proto_entry_list_free(requested);
+  proto_entry_list_free(compiled_in);
+  return result;
+}
+
+/** Override the protocols we claim to support with those in <b>s</b>, or
+ * remove any override if <b>s</b> is NULL.  Return 0 on success, or -1 if
+ * <b>s</b> isn't acceptable (see
+ * protover_check_supported_protocols_override()), in which case the current
+ * override is left in place.
+ *
+ * Any pointer previously returned by protover_get_supported_protocols() may
+ * be invalidated by this function.  (The Rust implementation stores
+ * overrides in canonical form.) */
+/// C_RUST_DIFFERS: src/rust/protover/protover.rs
+///                 `set_supported_protocols_override`
+int
+protover_set_supported_protocols_override(const char *s)
+{
+  char *err = protover_check_supported_protocols_override(s);
+  if (err) {
+    tor_free(err);
+    return -1;
+  }
+
+  tor_free(supported_protocols_override);
+  if (s)
+    supported_protocols_override = tor_strdup(s);
+
+  proto_entry_list_free(supported_protocol_list);
+  supported_protocol_list = NULL;
+  return 0;
+}
+
 /**
  * Given a protocol entry, encode it at the end of the smartlist <b>chunks</b>
  * as one or more newly allocated strings.
@@ -659,6 +838,32 @@ protover_compute_vote(const smartlist_t *list_of_proto_strings,
   return result;
 }
 
+/** If the protocol list <b>s</b> cannot be parsed, return a newly allocated
+ * string describing why, suitable for logging.  Otherwise (or if <b>s</b> is
+ * NULL), return NULL.
+ *
+ * The Rust implementation of this function also reports the byte offset,
+ * the offending token, and the protocol in which the error occurred.
+ */
+/// C_RUST_DIFFERS: src/rust/protover/ffi.rs `protover_describe_parse_error`
+char *
+protover_describe_parse_error(const char *s)
+{
+  smartlist_t *entries;
+
+  if (!s)
+    return NULL;
+
+  entries = parse_protocol_list(s);
+  if (entries) {
+    SMARTLIST_FOREACH(entries, proto_entry_t *, ent, proto_entry_free(ent));
+    smartlist_free(entries);
+    return NULL;
+  }
+
+  return tor_strdup("The protover string was unparseable.");
+}
+
 /** Return true if every protocol version described in the string <b>s</b> is
  * one that we support, and false otherwise.  If <b>missing_out</b> is
  * provided, set it to the list of protocols we do not support.
@@ -786,6 +991,82 @@ protover_all_supported(const char *s, char **missing_out)
   return all_supported;
 }
 
+/** Helper for protover_check_requirements(): return true iff we support
+ * every protocol in the consensus protocol line <b>s</b>, which may be
+ * NULL.  Otherwise, set *<b>missing_out</b> to a newly allocated string
+ * listing the protocols we're missing.  We warn about a line we can't parse,
+ * and treat it as requiring nothing, so that one malformed line can't make
+ * every relay and client exit. */
+static int
+requirements_line_met(const char *s, char **missing_out)
+{
+  if (s) {
+    smartlist_t *entries = parse_protocol_list(s);
+    if (!entries) {
+      log_warn(LD_NET, "Received an unparseable protocol list %s"
+               " from the consensus", escaped(s));
+      return 1;
+    }
+    proto_entry_list_free(entries);
+  }
+  return protover_all_supported(s, missing_out);
+}
+
+/** Check whether the protocols listed as "required" or "recommended" for
+ * clients (if <b>client_mode</b> is set) or relays (otherwise) by a consensus
+ * include any versions that we do not support.  Any of the four lists may be
+ * NULL.  If so, set *<b>warning_out</b> (unless it is NULL) to a newly
+ * allocated string describing the problem.
+ *
+ * Return 1 if we should exit, 0 if we should not. */
+/// C_RUST_COUPLED: src/rust/protover/requirements.rs `Verdict::warning`
+int
+protover_check_requirements(const char *required_client,
+                            const char *recommended_client,
+                            const char *required_relay,
+                            const char *recommended_relay,
+                            int client_mode,
+                            char **warning_out)
+{
+  const char *func = client_mode ? "client" : "relay";
+  const char *required, *recommended;
+  char *missing = NULL;
+
+  if (client_mode) {
+    required = required_client;
+    recommended = recommended_client;
+  } else {
+    required = required_relay;
+    recommended = recommended_relay;
+  }
+
+  if (!requirements_line_met(required, &missing)) {
+    if (warning_out)
+      tor_asprintf(warning_out, "At least one protocol listed as required in "
+                   "the consensus is not supported by this version of Tor. "
+                   "You should upgrade. This version of Tor will not work as "
+                   "a %s on the Tor network. The missing protocols are: %s",
+                   func, missing);
+    tor_free(missing);
+    return 1;
+  }
+
+  if (!requirements_line_met(recommended, &missing)) {
+    if (warning_out)
+      tor_asprintf(warning_out, "At least one protocol listed as recommended "
+                   "in the consensus is not supported by this version of "
+                   "Tor. You should upgrade. This version of Tor will "
+                   "eventually stop working as a %s on the Tor network. The "
+                   "missing protocols are: %s",
+                   func, missing);
+    tor_free(missing);
+  }
+
+  tor_assert_nonfatal(missing == NULL);
+
+  return 0;
+}
+
 /** Helper: Given a list of proto_entry_t, return true iff
  * <b>pr</b>=<b>ver</b> is included in that list. */
 static int
@@ -813,6 +1094,33 @@ protocol_list_contains(const smartlist_t *protos,
   return 0;
 }
 
+/** Helper: Given a list of proto_entry_t, return true iff every version
+ * of <b>pr</b> from <b>low</b> through <b>high</b> is included in a single
+ * range in that list. */
+static int
+protocol_list_contains_range(const smartlist_t *protos,
+                             protocol_type_t pr, uint32_t low, uint32_t high)
+{
+  if (BUG(protos == NULL)) {
+    return 0; // LCOV_EXCL_LINE
+  }
+  const char *pr_name = protocol_type_to_str(pr);
+  if (BUG(pr_name == NULL)) {
+    return 0; // LCOV_EXCL_LINE
+  }
+
+  SMARTLIST_FOREACH_BEGIN(protos, const proto_entry_t *, ent) {
+    if (strcasecmp(ent->name, pr_name))
+      continue;
+    SMARTLIST_FOREACH_BEGIN(ent->ranges, const proto_range_t *, range) {
+      if (low >= range->low && high <= range->high)
+        return 1;
+    } SMARTLIST_FOREACH_END(range);
+  } SMARTLIST_FOREACH_END(ent);
+
+  return 0;
+}
+
 /** Return a string describing the protocols supported by tor version
  * <b>version</b>, or an empty string if we cannot tell.
  *
@@ -820,6 +1128,7 @@ protocol_list_contains(const smartlist_t *protos,
  * can't declare their own.
  **/
 /// C_RUST_COUPLED: src/rust/protover/protover.rs `compute_for_old_tor`
+/// C_RUST_COUPLED: src/rust/protover/data/protocol_history.txt
 const char *
 protover_compute_for_old_tor(const char *version)
 {
@@ -867,7 +1176,8 @@ protover_free_all(void)
     smartlist_free(entries);
     supported_protocol_list = NULL;
   }
+  tor_free(supported_protocols_override);
 }
 
-#endif /* !defined(HAVE_RUST) */
+#endif /* !defined(HAVE_RUST) || defined(PROTOVER_PARITY_TEST) */
 
@@ -28,7 +28,7 @@
 
 /** List of recognized subprotocols. */
 /// C_RUST_COUPLED: src/rust/protover/ffi.rs `translate_to_rust`
-/// C_RUST_COUPLED: src/rust/protover/protover.rs `Proto`
+/// C_RUST_COUPLED: src/rust/protover/protover.rs `protocols!`
 typedef enum protocol_type_t {
   PRT_LINK,
   PRT_LINKAUTH,
@@ -40,11 +40,27 @@ typedef enum protocol_type_t {
   PRT_DESC,
   PRT_MICRODESC,
   PRT_CONS,
+  PRT_PADDING,
+  PRT_FLOWCTRL,
+  PRT_QUIC,
+  PRT_IMUX,
+  PRT_CHANTYPE,
 } protocol_type_t;
 
 int protover_all_supported(const char *s, char **missing);
+int protover_check_requirements(const char *required_client,
+                                const char *recommended_client,
+                                const char *required_relay,
+                                const char *recommended_relay,
+                                int client_mode,
+                                char **warning_out);
+char *protover_describe_parse_error(const char *s);
 int protover_is_supported_here(protocol_type_t pr, uint32_t ver);
 const char *protover_get_supported_protocols(void);
+char *protover_get_supported_protocols_for_transport(int channel_type,
+                                                     int using_quic);
+char *protover_check_supported_protocols_override(const char *s);
+int protover_set_supported_protocols_override(const char *s);
 
 char *protover_compute_vote(const smartlist_t *list_of_proto_strings,
                             int threshold);
@@ -54,6 +70,7 @@ int protocol_list_supports_protocol(const char *list, protocol_type_t tp,
 int protocol_list_supports_protocol_or_later(const char *list,
                                              protocol_type_t tp,
                                              uint32_t version);
+int protocol_list_supports_channel_type(const char *list, int channel_type);
 
 void protover_free_all(void);
 
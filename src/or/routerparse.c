@@ -4883,6 +4883,7 @@ microdescs_parse_from_string(const char *s, const char *eos,
  * If <b>strict</b> is non-zero, finding any weird version components
  * (like negative numbers) counts as a parsing failure.
  */
+/// C_RUST_COUPLED: src/rust/protover/version.rs `TorVersion::from_platform`
 int
 tor_version_parse_platform(const char *platform,
                            tor_version_t *router_version,
@@ -4927,6 +4928,7 @@ tor_version_parse_platform(const char *platform,
  * and compare it to the version in <b>cutoff</b>. Return 1 if
  * the router is at least as new as the cutoff, else return 0.
  */
+/// C_RUST_COUPLED: src/rust/protover/version.rs `is_as_new_as`
 int
 tor_version_as_new_as(const char *platform, const char *cutoff)
 {
@@ -4962,6 +4964,7 @@ tor_version_as_new_as(const char *platform, const char *cutoff)
 
 /** Parse a tor version from <b>s</b>, and store the result in <b>out</b>.
  * Return 0 on success, -1 on failure. */
+/// C_RUST_COUPLED: src/rust/protover/version.rs `TorVersion::parse_bytes`
 int
 tor_version_parse(const char *s, tor_version_t *out)
 {
@@ -5071,6 +5074,7 @@ tor_version_parse(const char *s, tor_version_t *out)
 
 /** Compare two tor versions; Return <0 if a < b; 0 if a ==b, >0 if a >
  * b. */
+/// C_RUST_COUPLED: src/rust/protover/version.rs `impl Ord for TorVersion`
 int
 tor_version_compare(tor_version_t *a, tor_version_t *b)
 {
@@ -0,0 +1,155 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Checking and rewriting protocol lists in canonical form.
+//!
+//! A protocol list is canonical when each protocol is listed exactly once,
+//! the protocol names are in ascending (bytewise, as C's `strcmp()` would
+//! sort them) order, and each protocol's versions are written in ascending
+//! order using as few ranges as possible.  For example, `"Cons=1-2 Link=1-3,5"`
+//! is canonical, but the equivalent `"Link=1-2,3,5 Cons=1,2"` is not.
+//!
+//! This is the form produced by `ProtoverVote::compute()`, and by
+//! `protover_compute_vote()` in C, so relays which publish their `proto`
+//! lines in it won't cause needless churn in consensus diffs.
+
+use std::collections::BTreeMap;
+
+use errors::ProtoverError;
+use protoset::ProtoSet;
+use protover::UnvalidatedProtoEntry;
+
+/// Rewrite a protocol list in canonical form.
+///
+/// The `protocols` are parsed leniently (as by `UnvalidatedProtoEntry`'s
+/// `FromStr` impl), except that a protocol which is listed more than once has
+/// all of its versions kept, rather than just those of its last entry.
+///
+/// # Returns
+///
+/// A `Result` whose `Ok` value is the canonical form of `protocols`, and
+/// whose `Err` value is the `ProtoverError` from parsing them.  Byte offsets
+/// in the error are relative to the start of `protocols`.
+///
+/// # Examples
+///
+/// ```
+/// use protover::canonical::canonicalize;
+///
+/// assert_eq!(Ok("Cons=1-2 Link=1-3,5".to_string()),
+///            canonicalize("Link=1-2,3,5 Cons=1,2"));
+/// assert_eq!(Ok("Link=1-4".to_string()), canonicalize("Link=3-4 Link=1-2"));
+/// assert_eq!(Ok("".to_string()), canonicalize(""));
+/// ```
+pub fn canonicalize(protocols: &str) -> Result<String, ProtoverError> {
+    let mut merged: BTreeMap<String, ProtoSet> = BTreeMap::new();
+    let mut offset: usize = 0;
+
+    // An empty protocol list is what we'd produce for no protocols at all.
+    if protocols.is_empty() {
+        return Ok(String::new());
+    }
+
+    // Parse each entry on its own, so that repeated protocols can be merged
+    // instead of overwriting one another.
+    for entry in protocols.split(' ') {
+        let parsed: UnvalidatedProtoEntry = entry.parse().map_err(|e: ProtoverError| {
+            e.offset_by(offset)
+        })?;
+
+        offset += entry.len() + 1;
+
+        for (protocol, versions) in parsed.iter() {
+            let all: &mut ProtoSet = merged.entry(protocol.to_string())
+                .or_insert(ProtoSet::default());
+
+            *all = all.union(versions);
+        }
+    }
+
+    let parts: Vec<String> = merged.iter()
+        .map(|(name, versions)| format!("{}={}", name, versions.to_string()))
+        .collect();
+
+    Ok(parts.join(" "))
+}
+
+/// Determine if a protocol list is already in canonical form.
+///
+/// # Returns
+///
+/// A `Result` whose `Ok` value is `true` iff `protocols` is exactly its own
+/// canonical form (see `canonicalize()`), and whose `Err` value is the
+/// `ProtoverError` from parsing it.
+///
+/// # Examples
+///
+/// ```
+/// use protover::canonical::is_canonical;
+///
+/// assert_eq!(Ok(true), is_canonical("Cons=1-2 Link=1-3,5"));
+/// assert_eq!(Ok(false), is_canonical("Cons=1-2 Link=1-2,3,5"));
+/// assert_eq!(Ok(false), is_canonical("Link=1-3,5 Cons=1-2"));
+/// assert!(is_canonical("Link=1-x").is_err());
+/// ```
+pub fn is_canonical(protocols: &str) -> Result<bool, ProtoverError> {
+    Ok(canonicalize(protocols)? == protocols)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    macro_rules! assert_canonicalizes {
+        ($input:expr, $expected:expr) => (
+            assert_eq!(Ok($expected.to_string()), canonicalize($input), "{:?}", $input);
+            assert_eq!(Ok($input == $expected), is_canonical($input), "{:?}", $input);
+        )
+    }
+
+    #[test]
+    fn test_canonicalize_already_canonical() {
+        assert_canonicalizes!("Cons=1-2", "Cons=1-2");
+        assert_canonicalizes!("Fribble=", "Fribble=");
+        assert_canonicalizes!("Cons=1-2 Desc=1-2 DirCache=1-2 HSDir=1-2 HSIntro=3-4 HSRend=1-2 \
+                               Link=1-5 LinkAuth=1,3 Microdesc=1-2 Relay=1-2",
+                              "Cons=1-2 Desc=1-2 DirCache=1-2 HSDir=1-2 HSIntro=3-4 HSRend=1-2 \
+                               Link=1-5 LinkAuth=1,3 Microdesc=1-2 Relay=1-2");
+    }
+
+    #[test]
+    fn test_canonicalize_merges_adjacent_ranges() {
+        assert_canonicalizes!("Link=1-2,3", "Link=1-3");
+        assert_canonicalizes!("Link=1,2,3,5", "Link=1-3,5");
+        assert_canonicalizes!("Link=1-1", "Link=1");
+        assert_canonicalizes!("Link=7,1-6", "Link=1-7");
+        assert_canonicalizes!("Link=0-4294967293,4294967294", "Link=0-4294967294");
+    }
+
+    #[test]
+    fn test_canonicalize_sorts_protocols_bytewise() {
+        assert_canonicalizes!("Relay=1 Cons=2", "Cons=2 Relay=1");
+        assert_canonicalizes!("LinkAuth=1 Link=2", "Link=2 LinkAuth=1");
+        assert_canonicalizes!("a=1 B=1", "B=1 a=1");
+    }
+
+    #[test]
+    fn test_canonicalize_merges_repeated_protocols() {
+        assert_canonicalizes!("Link=4-5 Cons=1 Link=1-3", "Cons=1 Link=1-5");
+        assert_canonicalizes!("Link=1 Link=", "Link=1");
+    }
+
+    #[test]
+    fn test_canonicalize_tolerates_lenient_input() {
+        assert_canonicalizes!("Link=3,,1", "Link=1,3");
+        assert_canonicalizes!("Link=+1,02", "Link=1-2");
+    }
+
+    #[test]
+    fn test_canonicalize_error_offset() {
+        let expected = ProtoverError::Unparseable { at: 14, token: "1-x".to_string() };
+
+        assert_eq!(Err(expected.in_protocol("Link")), canonicalize("Cons=1-2 Link=1-x"));
+        assert!(canonicalize("Cons=1  Link=2").is_err());
+    }
+}
@@ -8,16 +8,43 @@ use std::fmt;
 use std::fmt::Display;
 
 /// All errors which may occur during protover parsing routines.
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
 #[allow(missing_docs)] // See Display impl for error descriptions
 pub enum ProtoverError {
     Overlap,
     LowGreaterThanHigh,
-    Unparseable,
+    /// The `token` beginning at byte offset `at` could not be parsed.
+    Unparseable { at: usize, token: String },
     ExceedsMax,
     ExceedsExpansionLimit,
     UnknownProtocol,
     ExceedsNameLimit,
+    DuplicateProtocol,
+    Unordered,
+    NotSupportedHere,
+    /// The `error` occurred while parsing the entry for `protocol`.
+    InProtocol { protocol: String, error: Box<ProtoverError> },
+}
+
+impl ProtoverError {
+    /// Make the byte offset of this error (if it has one) relative to an
+    /// enclosing string, in which the string we were parsing began `n` bytes
+    /// in.
+    pub(crate) fn offset_by(self, n: usize) -> ProtoverError {
+        match self {
+            ProtoverError::Unparseable { at, token }
+                => ProtoverError::Unparseable { at: at + n, token },
+            ProtoverError::InProtocol { protocol, error }
+                => ProtoverError::InProtocol { protocol, error: Box::new(error.offset_by(n)) },
+            other => other,
+        }
+    }
+
+    /// Record that this error occurred within the entry for `protocol`,
+    /// wrapping it in a `ProtoverError::InProtocol`.
+    pub fn in_protocol(self, protocol: &str) -> ProtoverError {
+        ProtoverError::InProtocol { protocol: protocol.to_string(), error: Box::new(self) }
+    }
 }
 
 /// Descriptive error messages for `ProtoverError` variants.
@@ -28,8 +55,8 @@ impl Display for ProtoverError {
                 => write!(f, "Two or more (low, high) protover ranges would overlap once expanded."),
             ProtoverError::LowGreaterThanHigh
                 => write!(f, "The low in a (low, high) protover range was greater than high."),
-            ProtoverError::Unparseable
-                => write!(f, "The protover string was unparseable."),
+            ProtoverError::Unparseable { at, ref token }
+                => write!(f, "The protover string was unparseable at byte {}: {:?}.", at, token),
             ProtoverError::ExceedsMax
                 => write!(f, "The high in a (low, high) protover range exceeds u32::MAX."),
             ProtoverError::ExceedsExpansionLimit
@@ -38,6 +65,14 @@ impl Display for ProtoverError {
                 => write!(f, "A protocol in the protover string we attempted to parse is unknown."),
             ProtoverError::ExceedsNameLimit
                 => write!(f, "An unrecognised protocol name was too long."),
+            ProtoverError::DuplicateProtocol
+                => write!(f, "A protocol was listed more than once in the protover string."),
+            ProtoverError::Unordered
+                => write!(f, "The protocols or versions in the protover string were not in ascending order."),
+            ProtoverError::NotSupportedHere
+                => write!(f, "The protover string listed protocol versions which we do not support."),
+            ProtoverError::InProtocol { ref protocol, ref error }
+                => write!(f, "In protocol {:?}: {}", protocol, error),
         }
     }
 }
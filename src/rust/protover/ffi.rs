@@ -4,261 +4,514 @@
 //! FFI functions, only to be called from C.
 //!
 //! Equivalent C versions of this api are in `src/or/protover.c`
+//!
+//! Each function is defined with `ffi_fn!`, so that if it panics (in a build
+//! which unwinds, rather than aborting), the panic is logged as a bug, and it
+//! returns the value in brackets after its return type.
 
 use libc::{c_char, c_int, uint32_t};
 use std::ffi::CStr;
-use std::ffi::CString;
+use std::ptr;
+use std::sync::Arc;
 
 use smartlist::*;
-use tor_allocate::allocate_and_copy_string;
+use tor_allocate::TorOwnedCString;
+use tor_log::LD_NET;
 use tor_util::strings::byte_slice_is_c_like;
 use tor_util::strings::empty_static_cstr;
 
 
 use errors::ProtoverError;
 use protover::*;
+use requirements::*;
+use transport::*;
 
 /// Translate C enums to Rust Proto enums, using the integer value of the C
-/// enum to map to its associated Rust enum.
+/// enum to look up its associated Rust enum in the protocol registry.
 ///
 /// C_RUST_COUPLED: src/or/protover.h `protocol_type_t`
 fn translate_to_rust(c_proto: uint32_t) -> Result<Protocol, ProtoverError> {
-    match c_proto {
-        0 => Ok(Protocol::Link),
-        1 => Ok(Protocol::LinkAuth),
-        2 => Ok(Protocol::Relay),
-        3 => Ok(Protocol::DirCache),
-        4 => Ok(Protocol::HSDir),
-        5 => Ok(Protocol::HSIntro),
-        6 => Ok(Protocol::HSRend),
-        7 => Ok(Protocol::Desc),
-        8 => Ok(Protocol::Microdesc),
-        9 => Ok(Protocol::Cons),
-        _ => Err(ProtoverError::UnknownProtocol),
+    PROTOCOLS.iter()
+        .find(|info| info.c_id == c_proto)
+        .map(|info| info.protocol.clone())
+        .ok_or(ProtoverError::UnknownProtocol)
+}
+
+/// Copy `string` into a newly allocated C string, which the caller must free
+/// with `tor_free()`.  None of our strings contain a NUL, but if one did, the
+/// caller would get an empty string.
+fn allocate_c_string(string: &str) -> *mut c_char {
+    TorOwnedCString::new(string).unwrap_or_default().into_raw()
+}
+
+ffi_fn! {
+    /// Provide an interface for C to translate arguments and return types for
+    /// protover::all_supported
+    pub extern "C" fn protover_all_supported(
+        c_relay_version: *const c_char,
+        missing_out: *mut *mut c_char,
+    ) -> c_int [1] {
+
+        if c_relay_version.is_null() {
+            return 1;
+        }
+
+        // Require an unsafe block to read the version from a C string. The pointer
+        // is checked above to ensure it is not null.
+        let c_str: &CStr = unsafe { CStr::from_ptr(c_relay_version) };
+
+        let relay_proto_entry: UnvalidatedProtoEntry = match c_str.to_str().map(str::parse) {
+            Ok(Ok(n)) => n,
+            _ => {
+                log_warn!(LD_NET, protover_all_supported,
+                          "Received an unparseable protocol list {:?} from the consensus", c_str);
+                return 1;
+            },
+        };
+        let maybe_unsupported: Option<UnvalidatedProtoEntry> = relay_proto_entry.all_supported();
+
+        if maybe_unsupported.is_some() {
+            let unsupported: UnvalidatedProtoEntry = maybe_unsupported.unwrap();
+            let c_unsupported = match TorOwnedCString::new(unsupported.to_string()) {
+                Ok(n) => n,
+                Err(_) => return 1,
+            };
+
+            // As in C, the caller may pass NULL if it doesn't want to know
+            // what's missing.
+            if !missing_out.is_null() {
+                unsafe { *missing_out = c_unsupported.into_raw() };
+            }
+
+            return 0;
+        }
+
+        1
     }
 }
 
-/// Provide an interface for C to translate arguments and return types for
-/// protover::all_supported
-#[no_mangle]
-pub extern "C" fn protover_all_supported(
-    c_relay_version: *const c_char,
-    missing_out: *mut *mut c_char,
-) -> c_int {
+ffi_fn! {
+    /// Provide an interface for C to learn why a protocol list is unparseable,
+    /// so that it can log something more useful than a bare failure.
+    ///
+    /// Returns NULL if `c_protocol_list` is NULL or parses successfully.
+    /// Otherwise, returns a newly allocated description of the first error,
+    /// including its byte offset and the protocol it occurred in, which the
+    /// caller must free with `tor_free()`.
+    pub extern "C" fn protover_describe_parse_error(
+        c_protocol_list: *const c_char,
+    ) -> *mut c_char [ptr::null_mut()] {
+        if c_protocol_list.is_null() {
+            return ptr::null_mut();
+        }
+
+        // Require an unsafe block to read the version from a C string. The pointer
+        // is checked above to ensure it is not null.
+        let c_str: &CStr = unsafe { CStr::from_ptr(c_protocol_list) };
+
+        let protocol_list = match c_str.to_str() {
+            Ok(n) => n,
+            Err(e) => {
+                let error = ProtoverError::Unparseable { at: e.valid_up_to(), token: String::new() };
+                return allocate_c_string(&error.to_string());
+            },
+        };
 
-    if c_relay_version.is_null() {
-        return 1;
+        match protocol_list.parse::<UnvalidatedProtoEntry>() {
+            Ok(_)  => ptr::null_mut(),
+            Err(e) => allocate_c_string(&e.to_string()),
+        }
     }
+}
 
-    // Require an unsafe block to read the version from a C string. The pointer
-    // is checked above to ensure it is not null.
-    let c_str: &CStr = unsafe { CStr::from_ptr(c_relay_version) };
-
-    let relay_version = match c_str.to_str() {
-        Ok(n) => n,
-        Err(_) => return 1,
-    };
-
-    let relay_proto_entry: UnvalidatedProtoEntry = match relay_version.parse() {
-        Ok(n)  => n,
-        Err(_) => return 1,
-    };
-    let maybe_unsupported: Option<UnvalidatedProtoEntry> = relay_proto_entry.all_supported();
-
-    if maybe_unsupported.is_some() {
-        let unsupported: UnvalidatedProtoEntry = maybe_unsupported.unwrap();
-        let c_unsupported: CString = match CString::new(unsupported.to_string()) {
+ffi_fn! {
+    /// Provide an interface for C to translate arguments and return types for
+    /// protover::list_supports_protocol
+    pub extern "C" fn protocol_list_supports_protocol(
+        c_protocol_list: *const c_char,
+        c_protocol: uint32_t,
+        version: uint32_t,
+    ) -> c_int [0] {
+        if c_protocol_list.is_null() {
+            return 1;
+        }
+
+        // Require an unsafe block to read the version from a C string. The pointer
+        // is checked above to ensure it is not null.
+        let c_str: &CStr = unsafe { CStr::from_ptr(c_protocol_list) };
+
+        let protocol_list = match c_str.to_str() {
+            Ok(n) => n,
+            Err(_) => return 1,
+        };
+        let proto_entry: UnvalidatedProtoEntry = match protocol_list.parse() {
+            Ok(n)  => n,
+            Err(_) => return 0,
+        };
+        let protocol: UnknownProtocol = match translate_to_rust(c_protocol) {
+            Ok(n) => n.into(),
+            Err(_) => return 0,
+        };
+        match proto_entry.supports_protocol(&protocol, &version) {
+            false => return 0,
+            true  => return 1,
+        }
+    }
+}
+
+ffi_fn! {
+    /// Provide an interface for C to translate arguments and return types for
+    /// protover::list_supports_protocol_or_later
+    pub extern "C" fn protocol_list_supports_protocol_or_later(
+        c_protocol_list: *const c_char,
+        c_protocol: uint32_t,
+        version: uint32_t,
+    ) -> c_int [0] {
+        if c_protocol_list.is_null() {
+            return 1;
+        }
+
+        // Require an unsafe block to read the version from a C string. The pointer
+        // is checked above to ensure it is not null.
+        let c_str: &CStr = unsafe { CStr::from_ptr(c_protocol_list) };
+
+        let protocol_list = match c_str.to_str() {
             Ok(n) => n,
             Err(_) => return 1,
         };
 
-        let ptr = c_unsupported.into_raw();
-        unsafe { *missing_out = ptr };
+        let protocol = match translate_to_rust(c_protocol) {
+            Ok(n) => n,
+            Err(_) => return 0,
+        };
+
+        // As in C, a list we can't parse doesn't support anything.
+        let proto_entry: UnvalidatedProtoEntry = match protocol_list.parse() {
+            Ok(n)  => n,
+            Err(_) => return 0,
+        };
 
-        return 0;
+        if proto_entry.supports_protocol_or_later(&protocol.into(), &version) {
+            return 1;
+        }
+        0
     }
+}
 
-    1
+ffi_fn! {
+    /// Provide an interface for C to translate arguments and return types for
+    /// protover::get_supported_protocols
+    pub extern "C" fn protover_get_supported_protocols() -> *const c_char
+        [empty_static_cstr().as_ptr()]
+    {
+        let current: Arc<SupportedProtocols> = supported_protocols();
+        let supported: &CStr;
+        let supported_bytes: &[u8] = current.protocols_with_nul();
+
+        // If we're going to pass it to C, there cannot be any intermediate NUL
+        // bytes.  An assert is okay here, since changing the protocol registry
+        // in protover.rs to contain a NUL byte somewhere in the middle would be a
+        // programming error.
+        assert!(byte_slice_is_c_like(supported_bytes));
+
+        // It's okay to unwrap the result of this function because
+        // we can see that the bytes we're passing into it 1) are valid UTF-8,
+        // 2) have no intermediate NUL bytes, and 3) are terminated with a NUL
+        // byte.
+        supported = CStr::from_bytes_with_nul(supported_bytes).unwrap();
+
+        // The protocols stay alive after `current` is dropped, until they
+        // are replaced by protover_set_supported_protocols_override(), which
+        // C treats as invalidating this pointer.
+        supported.as_ptr()
+    }
 }
 
-/// Provide an interface for C to translate arguments and return types for
-/// protover::list_supports_protocol
-#[no_mangle]
-pub extern "C" fn protocol_list_supports_protocol(
-    c_protocol_list: *const c_char,
-    c_protocol: uint32_t,
-    version: uint32_t,
-) -> c_int {
-    if c_protocol_list.is_null() {
-        return 1;
+ffi_fn! {
+    /// Provide an interface for C to translate arguments and return types for
+    /// protover::check_supported_protocols_override
+    ///
+    /// Returns NULL if `c_protocols` is acceptable as an override for our
+    /// supported protocols, and otherwise a newly allocated description of what
+    /// is wrong with it, which the caller must free.
+    pub extern "C" fn protover_check_supported_protocols_override(
+        c_protocols: *const c_char,
+    ) -> *mut c_char [allocate_c_string("internal error")] {
+        if c_protocols.is_null() {
+            return ptr::null_mut();
+        }
+
+        // Require an unsafe block to read the version from a C string. The pointer
+        // is checked above to ensure it is not null.
+        let c_str: &CStr = unsafe { CStr::from_ptr(c_protocols) };
+
+        let protocols: &str = match c_str.to_str() {
+            Ok(n) => n,
+            Err(e) => {
+                let error = ProtoverError::Unparseable { at: e.valid_up_to(), token: String::new() };
+                return allocate_c_string(&error.to_string());
+            },
+        };
+
+        match check_supported_protocols_override(protocols) {
+            Ok(_) => ptr::null_mut(),
+            Err(e) => allocate_c_string(&e.to_string()),
+        }
     }
+}
 
-    // Require an unsafe block to read the version from a C string. The pointer
-    // is checked above to ensure it is not null.
-    let c_str: &CStr = unsafe { CStr::from_ptr(c_protocol_list) };
-
-    let protocol_list = match c_str.to_str() {
-        Ok(n) => n,
-        Err(_) => return 1,
-    };
-    let proto_entry: UnvalidatedProtoEntry = match protocol_list.parse() {
-        Ok(n)  => n,
-        Err(_) => return 0,
-    };
-    let protocol: UnknownProtocol = match translate_to_rust(c_protocol) {
-        Ok(n) => n.into(),
-        Err(_) => return 0,
-    };
-    match proto_entry.supports_protocol(&protocol, &version) {
-        false => return 0,
-        true  => return 1,
+ffi_fn! {
+    /// Provide an interface for C to translate arguments and return types for
+    /// protover::set_supported_protocols_override
+    ///
+    /// A NULL `c_protocols` removes any override.  Returns 0 on success, and -1
+    /// (leaving the current override in place) if `c_protocols` isn't an
+    /// acceptable override.
+    pub extern "C" fn protover_set_supported_protocols_override(
+        c_protocols: *const c_char,
+    ) -> c_int [-1] {
+        let protocols: Option<&str> = if c_protocols.is_null() {
+            None
+        } else {
+            // Require an unsafe block to read the version from a C string. The
+            // pointer is checked above to ensure it is not null.
+            let c_str: &CStr = unsafe { CStr::from_ptr(c_protocols) };
+
+            match c_str.to_str() {
+                Ok(n) => Some(n),
+                Err(_) => return -1,
+            }
+        };
+
+        match set_supported_protocols_override(protocols) {
+            Ok(()) => 0,
+            Err(_) => -1,
+        }
     }
 }
 
-/// Provide an interface for C to translate arguments and return types for
-/// protover::list_supports_protocol_or_later
-#[no_mangle]
-pub extern "C" fn protocol_list_supports_protocol_or_later(
-    c_protocol_list: *const c_char,
-    c_protocol: uint32_t,
-    version: uint32_t,
-) -> c_int {
-    if c_protocol_list.is_null() {
-        return 1;
+ffi_fn! {
+    /// Provide an interface for C to translate arguments and return types for
+    /// protover::transport::supported_protocols_for
+    ///
+    /// The returned string must be freed by the caller.
+    pub extern "C" fn protover_get_supported_protocols_for_transport(
+        channel_type: c_int,
+        using_quic: c_int,
+    ) -> *mut c_char [ptr::null_mut()] {
+        let config = TransportConfig {
+            channel_type: ChannelType::from_c(channel_type as u32),
+            using_quic: using_quic != 0,
+        };
+
+        allocate_c_string(&supported_protocols_for(&config))
     }
+}
 
-    // Require an unsafe block to read the version from a C string. The pointer
-    // is checked above to ensure it is not null.
-    let c_str: &CStr = unsafe { CStr::from_ptr(c_protocol_list) };
+ffi_fn! {
+    /// Provide an interface for C to translate arguments and return types for
+    /// protover::transport::supports_channel_type
+    pub extern "C" fn protocol_list_supports_channel_type(
+        c_protocol_list: *const c_char,
+        channel_type: c_int,
+    ) -> c_int [0] {
+        let channel_type: ChannelType = match ChannelType::from_c(channel_type as u32) {
+            Some(n) => n,
+            None => return 0,
+        };
 
-    let protocol_list = match c_str.to_str() {
-        Ok(n) => n,
-        Err(_) => return 1,
-    };
+        // With no protocol list at all, a relay speaks only TLS.
+        if c_protocol_list.is_null() {
+            return (channel_type == ChannelType::Tls) as c_int;
+        }
 
-    let protocol = match translate_to_rust(c_protocol) {
-        Ok(n) => n,
-        Err(_) => return 0,
-    };
+        // Require an unsafe block to read the version from a C string. The pointer
+        // is checked above to ensure it is not null.
+        let c_str: &CStr = unsafe { CStr::from_ptr(c_protocol_list) };
 
-    let proto_entry: UnvalidatedProtoEntry = match protocol_list.parse() {
-        Ok(n)  => n,
-        Err(_) => return 1,
-    };
+        let protocol_list = match c_str.to_str() {
+            Ok(n) => n,
+            Err(_) => return 0,
+        };
 
-    if proto_entry.supports_protocol_or_later(&protocol.into(), &version) {
-        return 1;
+        supports_channel_type(protocol_list, channel_type) as c_int
     }
-    0
 }
 
-/// Provide an interface for C to translate arguments and return types for
-/// protover::get_supported_protocols
-#[no_mangle]
-pub extern "C" fn protover_get_supported_protocols() -> *const c_char {
-    let supported: &'static CStr;
-
-    // If we're going to pass it to C, there cannot be any intermediate NUL
-    // bytes.  An assert is okay here, since changing the const byte slice
-    // in protover.rs to contain a NUL byte somewhere in the middle would be a
-    // programming error.
-    assert!(byte_slice_is_c_like(SUPPORTED_PROTOCOLS));
-
-    // It's okay to unwrap the result of this function because
-    // we can see that the bytes we're passing into it 1) are valid UTF-8,
-    // 2) have no intermediate NUL bytes, and 3) are terminated with a NUL
-    // byte.
-    supported = CStr::from_bytes_with_nul(SUPPORTED_PROTOCOLS).unwrap();
-
-    supported.as_ptr()
+/// Parse one of the protocol lines of a consensus for
+/// `protover_check_requirements`.  As with `protover_all_supported`, a NULL
+/// line requires nothing, and so does one which can't be parsed, after a
+/// warning.
+fn requirements_entry(c_line: *const c_char) -> UnvalidatedProtoEntry {
+    if c_line.is_null() {
+        return UnvalidatedProtoEntry::default();
+    }
+
+    // Require an unsafe block to read the version from a C string. The pointer
+    // is checked above to ensure it is not null.
+    let c_str: &CStr = unsafe { CStr::from_ptr(c_line) };
+
+    match c_str.to_str().map(parse_consensus_line) {
+        Ok(Ok(n)) => n,
+        _ => {
+            log_warn!(LD_NET, protover_check_requirements,
+                      "Received an unparseable protocol list {:?} from the consensus", c_str);
+            UnvalidatedProtoEntry::default()
+        },
+    }
 }
 
-/// Provide an interface for C to translate arguments and return types for
-/// protover::compute_vote
-//
-// Why is the threshold a signed integer? —isis
-#[no_mangle]
-pub extern "C" fn protover_compute_vote(
-    list: *const Stringlist,
-    threshold: c_int,
-) -> *mut c_char {
-
-    if list.is_null() {
-        let empty = String::new();
-        return allocate_and_copy_string(&empty);
+ffi_fn! {
+    /// Provide an interface for C to translate arguments and return types for
+    /// protover::requirements::ProtocolRequirements::evaluate
+    ///
+    /// Returns 1 if we should exit, and 0 otherwise.  If we are missing any
+    /// required or recommended protocol, sets `*warning_out` (unless it is NULL)
+    /// to a newly allocated description of the problem, which must be freed by
+    /// the caller.  A protocol line which can't be parsed is warned about, and
+    /// treated as requiring nothing.
+    pub extern "C" fn protover_check_requirements(
+        c_required_client: *const c_char,
+        c_recommended_client: *const c_char,
+        c_required_relay: *const c_char,
+        c_recommended_relay: *const c_char,
+        client_mode: c_int,
+        warning_out: *mut *mut c_char,
+    ) -> c_int [0] {
+        let role: Role = if client_mode != 0 { Role::Client } else { Role::Relay };
+        let mut requirements: ProtocolRequirements = ProtocolRequirements::default();
+
+        // Only our own role's lines are looked at, or warned about.
+        match role {
+            Role::Client => {
+                requirements.required_client = requirements_entry(c_required_client);
+                requirements.recommended_client = requirements_entry(c_recommended_client);
+            },
+            Role::Relay => {
+                requirements.required_relay = requirements_entry(c_required_relay);
+                requirements.recommended_relay = requirements_entry(c_recommended_relay);
+            },
+        }
+
+        let verdict: Verdict = requirements.evaluate(role, &supported_protocols().entry);
+
+        if !warning_out.is_null() {
+            if let Some(warning) = verdict.warning(role) {
+                unsafe { *warning_out = allocate_c_string(&warning) };
+            }
+        }
+
+        verdict.should_exit() as c_int
     }
+}
 
-    // Dereference of raw pointer requires an unsafe block. The pointer is
-    // checked above to ensure it is not null.
-    let data: Vec<String> = unsafe { (*list).get_list() };
-    let hold: usize = threshold as usize;
-    let mut proto_entries: Vec<UnvalidatedProtoEntry> = Vec::new();
+ffi_fn! {
+    /// Provide an interface for C to translate arguments and return types for
+    /// protover::compute_vote
+    //
+    // Why is the threshold a signed integer? —isis
+    pub extern "C" fn protover_compute_vote(
+        list: *const Stringlist,
+        threshold: c_int,
+    ) -> *mut c_char [allocate_c_string("")] {
+
+        if list.is_null() {
+            return allocate_c_string("");
+        }
+
+        // Dereference of raw pointer requires an unsafe block. The pointer is
+        // checked above to ensure it is not null.  The votes are borrowed
+        // from the list rather than copied, and any which aren't UTF-8 are
+        // skipped, like any others which don't parse.
+        let votes: &Stringlist = unsafe { &*list };
+        let hold: usize = threshold as usize;
+        let mut proto_entries: Vec<UnvalidatedProtoEntry> = Vec::new();
+
+        for vote in votes.cstrs() {
+            let entry: UnvalidatedProtoEntry = match vote.to_str().map(str::parse) {
+                Ok(Ok(x)) => x,
+                _ => {
+                    log_warn!(LD_NET, protover_compute_vote,
+                              "I failed with parsing a protocol list from an authority. \
+                               The offending string was: {:?}", vote);
+                    continue;
+                },
+            };
+            // ProtoverVote::compute() skips this too, but doesn't log.
+            if entry.len() > MAX_PROTOCOLS_TO_EXPAND {
+                log_warn!(LD_NET, protover_compute_vote,
+                          "When expanding a protocol list from an authority, I got too many \
+                           protocols. This is possibly an attack or a bug, unless the Tor \
+                           network truly has expanded to support over {} different \
+                           subprotocol versions. The offending string was: {:?}",
+                          MAX_PROTOCOLS_TO_EXPAND, vote);
+                continue;
+            }
+            proto_entries.push(entry);
+        }
+        let vote: UnvalidatedProtoEntry = ProtoverVote::compute(&proto_entries, &hold);
+
+        allocate_c_string(&vote.to_string())
+    }
+}
 
-    for datum in data {
-        let entry: UnvalidatedProtoEntry = match datum.parse() {
-            Ok(x)  => x,
-            Err(_) => continue,
+ffi_fn! {
+    /// Provide an interface for C to translate arguments and return types for
+    /// protover::is_supported_here
+    pub extern "C" fn protover_is_supported_here(
+        c_protocol: uint32_t,
+        version: uint32_t,
+    ) -> c_int [0] {
+        let protocol = match translate_to_rust(c_protocol) {
+            Ok(n) => n,
+            Err(_) => return 0,
         };
-        proto_entries.push(entry);
-    }
-    let vote: UnvalidatedProtoEntry = ProtoverVote::compute(&proto_entries, &hold);
 
-    allocate_and_copy_string(&vote.to_string())
-}
+        let is_supported = is_supported_here(&protocol, &version);
 
-/// Provide an interface for C to translate arguments and return types for
-/// protover::is_supported_here
-#[no_mangle]
-pub extern "C" fn protover_is_supported_here(
-    c_protocol: uint32_t,
-    version: uint32_t,
-) -> c_int {
-    let protocol = match translate_to_rust(c_protocol) {
-        Ok(n) => n,
-        Err(_) => return 0,
-    };
-
-    let is_supported = is_supported_here(&protocol, &version);
-
-    return if is_supported { 1 } else { 0 };
+        return if is_supported { 1 } else { 0 };
+    }
 }
 
-/// Provide an interface for C to translate arguments and return types for
-/// protover::compute_for_old_tor
-#[no_mangle]
-pub extern "C" fn protover_compute_for_old_tor(version: *const c_char) -> *const c_char {
-    let supported: &'static CStr;
-    let elder_protocols: &'static [u8];
-    let empty: &'static CStr;
+ffi_fn! {
+    /// Provide an interface for C to translate arguments and return types for
+    /// protover::compute_for_old_tor
+    pub extern "C" fn protover_compute_for_old_tor(version: *const c_char) -> *const c_char
+        [empty_static_cstr().as_ptr()]
+    {
+        let supported: &'static CStr;
+        let elder_protocols: &'static [u8];
+        let empty: &'static CStr;
 
-    empty = empty_static_cstr();
+        empty = empty_static_cstr();
 
-    if version.is_null() {
-        return empty.as_ptr();
-    }
+        if version.is_null() {
+            return empty.as_ptr();
+        }
 
-    // Require an unsafe block to read the version from a C string. The pointer
-    // is checked above to ensure it is not null.
-    let c_str: &CStr = unsafe { CStr::from_ptr(version) };
+        // Require an unsafe block to read the version from a C string. The pointer
+        // is checked above to ensure it is not null.
+        let c_str: &CStr = unsafe { CStr::from_ptr(version) };
 
-    let version = match c_str.to_str() {
-        Ok(n) => n,
-        Err(_) => return empty.as_ptr(),
-    };
+        let version = match c_str.to_str() {
+            Ok(n) => n,
+            Err(_) => return empty.as_ptr(),
+        };
 
-    elder_protocols = compute_for_old_tor_cstr(&version);
+        elder_protocols = compute_for_old_tor_cstr(&version);
 
-    // If we're going to pass it to C, there cannot be any intermediate NUL
-    // bytes.  An assert is okay here, since changing the const byte slice
-    // in protover.rs to contain a NUL byte somewhere in the middle would be a
-    // programming error.
-    assert!(byte_slice_is_c_like(elder_protocols));
+        // If we're going to pass it to C, there cannot be any intermediate NUL
+        // bytes.  An assert is okay here, since changing the const byte slice
+        // in protover.rs to contain a NUL byte somewhere in the middle would be a
+        // programming error.
+        assert!(byte_slice_is_c_like(elder_protocols));
 
-    // It's okay to unwrap the result of this function because
-    // we can see that the bytes we're passing into it 1) are valid UTF-8,
-    // 2) have no intermediate NUL bytes, and 3) are terminated with a NUL
-    // byte.
-    supported = CStr::from_bytes_with_nul(elder_protocols).unwrap_or(empty);
+        // It's okay to unwrap the result of this function because
+        // we can see that the bytes we're passing into it 1) are valid UTF-8,
+        // 2) have no intermediate NUL bytes, and 3) are terminated with a NUL
+        // byte.
+        supported = CStr::from_bytes_with_nul(elder_protocols).unwrap_or(empty);
 
-    supported.as_ptr()
+        supported.as_ptr()
+    }
 }
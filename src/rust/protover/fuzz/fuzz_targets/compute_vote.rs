@@ -0,0 +1,50 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Fuzz `ProtoverVote::compute`.  The first byte is the threshold, and each
+//! following line a vote.  Every version in the outcome must have been voted
+//! for at least that many times, and the outcome must reparse.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate protover;
+
+use std::str;
+
+use protover::ProtoverVote;
+use protover::UnvalidatedProtoEntry;
+
+fuzz_target!(|data: &[u8]| {
+    let (threshold, rest): (usize, &[u8]) = match data.split_first() {
+        Some((first, rest)) => ((*first % 8) as usize, rest),
+        None => return,
+    };
+    let input: &str = match str::from_utf8(rest) {
+        Ok(s) => s,
+        Err(_) => return,
+    };
+    let votes: Vec<UnvalidatedProtoEntry> = input.lines()
+        .filter_map(|line| line.parse().ok())
+        .collect();
+    let outcome: UnvalidatedProtoEntry = ProtoverVote::compute(&votes, &threshold);
+
+    for (protocol, versions) in outcome.iter() {
+        for &(low, high) in versions.iter() {
+            for version in [low, high].iter() {
+                let n_votes: usize = votes.iter()
+                    .filter(|vote| vote.supports_protocol(protocol, version))
+                    .count();
+
+                assert!(n_votes >= threshold.max(1),
+                        "{}={} has only {} votes", protocol.to_string(), version, n_votes);
+            }
+        }
+    }
+
+    let output: String = outcome.to_string();
+
+    if !output.is_empty() {
+        assert_eq!(Ok(outcome), output.parse::<UnvalidatedProtoEntry>());
+    }
+});
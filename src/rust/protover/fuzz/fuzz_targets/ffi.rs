@@ -0,0 +1,92 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Fuzz the FFI entry points which take protocol lists from C.  The first
+//! byte picks a protocol (by its C id, modulo 16) and the second a version
+//! (modulo 8); the rest, up to any NUL byte, is the list, and split into
+//! lines, the votes.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate libc;
+extern crate protover;
+extern crate smartlist;
+
+use std::ffi::CString;
+use std::ptr;
+
+use libc::c_char;
+use libc::c_int;
+use libc::c_void;
+
+use protover::ffi::*;
+use smartlist::Stringlist;
+
+/// tor's allocator, which the FFI uses for the strings it returns, as the
+/// plain allocator which `libc::free` expects.
+#[no_mangle]
+pub unsafe extern "C" fn tor_malloc_(size: usize) -> *mut c_void {
+    libc::malloc(size)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tor_free_(ptr: *mut c_void) {
+    libc::free(ptr)
+}
+
+/// tor's logging, which the FFI uses to warn about the lists it rejects.  A
+/// minimum severity of 0 means nothing is logged, so no time is spent
+/// formatting messages.
+#[no_mangle]
+pub static log_global_min_severity_: c_int = 0;
+
+#[no_mangle]
+pub extern "C" fn log_fn_(_severity: c_int, _domain: u32, _funcname: *const c_char,
+                          _format: *const c_char, _message: *const c_char) {
+}
+
+/// Free a string which the FFI allocated with `tor_malloc_`.
+fn free_c_string(s: *mut c_char) {
+    unsafe { libc::free(s as *mut c_void) };
+}
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 2 {
+        return;
+    }
+    let protocol: u32 = (data[0] % 16) as u32;
+    let version: u32 = (data[1] % 8) as u32;
+    let list: &[u8] = &data[2..];
+    let list: &[u8] = &list[..list.iter().position(|&b| b == 0).unwrap_or(list.len())];
+    let c_list: CString = CString::new(list).unwrap();
+
+    let mut missing: *mut c_char = ptr::null_mut();
+    protover_all_supported(c_list.as_ptr(), &mut missing);
+    if !missing.is_null() {
+        free_c_string(missing);
+    }
+
+    let supports: c_int = protocol_list_supports_protocol(c_list.as_ptr(), protocol, version);
+    let or_later: c_int =
+        protocol_list_supports_protocol_or_later(c_list.as_ptr(), protocol, version);
+    assert!(supports == 0 || or_later == 1);
+
+    free_c_string(protover_describe_parse_error(c_list.as_ptr()));
+    free_c_string(protover_check_supported_protocols_override(c_list.as_ptr()));
+    protover_is_supported_here(protocol, version);
+    protover_compute_for_old_tor(c_list.as_ptr());
+    protocol_list_supports_channel_type(c_list.as_ptr(), version as c_int);
+
+    let votes: Vec<CString> = list.split(|&b| b == b'\n')
+        .map(|line| CString::new(line).unwrap())
+        .collect();
+    let pointers: Vec<*const c_char> = votes.iter().map(|vote| vote.as_ptr()).collect();
+    let smartlist: Stringlist = Stringlist {
+        list: pointers.as_ptr(),
+        num_used: pointers.len() as c_int,
+        capacity: pointers.len() as c_int,
+    };
+
+    free_c_string(protover_compute_vote(&smartlist, data[0] as c_int % 8));
+});
@@ -0,0 +1,54 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Fuzz `UnvalidatedProtoEntry::from_str` and `ProtoEntry::from_str`: parsing
+//! what either writes out must give back the same protocols.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate protover;
+
+use std::str;
+
+use protover::ProtoEntry;
+use protover::UnvalidatedProtoEntry;
+
+fuzz_target!(|data: &[u8]| {
+    let input: &str = match str::from_utf8(data) {
+        Ok(s) => s,
+        Err(_) => return,
+    };
+
+    if let Ok(entry) = input.parse::<UnvalidatedProtoEntry>() {
+        let output: String = entry.to_string();
+
+        // An empty string doesn't parse, so there's nothing to compare.
+        if output.is_empty() {
+            assert!(entry.is_empty());
+        } else {
+            let reparsed: UnvalidatedProtoEntry = output.parse()
+                .expect("an UnvalidatedProtoEntry didn't reparse");
+
+            assert_eq!(entry, reparsed);
+            assert_eq!(output, reparsed.to_string());
+        }
+        // Everything which all_supported() finds missing is in the original.
+        if let Some(unsupported) = entry.all_supported() {
+            assert!(unsupported.len() <= entry.len());
+        }
+    }
+
+    if let Ok(entry) = input.parse::<ProtoEntry>() {
+        let output: String = entry.to_string();
+
+        if output.is_empty() {
+            assert!(entry.is_empty());
+        } else {
+            let reparsed: ProtoEntry = output.parse()
+                .expect("a ProtoEntry didn't reparse");
+
+            assert_eq!(entry, reparsed);
+        }
+    }
+});
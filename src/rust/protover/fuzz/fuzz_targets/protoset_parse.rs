@@ -0,0 +1,32 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Fuzz `ProtoSet::from_str`: whatever it accepts must be written back out
+//! canonically, and parse to the same set again.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate protover;
+
+use std::str;
+
+use protover::protoset::ParseMode;
+use protover::protoset::ProtoSet;
+
+fuzz_target!(|data: &[u8]| {
+    let input: &str = match str::from_utf8(data) {
+        Ok(s) => s,
+        Err(_) => return,
+    };
+    let versions: ProtoSet = match input.parse() {
+        Ok(versions) => versions,
+        Err(_) => return,
+    };
+    let output: String = versions.to_string();
+    let reparsed: ProtoSet = ProtoSet::parse_with(&output, ParseMode::Strict)
+        .expect("a ProtoSet wasn't written canonically");
+
+    assert_eq!(versions, reparsed);
+    assert_eq!(output, reparsed.to_string());
+});
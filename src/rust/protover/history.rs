@@ -0,0 +1,321 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! The protocols supported by each historical Tor release.
+//!
+//! Relays running versions of Tor older than
+//! `FIRST_TOR_VERSION_TO_ADVERTISE_PROTOCOLS` don't list their protocols, so
+//! we have to infer them from their versions.  The table of which versions
+//! supported which protocols lives in `data/protocol_history.txt`, which is
+//! embedded in the crate when it's built.  It also covers later versions, so
+//! that we can ask which is the oldest version to support some protocol.
+
+use std::ptr;
+use std::sync::Once;
+
+use errors::ProtoverError;
+use protover::FIRST_TOR_VERSION_TO_ADVERTISE_PROTOCOLS;
+use protover::ProtoEntry;
+use protover::UnvalidatedProtoEntry;
+use version::TorVersion;
+use version::platform_is_as_new_as;
+
+/// The table of historical protocols, as embedded from the data file.
+const PROTOCOL_HISTORY: &'static str = include_str!("data/protocol_history.txt");
+
+/// The protocols supported by a range of Tor versions, from one line of the
+/// table in `data/protocol_history.txt`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProtocolHistoryEntry {
+    /// The first version to support these protocols.
+    pub since: TorVersion,
+    /// Whether versions from `since` onwards list their protocols themselves,
+    /// rather than having them inferred.
+    pub advertised: bool,
+    /// The protocols, parsed.
+    pub protocols: ProtoEntry,
+    /// The protocols, as a string in canonical form, followed by a NUL byte.
+    protocols_with_nul: String,
+}
+
+impl ProtocolHistoryEntry {
+    /// Get the protocols supported by this range of versions, as a string in
+    /// canonical form.
+    pub fn protocols_str(&self) -> &str {
+        &self.protocols_with_nul[..self.protocols_with_nul.len() - 1]
+    }
+
+    /// Get the protocols supported by this range of versions, as a string
+    /// ending in a NUL byte, so that it can be passed to C.
+    pub(crate) fn protocols_with_nul(&self) -> &[u8] {
+        self.protocols_with_nul.as_bytes()
+    }
+}
+
+/// Parse a table of historical protocols, in the format of
+/// `data/protocol_history.txt`.
+///
+/// Each line which isn't blank or a `#` comment holds a version, `inferred` or
+/// `advertised`, and the protocols supported from that version onwards.  The
+/// versions must be in ascending order, and those from
+/// `FIRST_TOR_VERSION_TO_ADVERTISE_PROTOCOLS` onwards (and only those) must be
+/// `advertised`.
+///
+/// # Errors
+///
+/// * `ProtoverError::Unparseable`, if a line doesn't have three fields, or its
+///   version is unparseable, or its second field is something other than
+///   `inferred` or `advertised`, or is the wrong one of them for its version.
+///   The offset is from the start of `table`.
+/// * `ProtoverError::Unordered`, if the versions aren't in ascending order.
+/// * Any error from parsing the protocols as a `ProtoEntry`.
+///
+/// # Examples
+///
+/// ```
+/// use protover::history::parse_protocol_history;
+///
+/// let history = parse_protocol_history("0.2.4.19 inferred Cons=1 Link=1-4\n\
+///                                       0.2.9.3-alpha advertised Cons=1-2 Link=1-4\n").unwrap();
+///
+/// assert_eq!(2, history.len());
+/// assert_eq!("Cons=1-2 Link=1-4", history[1].protocols_str());
+/// ```
+pub fn parse_protocol_history(table: &str) -> Result<Vec<ProtocolHistoryEntry>, ProtoverError> {
+    let first_advertised: TorVersion = FIRST_TOR_VERSION_TO_ADVERTISE_PROTOCOLS.parse()?;
+    let mut history: Vec<ProtocolHistoryEntry> = Vec::new();
+    let mut offset: usize = 0;
+
+    for line in table.split('\n') {
+        let start: usize = offset;
+        offset += line.len() + 1;
+
+        let trimmed: &str = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let unparseable = || ProtoverError::Unparseable { at: start, token: line.to_string() };
+        let fields: Vec<&str> = trimmed.split_whitespace().collect();
+        if fields.len() < 3 {
+            return Err(unparseable());
+        }
+
+        let since: TorVersion = fields[0].parse().map_err(|e: ProtoverError| e.offset_by(start))?;
+        let advertised: bool = match fields[1] {
+            "inferred" => false,
+            "advertised" => true,
+            _ => return Err(unparseable()),
+        };
+        if advertised != (since >= first_advertised) {
+            return Err(unparseable());
+        }
+        let protocols: String = fields[2..].join(" ");
+
+        if let Some(previous) = history.last() {
+            if previous.since >= since {
+                return Err(ProtoverError::Unordered);
+            }
+        }
+
+        history.push(ProtocolHistoryEntry {
+            since,
+            advertised,
+            protocols: protocols.parse()?,
+            protocols_with_nul: format!("{}\0", protocols),
+        });
+    }
+    Ok(history)
+}
+
+/// Guards the initialisation of `HISTORY`.
+static HISTORY_INIT: Once = Once::new();
+
+/// The parsed table of historical protocols.  Use `protocol_history()`
+/// rather than this.
+static mut HISTORY: *const Vec<ProtocolHistoryEntry> = ptr::null();
+
+/// Get the table of historical protocols, oldest first, parsing it the first
+/// time we're called.
+pub fn protocol_history() -> &'static [ProtocolHistoryEntry] {
+    // `HISTORY` is only written once, by the `call_once()` closure, and only
+    // read after `call_once()` has returned.
+    unsafe {
+        HISTORY_INIT.call_once(|| {
+            // The table is under our control, and is checked to be parseable
+            // by the tests.
+            let history: Vec<ProtocolHistoryEntry> =
+                parse_protocol_history(PROTOCOL_HISTORY).unwrap_or_default();
+
+            HISTORY = Box::into_raw(Box::new(history));
+        });
+        &*HISTORY
+    }
+}
+
+/// Get the protocols supported by the Tor `version`, whether they were
+/// inferred or advertised.
+///
+/// # Returns
+///
+/// The last entry in the table whose version is no newer than `version`, or
+/// `None` if `version` is older than everything in the table.
+///
+/// # Examples
+///
+/// ```
+/// use protover::history::protocols_for_version;
+///
+/// let entry = protocols_for_version(&"0.2.8.12".parse().unwrap()).unwrap();
+///
+/// assert!(!entry.advertised);
+/// assert!(entry.protocols_str().contains("HSIntro=3 "));
+/// assert_eq!(None, protocols_for_version(&"0.2.3.25".parse().unwrap()));
+/// ```
+pub fn protocols_for_version(version: &TorVersion) -> Option<&'static ProtocolHistoryEntry> {
+    protocol_history().iter().rev().find(|entry| entry.since <= *version)
+}
+
+/// Get the protocols supported by a relay with the given `platform` line, as
+/// in `protocols_for_version()`.  Platforms which aren't Tor, or whose
+/// versions are unparseable, support nothing we know of.
+///
+/// # Examples
+///
+/// ```
+/// use protover::history::protocols_for_platform;
+///
+/// let entry = protocols_for_platform("Tor 0.3.2.10 on Linux").unwrap();
+///
+/// assert!(entry.advertised);
+/// assert!(entry.protocols_str().contains("HSIntro=3-4 "));
+/// assert_eq!(None, protocols_for_platform("Arti 0.1.0 on Linux"));
+/// ```
+pub fn protocols_for_platform(platform: &str) -> Option<&'static ProtocolHistoryEntry> {
+    match TorVersion::from_platform(platform) {
+        Ok(Some(version)) => protocols_for_version(&version),
+        Ok(None) | Err(_) => None,
+    }
+}
+
+/// Find the oldest Tor version which supports all of `protocols`.
+///
+/// # Returns
+///
+/// `Ok(None)` if no version in the table supports them all, such as when
+/// they include an unknown protocol.  Note that a version newer than that
+/// returned isn't guaranteed to support them too.
+///
+/// # Errors
+///
+/// Any error from parsing `protocols` as an `UnvalidatedProtoEntry`.
+///
+/// # Examples
+///
+/// ```
+/// use protover::history::oldest_version_supporting;
+///
+/// let version = oldest_version_supporting("HSIntro=4").unwrap().unwrap();
+///
+/// assert_eq!("0.3.0.4-rc", version.to_string());
+/// assert_eq!(Ok(None), oldest_version_supporting("Wombat=1"));
+/// ```
+pub fn oldest_version_supporting(protocols: &str)
+    -> Result<Option<&'static TorVersion>, ProtoverError>
+{
+    let wanted: UnvalidatedProtoEntry = protocols.parse()?;
+
+    Ok(protocol_history().iter()
+        .find(|entry| wanted.unsupported_by(&entry.protocols).is_none())
+        .map(|entry| &entry.since))
+}
+
+/// Get the protocols which a relay with the given `platform` line implicitly
+/// supports, for `compute_for_old_tor_cstr()`: those from the table, if its
+/// version is one which can't advertise them itself, and otherwise none.
+///
+/// As with C's `tor_version_as_new_as()`, a platform which isn't Tor, or
+/// whose version is unparseable, is assumed to be new.
+pub(crate) fn inferred_protocols_with_nul(platform: &str) -> Option<&'static [u8]> {
+    protocol_history().iter().rev()
+        .find(|entry| platform_is_as_new_as(platform, &entry.since))
+        .and_then(|entry| if entry.advertised { None } else { Some(entry.protocols_with_nul()) })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use canonical::is_canonical;
+    use protover::get_compiled_in_protocols;
+
+    #[test]
+    fn test_protocol_history_is_parseable_and_canonical() {
+        let history: Vec<ProtocolHistoryEntry> = parse_protocol_history(PROTOCOL_HISTORY).unwrap();
+
+        assert_eq!(history.as_slice(), protocol_history());
+        for entry in history.iter() {
+            assert_eq!(Ok(true), is_canonical(entry.protocols_str()), "{}", entry.since);
+        }
+    }
+
+    #[test]
+    fn test_protocol_history_first_advertised() {
+        let first: &ProtocolHistoryEntry =
+            protocol_history().iter().find(|entry| entry.advertised).unwrap();
+
+        assert_eq!(FIRST_TOR_VERSION_TO_ADVERTISE_PROTOCOLS, first.since.to_string());
+        assert!(protocol_history().iter().skip_while(|entry| !entry.advertised)
+                .all(|entry| entry.advertised));
+    }
+
+    #[test]
+    fn test_protocol_history_ends_with_compiled_in() {
+        assert_eq!(get_compiled_in_protocols(),
+                   protocol_history().last().unwrap().protocols_str());
+    }
+
+    #[test]
+    fn test_oldest_version_supporting() {
+        let oldest = |protocols: &str| {
+            oldest_version_supporting(protocols).unwrap().map(|v| v.to_string())
+        };
+
+        assert_eq!(Some("0.2.4.19".to_string()), oldest("Link=1-4 Relay=2"));
+        assert_eq!(Some("0.2.7.5".to_string()), oldest("Desc=2"));
+        assert_eq!(Some("0.2.9.1-alpha".to_string()), oldest("HSRend=2"));
+        assert_eq!(Some("0.3.0.1-alpha".to_string()), oldest("LinkAuth=3"));
+        assert_eq!(Some("0.3.0.4-rc".to_string()), oldest("HSDir=2 HSIntro=4"));
+        assert_eq!(Some("0.3.1.1-alpha".to_string()), oldest("DirCache=2 HSIntro=4"));
+        assert_eq!(Some("0.3.3.2-alpha".to_string()), oldest("Link=5"));
+        assert_eq!(None, oldest("Link=6"));
+        assert!(oldest_version_supporting("Link=1-x").is_err());
+    }
+
+    #[test]
+    fn test_protocols_for_version_boundaries() {
+        let since = |version: &str| {
+            protocols_for_version(&version.parse().unwrap()).map(|e| e.since.to_string())
+        };
+
+        assert_eq!(None, since("0.2.4.18-rc"));
+        assert_eq!(Some("0.2.4.19".to_string()), since("0.2.4.19"));
+        assert_eq!(Some("0.2.4.19".to_string()), since("0.2.7.4-rc"));
+        assert_eq!(Some("0.2.7.5".to_string()), since("0.2.7.5"));
+        assert_eq!(Some("0.3.1.1-alpha".to_string()), since("0.3.3.1-alpha"));
+        assert_eq!(Some("0.3.3.2-alpha".to_string()), since("0.3.4.1-alpha"));
+    }
+
+    #[test]
+    fn test_parse_protocol_history_errors() {
+        assert_eq!(Err(ProtoverError::Unordered),
+                   parse_protocol_history("0.2.7.5 inferred Link=1\n0.2.4.19 inferred Link=1"));
+        assert_eq!(Err(ProtoverError::Unparseable { at: 10, token: "0.2.7.5 guessed Link=1".to_string() }),
+                   parse_protocol_history("# comment\n0.2.7.5 guessed Link=1"));
+        assert_eq!(Err(ProtoverError::Unparseable { at: 0, token: "0.2.7.5 advertised Link=1".to_string() }),
+                   parse_protocol_history("0.2.7.5 advertised Link=1"));
+        assert!(parse_protocol_history("0.3.0.1-alpha inferred Link=1").is_err());
+        assert!(parse_protocol_history("0.2.7.5 inferred").is_err());
+        assert!(parse_protocol_history("0.2.7.5 inferred Wombat=1").is_err());
+        assert_eq!(Ok(Vec::new()), parse_protocol_history("\n  # nothing here\n"));
+    }
+}
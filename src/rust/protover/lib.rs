@@ -26,13 +26,31 @@
 
 extern crate libc;
 extern crate smartlist;
-extern crate external;
 extern crate tor_allocate;
+#[macro_use]
+extern crate tor_log;
+#[cfg(test)]
+extern crate tor_log_testing;
+extern crate tor_options;
+#[macro_use]
 extern crate tor_util;
 
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+
 pub mod errors;
 pub mod protoset;
 mod protover;
+pub mod canonical;
+pub mod history;
+pub mod requirements;
+#[cfg(feature = "serde")]
+pub mod serialize;
+pub mod stats;
+pub mod transport;
+pub mod version;
 pub mod ffi;
 
 pub use protover::*;
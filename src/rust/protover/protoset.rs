@@ -4,6 +4,7 @@
 
 //! Sets for lazily storing ordered, non-overlapping ranges of integers.
 
+use std::cmp;
 use std::slice;
 use std::str::FromStr;
 use std::u32;
@@ -44,7 +45,7 @@ pub type Version = u32;
 /// # Ok(protoset)
 /// # }
 /// # fn main() { do_test(); }  // wrap the test so we can use the ? operator
-#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Clone, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
 pub struct ProtoSet {
     pub(crate) pairs: Vec<(Version, Version)>,
 }
@@ -63,6 +64,18 @@ impl<'a> ProtoSet {
     /// # Inputs
     ///
     /// We do not assume the input pairs are deduplicated or ordered.
+    ///
+    /// # Returns
+    ///
+    /// A `ProtoSet` whose pairs are sorted, and merged wherever one range
+    /// ends right before the next begins, so that `(1, 2), (3, 3)` becomes
+    /// `(1, 3)`.
+    ///
+    /// # Errors
+    ///
+    /// `ProtoverError::Overlap` if any two pairs share a version,
+    /// `ProtoverError::LowGreaterThanHigh` if a `low` is above its `high`,
+    /// and `ProtoverError::ExceedsMax` if either is `u32::MAX`.
     pub fn from_slice(low_high_pairs: &'a [(Version, Version)]) -> Result<Self, ProtoverError> {
         let mut pairs: Vec<(Version, Version)> = Vec::with_capacity(low_high_pairs.len());
 
@@ -73,7 +86,9 @@ impl<'a> ProtoSet {
         pairs.sort_unstable();
         pairs.dedup();
 
-        ProtoSet{ pairs }.is_ok()
+        let checked: ProtoSet = ProtoSet{ pairs }.is_ok()?;
+
+        Ok(ProtoSet{ pairs: coalesce(checked.pairs) })
     }
 }
 
@@ -159,7 +174,8 @@ impl ProtoSet {
     ///   well-formed, i.e. a `low` in a `(low, high)` was higher than the
     ///   previous `high`,
     /// * `ProtoverError::Overlap`: if one or more of the `pairs` are
-    ///   overlapping,
+    ///   overlapping, including ranges which merely share an endpoint, like
+    ///   `(1, 2), (2, 3)`,
     /// * `ProtoverError::ExceedsMax`: if the number of versions when expanded
     ///   would exceed `MAX_PROTOCOLS_TO_EXPAND`, and
     ///
@@ -168,18 +184,18 @@ impl ProtoSet {
     /// A `Result` whose `Ok` is this `Protoset`, and whose `Err` is one of the
     /// errors enumerated in the Errors section above.
     fn is_ok(self) -> Result<ProtoSet, ProtoverError> {
-        let mut last_high: Version = 0;
+        let mut last_high: Option<Version> = None;
 
         for &(low, high) in self.iter() {
             if low == u32::MAX || high == u32::MAX {
                 return Err(ProtoverError::ExceedsMax);
             }
-            if low < last_high {
+            if last_high.map_or(false, |last| low <= last) {
                 return Err(ProtoverError::Overlap);
             } else if low > high {
                 return Err(ProtoverError::LowGreaterThanHigh);
             }
-            last_high = high;
+            last_high = Some(high);
         }
 
         Ok(self)
@@ -269,6 +285,342 @@ impl ProtoSet {
         expanded.retain(f);
         *self = expanded.into();
     }
+
+    /// Get the union of this `ProtoSet` and `other`, that is, every `Version`
+    /// which is in either set.
+    ///
+    /// Like all of the set operations below, this works directly upon the
+    /// `(low, high)` pairs and never expands either set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use protover::errors::ProtoverError;
+    /// use protover::protoset::ProtoSet;
+    ///
+    /// # fn do_test() -> Result<bool, ProtoverError> {
+    /// let a: ProtoSet = "1-3,7".parse()?;
+    /// let b: ProtoSet = "4-5,7-4294967294".parse()?;
+    ///
+    /// assert_eq!(a.union(&b).to_string(), "1-5,7-4294967294");
+    /// #
+    /// # Ok(true)
+    /// # }
+    /// # fn main() { do_test(); }  // wrap the test so we can use the ? operator
+    /// ```
+    pub fn union(&self, other: &ProtoSet) -> ProtoSet {
+        let mut pairs: Vec<(Version, Version)> =
+            Vec::with_capacity(self.pairs.len() + other.pairs.len());
+
+        pairs.extend_from_slice(&self.pairs[..]);
+        pairs.extend_from_slice(&other.pairs[..]);
+        pairs.sort_unstable();
+
+        ProtoSet{ pairs: coalesce(pairs) }
+    }
+
+    /// Get the intersection of this `ProtoSet` and `other`, that is, every
+    /// `Version` which is in both sets.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use protover::errors::ProtoverError;
+    /// use protover::protoset::ProtoSet;
+    ///
+    /// # fn do_test() -> Result<bool, ProtoverError> {
+    /// let a: ProtoSet = "1-10,20-4294967294".parse()?;
+    /// let b: ProtoSet = "5-25".parse()?;
+    ///
+    /// assert_eq!(a.intersection(&b).to_string(), "5-10,20-25");
+    /// #
+    /// # Ok(true)
+    /// # }
+    /// # fn main() { do_test(); }  // wrap the test so we can use the ? operator
+    /// ```
+    pub fn intersection(&self, other: &ProtoSet) -> ProtoSet {
+        let mut pairs: Vec<(Version, Version)> = Vec::new();
+        let mut i: usize = 0;
+        let mut j: usize = 0;
+
+        while i < self.pairs.len() && j < other.pairs.len() {
+            let (a_low, a_high) = self.pairs[i];
+            let (b_low, b_high) = other.pairs[j];
+            let low:  Version = cmp::max(a_low, b_low);
+            let high: Version = cmp::min(a_high, b_high);
+
+            if low <= high {
+                pairs.push((low, high));
+            }
+            // Advance whichever range ends first, since it cannot overlap
+            // anything further along in the other set.
+            if a_high < b_high {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        ProtoSet{ pairs: coalesce(pairs) }
+    }
+
+    /// Get the difference of this `ProtoSet` and `other`, that is, every
+    /// `Version` which is in this set but not in `other`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use protover::errors::ProtoverError;
+    /// use protover::protoset::ProtoSet;
+    ///
+    /// # fn do_test() -> Result<bool, ProtoverError> {
+    /// let a: ProtoSet = "1-4294967294".parse()?;
+    /// let b: ProtoSet = "2,5-9".parse()?;
+    ///
+    /// assert_eq!(a.difference(&b).to_string(), "1,3-4,10-4294967294");
+    /// #
+    /// # Ok(true)
+    /// # }
+    /// # fn main() { do_test(); }  // wrap the test so we can use the ? operator
+    /// ```
+    pub fn difference(&self, other: &ProtoSet) -> ProtoSet {
+        let mut pairs: Vec<(Version, Version)> = Vec::new();
+        let mut j: usize = 0;
+
+        for &(low, high) in self.iter() {
+            // Work in u64 so that `high + 1` cannot overflow.
+            let mut start: u64 = low as u64;
+            let end: u64 = high as u64;
+
+            // Skip the ranges in `other` which end before this one begins.
+            while j < other.pairs.len() && (other.pairs[j].1 as u64) < start {
+                j += 1;
+            }
+
+            let mut k: usize = j;
+
+            while k < other.pairs.len() && (other.pairs[k].0 as u64) <= end && start <= end {
+                let (cut_low, cut_high) = other.pairs[k];
+
+                if cut_low as u64 > start {
+                    pairs.push((start as Version, cut_low - 1));
+                }
+                start = cmp::max(start, cut_high as u64 + 1);
+                k += 1;
+            }
+            if start <= end {
+                pairs.push((start as Version, high));
+            }
+        }
+        ProtoSet{ pairs: coalesce(pairs) }
+    }
+
+    /// Get the symmetric difference of this `ProtoSet` and `other`, that is,
+    /// every `Version` which is in exactly one of the two sets.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use protover::errors::ProtoverError;
+    /// use protover::protoset::ProtoSet;
+    ///
+    /// # fn do_test() -> Result<bool, ProtoverError> {
+    /// let a: ProtoSet = "1-5".parse()?;
+    /// let b: ProtoSet = "4-8".parse()?;
+    ///
+    /// assert_eq!(a.symmetric_difference(&b).to_string(), "1-3,6-8");
+    /// #
+    /// # Ok(true)
+    /// # }
+    /// # fn main() { do_test(); }  // wrap the test so we can use the ? operator
+    /// ```
+    pub fn symmetric_difference(&self, other: &ProtoSet) -> ProtoSet {
+        self.union(other).difference(&self.intersection(other))
+    }
+
+    /// Determine if every `Version` in this `ProtoSet` is also in `other`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use protover::errors::ProtoverError;
+    /// use protover::protoset::ProtoSet;
+    ///
+    /// # fn do_test() -> Result<bool, ProtoverError> {
+    /// let a: ProtoSet = "3-4".parse()?;
+    /// let b: ProtoSet = "1-2,3,4-4294967294".parse()?;
+    ///
+    /// assert!(a.is_subset(&b));
+    /// assert!(!b.is_subset(&a));
+    /// #
+    /// # Ok(true)
+    /// # }
+    /// # fn main() { do_test(); }  // wrap the test so we can use the ? operator
+    /// ```
+    pub fn is_subset(&self, other: &ProtoSet) -> bool {
+        self.difference(other).is_empty()
+    }
+
+    /// Determine if every `Version` in `other` is also in this `ProtoSet`.
+    pub fn is_superset(&self, other: &ProtoSet) -> bool {
+        other.is_subset(self)
+    }
+}
+
+/// Merge any overlapping or adjacent `(low, high)` pairs.
+///
+/// # Inputs
+///
+/// `pairs`, a vector of well-formed `(low, high)` pairs, sorted by `low`.
+///
+/// # Returns
+///
+/// The smallest vector of sorted, disjoint pairs covering the same versions,
+/// e.g. `[(1, 2), (3, 3), (5, 9), (6, 7)]` becomes `[(1, 3), (5, 9)]`.
+pub(crate) fn coalesce(pairs: Vec<(Version, Version)>) -> Vec<(Version, Version)> {
+    let mut merged: Vec<(Version, Version)> = Vec::with_capacity(pairs.len());
+
+    for (low, high) in pairs {
+        let last: usize = merged.len();
+
+        if last > 0 && low as u64 <= merged[last - 1].1 as u64 + 1 {
+            merged[last - 1].1 = cmp::max(merged[last - 1].1, high);
+        } else {
+            merged.push((low, high));
+        }
+    }
+    merged
+}
+
+/// How strictly protocol version strings should be parsed.
+///
+/// The `FromStr` implementations for `ProtoSet`, `ProtoEntry` and
+/// `UnvalidatedProtoEntry` are all `Lenient`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum ParseMode {
+    /// Only accept strings written exactly as tor would write them: no
+    /// whitespace around versions, no empty pieces (as in `"1,,3"`), no
+    /// leading zeros or `+` signs, no more than one `-` per range, versions
+    /// in ascending order, and protocol names in ascending order without any
+    /// duplicates.
+    Strict,
+    /// Accept everything we have historically accepted.  If a protocol name
+    /// is listed twice, its last entry wins.
+    Lenient,
+}
+
+impl Default for ParseMode {
+    fn default() -> ParseMode {
+        ParseMode::Lenient
+    }
+}
+
+/// Determine if `s` is a version number written without a sign or any
+/// leading zeros.
+fn is_strict_version(s: &str) -> bool {
+    !s.is_empty() &&
+        s.bytes().all(|b| b.is_ascii_digit()) &&
+        (s == "0" || !s.starts_with('0'))
+}
+
+impl ProtoSet {
+    /// Parse the unique version numbers supported by a subprotocol from a
+    /// string, according to the given `ParseMode`.
+    ///
+    /// See `impl FromStr for ProtoSet` for details of the `Lenient` mode.
+    ///
+    /// # Errors
+    ///
+    /// In `ParseMode::Strict`, this additionally returns a
+    /// `ProtoverError::Unparseable` for any piece which isn't written
+    /// canonically, and a `ProtoverError::Unordered` if the ranges aren't in
+    /// ascending order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use protover::errors::ProtoverError;
+    /// use protover::protoset::ParseMode;
+    /// use protover::protoset::ProtoSet;
+    ///
+    /// assert!(ProtoSet::parse_with("1,3-5", ParseMode::Strict).is_ok());
+    /// assert!(ProtoSet::parse_with("1,,3", ParseMode::Lenient).is_ok());
+    ///
+    /// assert_eq!(Err(ProtoverError::Unparseable { at: 2, token: "".to_string() }),
+    ///            ProtoSet::parse_with("1,,3", ParseMode::Strict));
+    /// assert_eq!(Err(ProtoverError::Unparseable { at: 0, token: "01".to_string() }),
+    ///            ProtoSet::parse_with("01", ParseMode::Strict));
+    /// assert_eq!(Err(ProtoverError::Unordered),
+    ///            ProtoSet::parse_with("3,1", ParseMode::Strict));
+    /// ```
+    pub fn parse_with(version_string: &str, mode: ParseMode) -> Result<Self, ProtoverError> {
+        let strict: bool = mode == ParseMode::Strict;
+        let mut pairs: Vec<(Version, Version)> = Vec::new();
+        let mut offset: usize = 0;
+
+        // An entirely empty version string is simply an empty set, in either
+        // mode, e.g. for "Fribble=".
+        if strict && version_string.is_empty() {
+            return Ok(ProtoSet::default());
+        }
+
+        for piece in version_string.split(',') {
+            let start: usize = offset;
+            let p: &str = piece.trim();
+            // The byte offset of the trimmed piece, for error reporting.
+            let at: usize = start + piece.find(p).unwrap_or(0);
+            let unparseable = || ProtoverError::Unparseable { at, token: p.to_string() };
+
+            offset += piece.len() + 1;
+
+            if strict && (p.len() != piece.len() || p.is_empty()) {
+                return Err(ProtoverError::Unparseable { at: start, token: piece.to_string() });
+            }
+
+            if p.is_empty() {
+                continue;
+            } else if p.contains('-') {
+                let mut pair = p.split('-');
+
+                let low  = pair.next().ok_or_else(&unparseable)?;
+                let high = pair.next().ok_or_else(&unparseable)?;
+
+                if strict && (pair.next().is_some() ||
+                              !is_strict_version(low) || !is_strict_version(high)) {
+                    return Err(unparseable());
+                }
+
+                let lo: Version =  low.parse().or_else(|_| Err(unparseable()))?;
+                let hi: Version = high.parse().or_else(|_| Err(unparseable()))?;
+
+                if lo == u32::MAX || hi == u32::MAX {
+                    return Err(ProtoverError::ExceedsMax);
+                }
+                pairs.push((lo, hi));
+            } else {
+                if strict && !is_strict_version(p) {
+                    return Err(unparseable());
+                }
+
+                let v: u32 = p.parse().or_else(|_| Err(unparseable()))?;
+
+                if v == u32::MAX {
+                    return Err(ProtoverError::ExceedsMax);
+                }
+                pairs.push((v, v));
+            }
+        }
+        // If we were passed in an empty string, or a bunch of whitespace, or
+        // simply a comma, or a pile of commas, then return an empty ProtoSet.
+        if pairs.len() == 0 {
+            return Ok(ProtoSet::default());
+        }
+        // Overlapping ranges are caught by from_slice(), so only check that
+        // the ranges were given in ascending order.
+        if strict && pairs.windows(2).any(|w| w[1].0 < w[0].0) {
+            return Err(ProtoverError::Unordered);
+        }
+        ProtoSet::from_slice(&pairs[..])
+    }
 }
 
 impl FromStr for ProtoSet {
@@ -322,12 +674,17 @@ impl FromStr for ProtoSet {
     /// let protoset: ProtoSet = "1-4294967296".parse()?;
     ///
     /// // There are lots of ways to get an `Err` from this function.  Here are
-    /// // a few:
-    /// assert_eq!(Err(ProtoverError::Unparseable), ProtoSet::from_str("="));
-    /// assert_eq!(Err(ProtoverError::Unparseable), ProtoSet::from_str("-"));
-    /// assert_eq!(Err(ProtoverError::Unparseable), ProtoSet::from_str("not_an_int"));
-    /// assert_eq!(Err(ProtoverError::Unparseable), ProtoSet::from_str("3-"));
-    /// assert_eq!(Err(ProtoverError::Unparseable), ProtoSet::from_str("1-,4"));
+    /// // a few, each of which says where parsing went wrong:
+    /// assert_eq!(Err(ProtoverError::Unparseable { at: 0, token: "=".to_string() }),
+    ///            ProtoSet::from_str("="));
+    /// assert_eq!(Err(ProtoverError::Unparseable { at: 0, token: "-".to_string() }),
+    ///            ProtoSet::from_str("-"));
+    /// assert_eq!(Err(ProtoverError::Unparseable { at: 0, token: "not_an_int".to_string() }),
+    ///            ProtoSet::from_str("not_an_int"));
+    /// assert_eq!(Err(ProtoverError::Unparseable { at: 0, token: "3-".to_string() }),
+    ///            ProtoSet::from_str("3-"));
+    /// assert_eq!(Err(ProtoverError::Unparseable { at: 2, token: "1-".to_string() }),
+    ///            ProtoSet::from_str("5,1-,4"));
     ///
     /// // Things which would get parsed into an _empty_ `ProtoSet` are,
     /// // however, legal, and result in an empty `ProtoSet`:
@@ -339,42 +696,7 @@ impl FromStr for ProtoSet {
     /// # fn main() { do_test(); }  // wrap the test so we can use the ? operator
     /// ```
     fn from_str(version_string: &str) -> Result<Self, Self::Err> {
-        let mut pairs: Vec<(Version, Version)> = Vec::new();
-        let pieces: ::std::str::Split<char> = version_string.trim().split(',');
-
-        for piece in pieces {
-            let p: &str = piece.trim();
-
-            if p.is_empty() {
-                continue;
-            } else if p.contains('-') {
-                let mut pair = p.split('-');
-
-                let low  = pair.next().ok_or(ProtoverError::Unparseable)?;
-                let high = pair.next().ok_or(ProtoverError::Unparseable)?;
-
-                let lo: Version =  low.parse().or(Err(ProtoverError::Unparseable))?;
-                let hi: Version = high.parse().or(Err(ProtoverError::Unparseable))?;
-
-                if lo == u32::MAX || hi == u32::MAX {
-                    return Err(ProtoverError::ExceedsMax);
-                }
-                pairs.push((lo, hi));
-            } else {
-                let v: u32 = p.parse().or(Err(ProtoverError::Unparseable))?;
-
-                if v == u32::MAX {
-                    return Err(ProtoverError::ExceedsMax);
-                }
-                pairs.push((v, v));
-            }
-        }
-        // If we were passed in an empty string, or a bunch of whitespace, or
-        // simply a comma, or a pile of commas, then return an empty ProtoSet.
-        if pairs.len() == 0 {
-            return Ok(ProtoSet::default());
-        }
-        ProtoSet::from_slice(&pairs[..])
+        ProtoSet::parse_with(version_string, ParseMode::Lenient)
     }
 }
 
@@ -530,22 +852,64 @@ mod test {
 
     #[test]
     fn test_versions_from_str_ab() {
-        assert_eq!(Err(ProtoverError::Unparseable), ProtoSet::from_str("a,b"));
+        assert_eq!(Err(ProtoverError::Unparseable { at: 0, token: "a".to_string() }),
+                   ProtoSet::from_str("a,b"));
     }
 
     #[test]
     fn test_versions_from_str_negative_1() {
-        assert_eq!(Err(ProtoverError::Unparseable), ProtoSet::from_str("-1"));
+        assert_eq!(Err(ProtoverError::Unparseable { at: 0, token: "-1".to_string() }),
+                   ProtoSet::from_str("-1"));
     }
 
     #[test]
     fn test_versions_from_str_1exclam() {
-        assert_eq!(Err(ProtoverError::Unparseable), ProtoSet::from_str("1,!"));
+        assert_eq!(Err(ProtoverError::Unparseable { at: 2, token: "!".to_string() }),
+                   ProtoSet::from_str("1,!"));
     }
 
     #[test]
     fn test_versions_from_str_percent_equal() {
-        assert_eq!(Err(ProtoverError::Unparseable), ProtoSet::from_str("%="));
+        assert_eq!(Err(ProtoverError::Unparseable { at: 0, token: "%=".to_string() }),
+                   ProtoSet::from_str("%="));
+    }
+
+    #[test]
+    fn test_versions_from_str_unparseable_offset_skips_whitespace() {
+        assert_eq!(Err(ProtoverError::Unparseable { at: 9, token: "1-x".to_string() }),
+                   ProtoSet::from_str("1-3, 5,  1-x"));
+    }
+
+    #[test]
+    fn test_versions_parse_strict_accepts_canonical() {
+        for s in ["", "0", "1", "1,3", "0-10,12", "1-4294967294"].iter() {
+            assert!(ProtoSet::parse_with(s, ParseMode::Strict).is_ok(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn test_versions_parse_strict_rejects_malformed() {
+        for s in [" 1", "1 ", "1,,3", "1,", ",1", ",", "01", "1-02", "+1", "1-+3",
+                  "1-2-3", "1 -3"].iter() {
+            match ProtoSet::parse_with(s, ParseMode::Strict) {
+                Err(ProtoverError::Unparseable { .. }) => (),
+                other => panic!("{:?} should be unparseable, got {:?}", s, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_versions_parse_strict_rejects_unordered() {
+        assert_eq!(Err(ProtoverError::Unordered), ProtoSet::parse_with("5-7,1", ParseMode::Strict));
+        assert_eq!(Err(ProtoverError::Overlap), ProtoSet::parse_with("1-3,2-4", ParseMode::Strict));
+    }
+
+    #[test]
+    fn test_versions_parse_lenient_matches_from_str() {
+        for s in [" 1", "1,,3", ",", "01", "+1", "1-2-3", "5-7,1"].iter() {
+            assert_eq!(ProtoSet::from_str(s), ProtoSet::parse_with(s, ParseMode::Lenient));
+            assert!(ProtoSet::parse_with(s, ParseMode::Lenient).is_ok(), "{:?}", s);
+        }
     }
 
     #[test]
@@ -558,6 +922,20 @@ mod test {
         assert_eq!(Err(ProtoverError::Overlap), ProtoSet::from_slice(&[(1, 3), (2, 4)]));
     }
 
+    #[test]
+    fn test_versions_from_slice_touching() {
+        assert_eq!(Err(ProtoverError::Overlap), ProtoSet::from_slice(&[(1, 2), (2, 3)]));
+        assert_eq!(Err(ProtoverError::Overlap), ProtoSet::from_str("0,0-5"));
+    }
+
+    #[test]
+    fn test_versions_from_slice_merges_adjacent() {
+        let protoset: ProtoSet = ProtoSet::from_slice(&[(3, 3), (1, 2), (5, 6), (7, 7)]).unwrap();
+
+        assert_eq!(vec![(1, 3), (5, 7)], protoset.iter().cloned().collect::<Vec<_>>());
+        assert_eq!(ProtoSet::from_str("1-3"), ProtoSet::from_str("1-2,3"));
+    }
+
     #[test]
     fn test_versions_from_str_max() {
         assert_eq!(Err(ProtoverError::ExceedsMax), ProtoSet::from_str("4294967295"));
@@ -626,9 +1004,188 @@ mod test {
         assert!(v.contains(&9001));
         assert!(v.contains(&4294967294));
     }
+
+    #[test]
+    fn test_coalesce() {
+        assert_eq!(coalesce(vec![]), vec![]);
+        assert_eq!(coalesce(vec![(1, 2), (3, 3)]), vec![(1, 3)]);
+        assert_eq!(coalesce(vec![(1, 2), (2, 5), (4, 4), (7, 9)]), vec![(1, 5), (7, 9)]);
+        assert_eq!(coalesce(vec![(0, 4294967294), (5, 6)]), vec![(0, 4294967294)]);
+    }
+
+    macro_rules! assert_set_op {
+        ($op:ident, $a:expr, $b:expr, $expected:expr) => (
+            let a: ProtoSet = $a.parse().unwrap();
+            let b: ProtoSet = $b.parse().unwrap();
+
+            assert_eq!(a.$op(&b).to_string(), $expected,
+                       "{}.{}({})", $a, stringify!($op), $b);
+        )
+    }
+
+    #[test]
+    fn test_protoset_union() {
+        assert_set_op!(union, "", "", "");
+        assert_set_op!(union, "1-3", "", "1-3");
+        assert_set_op!(union, "1-3", "4", "1-4");
+        assert_set_op!(union, "1-2,3", "9", "1-3,9");
+        assert_set_op!(union, "5-9", "1-4294967294", "1-4294967294");
+        assert_set_op!(union, "1,3,5", "2,4", "1-5");
+    }
+
+    #[test]
+    fn test_protoset_intersection() {
+        assert_set_op!(intersection, "", "1-3", "");
+        assert_set_op!(intersection, "1-3", "4-6", "");
+        assert_set_op!(intersection, "1-10", "3,5-6,12", "3,5-6");
+        assert_set_op!(intersection, "1-2,3-4", "2-3", "2-3");
+        assert_set_op!(intersection, "1-4294967294", "7-4294967294", "7-4294967294");
+    }
+
+    #[test]
+    fn test_protoset_difference() {
+        assert_set_op!(difference, "", "1-3", "");
+        assert_set_op!(difference, "1-3", "", "1-3");
+        assert_set_op!(difference, "1-3", "1-3", "");
+        assert_set_op!(difference, "1-10", "3,5-6,12", "1-2,4,7-10");
+        assert_set_op!(difference, "1-5,7-9", "4-8", "1-3,9");
+        assert_set_op!(difference, "0-4294967294", "1-4294967293", "0,4294967294");
+        assert_set_op!(difference, "1-3,999", "1-5", "999");
+    }
+
+    #[test]
+    fn test_protoset_symmetric_difference() {
+        assert_set_op!(symmetric_difference, "1-5", "4-8", "1-3,6-8");
+        assert_set_op!(symmetric_difference, "1-5", "1-5", "");
+        assert_set_op!(symmetric_difference, "1", "3", "1,3");
+        assert_set_op!(symmetric_difference, "1-4294967294", "2-4294967294", "1");
+    }
+
+    #[test]
+    fn test_protoset_subset_superset() {
+        let all: ProtoSet = "0-4294967294".parse().unwrap();
+        let some: ProtoSet = "3-5,9".parse().unwrap();
+        let empty: ProtoSet = ProtoSet::default();
+
+        assert!(some.is_subset(&all));
+        assert!(all.is_superset(&some));
+        assert!(!all.is_subset(&some));
+        assert!(empty.is_subset(&some));
+        assert!(some.is_subset(&some));
+        assert!(!some.is_subset(&"3-5".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_protoset_set_ops_agree_with_expansion() {
+        let sets: Vec<ProtoSet> = ["", "1", "1-3", "2-4,6", "1,3,5,7", "0-9", "4-5,8-9"]
+            .iter().map(|s| s.parse().unwrap()).collect();
+
+        for a in sets.iter() {
+            for b in sets.iter() {
+                let union: Vec<Version> = (0..12).filter(|v| a.contains(v) || b.contains(v)).collect();
+                let inter: Vec<Version> = (0..12).filter(|v| a.contains(v) && b.contains(v)).collect();
+                let diff:  Vec<Version> = (0..12).filter(|v| a.contains(v) && !b.contains(v)).collect();
+                let sym:   Vec<Version> = (0..12).filter(|v| a.contains(v) != b.contains(v)).collect();
+
+                assert_eq!(a.union(b).expand(), union);
+                assert_eq!(a.intersection(b).expand(), inter);
+                assert_eq!(a.difference(b).expand(), diff);
+                assert_eq!(a.symmetric_difference(b).expand(), sym);
+                assert_eq!(a.is_subset(b), diff.is_empty());
+            }
+        }
+    }
 }
 
+// These time themselves, as criterion does, rather than using the unstable
+// `test` crate, so that they build with a stable compiler even when every
+// feature is enabled.
 #[cfg(all(test, feature = "bench"))]
 mod bench {
-    use super::*;
+    use std::mem;
+    use std::ptr;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    use protover::*;
+
+    /// Roughly the number of relays in a consensus.
+    const RELAYS: usize = 7000;
+
+    /// How many times to time each benchmark, keeping the fastest.
+    const SAMPLES: usize = 5;
+
+    /// Return `x`, in a way which the optimiser can't see through, so that
+    /// it can't skip computing `x`.
+    fn black_box<T>(x: T) -> T {
+        unsafe {
+            let y: T = ptr::read_volatile(&x);
+
+            mem::forget(x);
+            y
+        }
+    }
+
+    /// Time `f` `SAMPLES` times, after running it once to warm up, and
+    /// report the fastest time.
+    fn bench<F: FnMut()>(name: &str, mut f: F) {
+        f();
+
+        let fastest: Duration = (0..SAMPLES).map(|_| {
+            let start: Instant = Instant::now();
+
+            f();
+            start.elapsed()
+        }).min().unwrap();
+
+        println!("bench {}: {} us for {} relays", name,
+                 fastest.as_secs() * 1_000_000 + fastest.subsec_nanos() as u64 / 1000,
+                 RELAYS);
+    }
+
+    /// Make up the protocol lists of `RELAYS` relays, running a handful of
+    /// different tor versions.
+    fn relay_protocols() -> Vec<UnvalidatedProtoEntry> {
+        let lists: &[&str] = &[
+            "Cons=1-2 Desc=1-2 DirCache=1-2 HSDir=1-2 HSIntro=3-4 HSRend=1-2 \
+             Link=1-5 LinkAuth=1,3 Microdesc=1-2 Relay=1-2",
+            "Cons=1-2 Desc=1-2 DirCache=1 HSDir=1 HSIntro=3 HSRend=1-2 \
+             Link=1-4 LinkAuth=1 Microdesc=1-2 Relay=1-2",
+            "Cons=1 Desc=1 DirCache=1 HSDir=1 HSIntro=3 HSRend=1 \
+             Link=1-4 LinkAuth=1 Microdesc=1 Relay=1-2",
+            "ChanType=4 Cons=1-2 Desc=1-2 DirCache=1-2 HSDir=1-2 HSIntro=3-4 \
+             HSRend=1-2 Link=1-5 LinkAuth=1,3 Microdesc=1-2 Quic=1 Relay=1-2 Wombat=9",
+        ];
+
+        (0..RELAYS).map(|i| lists[i % lists.len()].parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn bench_is_supported_here() {
+        // What checking whether we support some protocol version cost when
+        // the supported protocols were reparsed every time.
+        bench("is_supported_here, reparsed", || {
+            for _ in 0..RELAYS {
+                let supported: ProtoEntry = get_supported_protocols().parse().unwrap();
+
+                black_box(supported.get(&Protocol::Link).map(|v| v.contains(&5)));
+            }
+        });
+        bench("is_supported_here, memoized", || {
+            for _ in 0..RELAYS {
+                black_box(is_supported_here(&Protocol::Link, &5));
+            }
+        });
+    }
+
+    #[test]
+    fn bench_all_supported() {
+        let relays: Vec<UnvalidatedProtoEntry> = relay_protocols();
+
+        bench("all_supported", || {
+            for relay in relays.iter() {
+                black_box(relay.all_supported());
+            }
+        });
+    }
 }
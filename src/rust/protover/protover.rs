@@ -1,107 +1,327 @@
 // Copyright (c) 2016-2017, The Tor Project, Inc. */
 // See LICENSE for licensing information */
 
-use std::collections::HashMap;
-use std::collections::hash_map;
+use std::cmp;
+use std::collections::BTreeMap;
+use std::collections::btree_map;
 use std::fmt;
+use std::ptr;
 use std::str;
 use std::str::FromStr;
 use std::string::String;
+use std::sync::Arc;
+use std::sync::Once;
+use std::sync::PoisonError;
+use std::sync::RwLock;
 
 use tor_util::strings::NUL_BYTE;
-use external::c_tor_version_as_new_as;
 
 use errors::ProtoverError;
 use protoset::Version;
 use protoset::ProtoSet;
+use protoset::coalesce;
+use protoset::ParseMode;
+use history::inferred_protocols_with_nul;
 
 /// The first version of Tor that included "proto" entries in its descriptors.
 /// Authorities should use this to decide whether to guess proto lines.
 ///
 /// C_RUST_COUPLED:
 ///     src/or/protover.h `FIRST_TOR_VERSION_TO_ADVERTISE_PROTOCOLS`
-const FIRST_TOR_VERSION_TO_ADVERTISE_PROTOCOLS: &'static str = "0.2.9.3-alpha";
+pub(crate) const FIRST_TOR_VERSION_TO_ADVERTISE_PROTOCOLS: &'static str = "0.2.9.3-alpha";
 
 /// The maximum number of subprotocol version numbers we will attempt to expand
 /// before concluding that someone is trying to DoS us
 ///
 /// C_RUST_COUPLED: src/or/protover.c `MAX_PROTOCOLS_TO_EXPAND`
-const MAX_PROTOCOLS_TO_EXPAND: usize = (1<<16);
+pub(crate) const MAX_PROTOCOLS_TO_EXPAND: usize = (1<<16);
+
+/// Everything we know about one of the subprotocols in `PROTOCOLS`.
+#[derive(Clone, Debug)]
+pub struct ProtocolInfo {
+    /// The `Protocol` this entry describes.
+    pub protocol: Protocol,
+    /// The name of the protocol, as it appears in protocol lists.
+    pub name: &'static str,
+    /// The value of the corresponding C `protocol_type_t`.
+    pub c_id: u32,
+    /// The versions of the protocol which we support, or `""` if we don't
+    /// support any.
+    pub supported: &'static str,
+    /// A short, human-readable description of the protocol.
+    pub description: &'static str,
+}
+
+/// Produce the `" Name=versions"` fragment of the supported protocols string
+/// for a single protocol, or nothing if we support no versions of it.
+macro_rules! supported_fragment {
+    ($name:tt, "") => ("");
+    ($name:tt, $versions:tt) => (concat!(" ", $name, "=", $versions));
+}
 
-/// Currently supported protocols and their versions, as a byte-slice.
+/// Define the known subprotocols.
 ///
-/// # Warning
+/// Each entry is of the form
+///
+/// ```text
+/// Variant = c_id, "Name", "supported versions", "description";
+/// ```
 ///
-/// This byte-slice ends in a NUL byte.  This is so that we can directly convert
-/// it to an `&'static CStr` in the FFI code, in order to hand the static string
-/// to C in a way that is compatible with C static strings.
-///
-/// Rust code which wishes to accesses this string should use
-/// `protover::get_supported_protocols()` instead.
-///
-/// C_RUST_COUPLED: src/or/protover.c `protover_get_supported_protocols`
-pub(crate) const SUPPORTED_PROTOCOLS: &'static [u8] =
-    b"Cons=1-2 \
-    Desc=1-2 \
-    DirCache=1-2 \
-    HSDir=1-2 \
-    HSIntro=3-4 \
-    HSRend=1-2 \
-    Link=1-5 \
-    LinkAuth=1,3 \
-    Microdesc=1-2 \
-    Relay=1-2\0";
-
-/// Known subprotocols in Tor. Indicates which subprotocol a relay supports.
-///
-/// C_RUST_COUPLED: src/or/protover.h `protocol_type_t`
-#[derive(Clone, Hash, Eq, PartialEq, Debug)]
-pub enum Protocol {
-    Cons,
-    Desc,
-    DirCache,
-    HSDir,
-    HSIntro,
-    HSRend,
-    Link,
-    LinkAuth,
-    Microdesc,
-    Relay,
+/// and from these we derive the `Protocol` enum, its conversions to and from
+/// strings, the `PROTOCOLS` table (which the FFI code uses to translate C
+/// `protocol_type_t`s), and the supported protocols string.  Entries must be
+/// listed in ascending order by name, so that the supported protocols string
+/// is in canonical form, and so that the derived `Ord` for `Protocol` orders
+/// protocols by name, as `UnknownProtocol`'s does.
+macro_rules! protocols {
+    ($( $variant:ident = $c_id:expr, $name:tt, $versions:tt, $desc:tt; )+) => (
+        /// Known subprotocols in Tor. Indicates which subprotocol a relay
+        /// supports.
+        ///
+        /// C_RUST_COUPLED: src/or/protover.h `protocol_type_t`
+        #[derive(Clone, Hash, Eq, PartialEq, Ord, PartialOrd, Debug)]
+        pub enum Protocol {
+            $( #[doc = $desc] $variant, )+
+        }
+
+        impl Protocol {
+            /// Get the name of this protocol, as it appears in protocol lists.
+            pub fn name(&self) -> &'static str {
+                match *self {
+                    $( Protocol::$variant => $name, )+
+                }
+            }
+        }
+
+        /// Translates a string representation of a protocol into a Proto type.
+        /// Error if the string is an unrecognized protocol name.
+        ///
+        /// C_RUST_COUPLED: src/or/protover.c `PROTOCOL_NAMES`
+        impl FromStr for Protocol {
+            type Err = ProtoverError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s {
+                    $( $name => Ok(Protocol::$variant), )+
+                    _ => Err(ProtoverError::UnknownProtocol),
+                }
+            }
+        }
+
+        /// The registry of all known subprotocols, in ascending order by name.
+        pub const PROTOCOLS: &'static [ProtocolInfo] = &[
+            $( ProtocolInfo {
+                protocol: Protocol::$variant,
+                name: $name,
+                c_id: $c_id,
+                supported: $versions,
+                description: $desc,
+            }, )+
+        ];
+
+        /// The compiled-in supported protocols and their versions, with a
+        /// leading space and a trailing NUL byte.  Use
+        /// `get_compiled_in_protocols()` rather than this.
+        const SUPPORTED_PROTOCOLS_RAW: &'static str =
+            concat!($( supported_fragment!($name, $versions), )+ "\0");
+    )
 }
 
-impl fmt::Display for Protocol {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
+// C_RUST_COUPLED: src/or/protover.h `protocol_type_t`
+// C_RUST_COUPLED: src/or/protover.c `PROTOCOL_NAMES`
+// C_RUST_COUPLED: src/or/protover.c `protover_get_supported_protocols`
+//
+// We don't statically support any versions of `ChanType` or `Quic`, since
+// which of them we speak depends upon our configuration; see `transport`.
+protocols! {
+    ChanType = 14, "ChanType", "", "Channel types, as in the C `channel_type_t`.";
+    Cons = 9, "Cons", "1-2", "Consensus document types.";
+    Desc = 7, "Desc", "1-2", "Router descriptor types.";
+    DirCache = 3, "DirCache", "1-2", "Directory cache functionality.";
+    FlowCtrl = 11, "FlowCtrl", "", "Circuit-level flow control.";
+    HSDir = 4, "HSDir", "1-2", "Onion service directory functionality.";
+    HSIntro = 5, "HSIntro", "3-4", "Onion service introduction point functionality.";
+    HSRend = 6, "HSRend", "1-2", "Onion service rendezvous point functionality.";
+    Imux = 13, "Imux", "", "Channels inverse-multiplexed over several connections.";
+    Link = 0, "Link", "1-5", "Link protocol versions (cell formats and handshakes).";
+    LinkAuth = 1, "LinkAuth", "1,3", "Link authentication handshakes.";
+    Microdesc = 8, "Microdesc", "1-2", "Microdescriptor types.";
+    Padding = 10, "Padding", "", "Padding negotiation.";
+    Quic = 12, "Quic", "", "Channels carried over QUIC connections.";
+    Relay = 2, "Relay", "1-2", "Relay cell types and circuit extension handshakes.";
+}
+
+/// The protocols and versions which we were compiled to support, as a
+/// byte-slice ending in a NUL byte.
+fn compiled_in_protocols_with_nul() -> &'static [u8] {
+    if SUPPORTED_PROTOCOLS_RAW.starts_with(' ') {
+        SUPPORTED_PROTOCOLS_RAW[1..].as_bytes()
+    } else {
+        SUPPORTED_PROTOCOLS_RAW.as_bytes()
     }
 }
 
-/// Translates a string representation of a protocol into a Proto type.
-/// Error if the string is an unrecognized protocol name.
+/// The protocols and versions which we were compiled to support, regardless
+/// of any override set with `set_supported_protocols_override()`.
+pub fn get_compiled_in_protocols() -> &'static str {
+    let compiled_in: &'static [u8] = compiled_in_protocols_with_nul();
+
+    // The `unwrap` is safe because the registry is under our control.
+    str::from_utf8(&compiled_in[..compiled_in.len() - 1]).unwrap_or("")
+}
+
+/// The protocols and versions which we support, kept both as a string and
+/// parsed, so that neither has to be recomputed whenever it's needed.
+#[derive(Debug)]
+pub(crate) struct SupportedProtocols {
+    /// The protocols, in canonical form, followed by a NUL byte.
+    protocols_with_nul: String,
+    /// The protocols, parsed.
+    pub(crate) entry: ProtoEntry,
+}
+
+impl SupportedProtocols {
+    /// The protocols we were compiled to support.
+    fn compiled_in() -> SupportedProtocols {
+        let mut protocols_with_nul: String = get_compiled_in_protocols().to_string();
+        // The compiled-in protocols are under our control, and are checked
+        // to be parseable by the tests.
+        let entry: ProtoEntry = protocols_with_nul.parse().unwrap_or_default();
+
+        protocols_with_nul.push('\0');
+        SupportedProtocols { protocols_with_nul, entry }
+    }
+
+    /// The protocols, as a string.
+    pub(crate) fn protocols(&self) -> &str {
+        &self.protocols_with_nul[..self.protocols_with_nul.len() - 1]
+    }
+
+    /// The protocols, as a byte-slice ending in a NUL byte, so that they can
+    /// be handed to C.
+    pub(crate) fn protocols_with_nul(&self) -> &[u8] {
+        self.protocols_with_nul.as_bytes()
+    }
+}
+
+/// Guards the initialisation of `SUPPORTED`.
+static SUPPORTED_INIT: Once = Once::new();
+
+/// The protocols we currently support: the compiled-in ones, unless they
+/// have been overridden with `set_supported_protocols_override()`.  Use
+/// `supported_protocols()` rather than this.
 ///
-/// C_RUST_COUPLED: src/or/protover.c `PROTOCOL_NAMES`
-impl FromStr for Protocol {
-    type Err = ProtoverError;
+/// Replacing the protocols only drops this reference to the old ones, so
+/// anyone still using them keeps them alive.
+static mut SUPPORTED: *const RwLock<Arc<SupportedProtocols>> = ptr::null();
+
+/// Get the lock around the protocols we currently support, making it the
+/// first time we're called.
+fn supported_lock() -> &'static RwLock<Arc<SupportedProtocols>> {
+    // `SUPPORTED` is only written once, by the `call_once()` closure, and
+    // only read after `call_once()` has returned.
+    unsafe {
+        SUPPORTED_INIT.call_once(|| {
+            let supported: Arc<SupportedProtocols> = Arc::new(SupportedProtocols::compiled_in());
+
+            SUPPORTED = Box::into_raw(Box::new(RwLock::new(supported)));
+        });
+        &*SUPPORTED
+    }
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "Cons" => Ok(Protocol::Cons),
-            "Desc" => Ok(Protocol::Desc),
-            "DirCache" => Ok(Protocol::DirCache),
-            "HSDir" => Ok(Protocol::HSDir),
-            "HSIntro" => Ok(Protocol::HSIntro),
-            "HSRend" => Ok(Protocol::HSRend),
-            "Link" => Ok(Protocol::Link),
-            "LinkAuth" => Ok(Protocol::LinkAuth),
-            "Microdesc" => Ok(Protocol::Microdesc),
-            "Relay" => Ok(Protocol::Relay),
-            _ => Err(ProtoverError::UnknownProtocol),
+/// Get the protocols we currently support.  Unlike `ProtoEntry::supported()`
+/// and `get_supported_protocols()`, this doesn't reparse or copy them.
+pub(crate) fn supported_protocols() -> Arc<SupportedProtocols> {
+    // Nothing which holds the lock can panic, but if it did, the protocols
+    // would still be whole.
+    supported_lock().read().unwrap_or_else(PoisonError::into_inner).clone()
+}
+
+/// Check that `protocols` is acceptable as an override for our supported
+/// protocols: that it parses, and that it lists only protocols and versions
+/// which we were compiled to support.
+///
+/// # Returns
+///
+/// A `Result` whose `Ok` value is `protocols` in canonical form, and whose
+/// `Err` value is a `ProtoverError`.  A protocol version which we weren't
+/// compiled to support is reported as a `ProtoverError::NotSupportedHere`
+/// within that protocol.
+///
+/// # Examples
+///
+/// ```
+/// use protover::check_supported_protocols_override;
+///
+/// assert_eq!(Ok("HSIntro=3 Link=1-4".to_string()),
+///            check_supported_protocols_override("Link=1-4 HSIntro=3"));
+/// assert!(check_supported_protocols_override("Link=1-6").is_err());
+/// assert!(check_supported_protocols_override("Wombat=1").is_err());
+/// ```
+pub fn check_supported_protocols_override(protocols: &str) -> Result<String, ProtoverError> {
+    // Supporting nothing at all is fine, but isn't a parseable `ProtoEntry`.
+    if protocols.is_empty() {
+        return Ok(String::new());
+    }
+
+    let requested: ProtoEntry = protocols.parse()?;
+    let compiled_in: ProtoEntry = get_compiled_in_protocols().parse()?;
+
+    for (protocol, versions) in requested.iter() {
+        let allowed: bool = match compiled_in.get(protocol) {
+            Some(supported) => versions.is_subset(supported),
+            None => versions.is_empty(),
+        };
+        if !allowed {
+            return Err(ProtoverError::NotSupportedHere.in_protocol(protocol.name()));
         }
     }
+    ::canonical::canonicalize(protocols)
+}
+
+/// Override the protocols and versions which we claim to support, for
+/// instance so that an experimental relay can stop advertising `Link=5`.
+///
+/// The override is honoured by `get_supported_protocols()`,
+/// `ProtoEntry::supported()`, `is_supported_here()`, and by everything which
+/// uses them.  Passing `None` removes any override, so that we once again
+/// support the compiled-in protocols.
+///
+/// # Returns
+///
+/// A `Result` whose `Err` value is the `ProtoverError` from
+/// `check_supported_protocols_override()`, in which case the current override
+/// (if any) is left as it was.
+pub fn set_supported_protocols_override(protocols: Option<&str>) -> Result<(), ProtoverError> {
+    let replacement: SupportedProtocols = match protocols {
+        Some(p) => {
+            let mut protocols_with_nul: String = check_supported_protocols_override(p)?;
+            let entry: ProtoEntry = if protocols_with_nul.is_empty() {
+                ProtoEntry::default()
+            } else {
+                protocols_with_nul.parse()?
+            };
+
+            protocols_with_nul.push('\0');
+            SupportedProtocols { protocols_with_nul, entry }
+        },
+        None => SupportedProtocols::compiled_in(),
+    };
+
+    *supported_lock().write().unwrap_or_else(PoisonError::into_inner) = Arc::new(replacement);
+    Ok(())
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
 }
 
 /// A protocol string which is not one of the `Protocols` we currently know
 /// about.
-#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub struct UnknownProtocol(String);
 
 impl fmt::Display for UnknownProtocol {
@@ -128,42 +348,111 @@ impl From<Protocol> for UnknownProtocol {
 ///
 /// # Returns
 ///
-/// A `String` whose value is the existing protocols supported by tor.
+/// A `String` whose value is the existing protocols supported by tor, or
+/// the override set with `set_supported_protocols_override()`, if any.
 /// Returned data is in the format as follows:
 ///
 /// "HSDir=1-1 LinkAuth=1"
 ///
-pub fn get_supported_protocols() -> &'static str {
-    // The `len() - 1` is to remove the NUL byte.
-    // The `unwrap` is safe becauase we SUPPORTED_PROTOCOLS is under
-    // our control.
-    str::from_utf8(&SUPPORTED_PROTOCOLS[..SUPPORTED_PROTOCOLS.len() - 1])
-        .unwrap_or("")
+pub fn get_supported_protocols() -> String {
+    supported_protocols().protocols().to_string()
 }
 
 /// A map of protocol names to the versions of them which are supported.
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct ProtoEntry(HashMap<Protocol, ProtoSet>);
+///
+/// The protocols are kept in order of name, so iterating over them, or writing
+/// them out with `to_string()`, always gives the same order.  `ProtoEntry`s
+/// are ordered by comparing their `(protocol, versions)` pairs in turn.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ProtoEntry(BTreeMap<Protocol, ProtoSet>);
 
 impl Default for ProtoEntry {
     fn default() -> ProtoEntry {
-        ProtoEntry( HashMap::new() )
+        ProtoEntry( BTreeMap::new() )
     }
 }
 
 impl ProtoEntry {
-    /// Get an iterator over the `Protocol`s and their `ProtoSet`s in this `ProtoEntry`.
-    pub fn iter(&self) -> hash_map::Iter<Protocol, ProtoSet> {
+    /// Get an iterator over the `Protocol`s and their `ProtoSet`s in this
+    /// `ProtoEntry`, in order of protocol name.
+    pub fn iter(&self) -> btree_map::Iter<Protocol, ProtoSet> {
         self.0.iter()
     }
 
-    /// Translate the supported tor versions from a string into a
-    /// ProtoEntry, which is useful when looking up a specific
-    /// subprotocol.
-    pub fn supported() -> Result<Self, ProtoverError> {
-        let supported: &'static str = get_supported_protocols();
+    /// Parse a string of subprotocol types and their version numbers,
+    /// according to the given `ParseMode`.
+    ///
+    /// See `impl FromStr for ProtoEntry` for details of the `Lenient` mode.
+    ///
+    /// # Errors
+    ///
+    /// In `ParseMode::Strict`, this additionally returns a
+    /// `ProtoverError::DuplicateProtocol` if a protocol is listed twice, a
+    /// `ProtoverError::Unordered` if the protocol names aren't in ascending
+    /// order, and any error from `ProtoSet::parse_with()` in strict mode.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use protover::ProtoEntry;
+    /// use protover::errors::ProtoverError;
+    /// use protover::protoset::ParseMode;
+    ///
+    /// assert!(ProtoEntry::parse_with("Cons=1-2 Link=1,3", ParseMode::Strict).is_ok());
+    /// assert!(ProtoEntry::parse_with("Link=1 Cons=1-2", ParseMode::Lenient).is_ok());
+    ///
+    /// assert_eq!(Err(ProtoverError::Unordered.in_protocol("Cons")),
+    ///            ProtoEntry::parse_with("Link=1 Cons=1-2", ParseMode::Strict));
+    /// assert_eq!(Err(ProtoverError::DuplicateProtocol.in_protocol("Link")),
+    ///            ProtoEntry::parse_with("Link=1 Link=2", ParseMode::Strict));
+    /// ```
+    pub fn parse_with(protocol_entry: &str, mode: ParseMode) -> Result<Self, ProtoverError> {
+        let mut proto_entry: ProtoEntry = ProtoEntry::default();
+        let mut previous: Option<&str> = None;
+        let mut offset: usize = 0;
 
-        supported.parse()
+        for entry in protocol_entry.split(' ') {
+            let at: usize = offset;
+            let unparseable = || ProtoverError::Unparseable { at, token: entry.to_string() };
+            let mut parts = entry.splitn(2, '=');
+
+            offset += entry.len() + 1;
+
+            let proto = match parts.next() {
+                Some(n) => n,
+                None => return Err(unparseable()),
+            };
+
+            let vers = match parts.next() {
+                Some(n) => n,
+                None => return Err(unparseable()),
+            };
+            let versions: ProtoSet = ProtoSet::parse_with(vers, mode).map_err(|e| {
+                e.offset_by(at + proto.len() + 1).in_protocol(proto)
+            })?;
+            let proto_name: Protocol = proto.parse().map_err(|e: ProtoverError| {
+                e.in_protocol(proto)
+            })?;
+
+            if mode == ParseMode::Strict {
+                check_strict_order(&mut previous, proto, proto_entry.get(&proto_name).is_some())?;
+            }
+            proto_entry.insert(proto_name, versions);
+
+            if proto_entry.len() > MAX_PROTOCOLS_TO_EXPAND {
+                return Err(ProtoverError::ExceedsMax);
+            }
+        }
+        Ok(proto_entry)
+    }
+
+    /// Get the supported tor versions as a ProtoEntry, which is useful when
+    /// looking up a specific subprotocol.
+    ///
+    /// The supported versions are only parsed once (and again whenever they
+    /// are overridden), so this is cheap.
+    pub fn supported() -> Result<Self, ProtoverError> {
+        Ok(supported_protocols().entry.clone())
     }
 
     pub fn len(&self) -> usize {
@@ -205,32 +494,25 @@ impl FromStr for ProtoEntry {
     /// element is an ordered set of `(low, high)` unique version numbers which are supported.
     /// Otherwise, the `Err` value of this `Result` is a `ProtoverError`.
     fn from_str(protocol_entry: &str) -> Result<ProtoEntry, ProtoverError> {
-        let mut proto_entry: ProtoEntry = ProtoEntry::default();
-        let entries = protocol_entry.split(' ');
-
-        for entry in entries {
-            let mut parts = entry.splitn(2, '=');
-
-            let proto = match parts.next() {
-                Some(n) => n,
-                None => return Err(ProtoverError::Unparseable),
-            };
-
-            let vers = match parts.next() {
-                Some(n) => n,
-                None => return Err(ProtoverError::Unparseable),
-            };
-            let versions: ProtoSet = vers.parse()?;
-            let proto_name: Protocol = proto.parse()?;
-
-            proto_entry.insert(proto_name, versions);
+        ProtoEntry::parse_with(protocol_entry, ParseMode::Lenient)
+    }
+}
 
-            if proto_entry.len() > MAX_PROTOCOLS_TO_EXPAND {
-                return Err(ProtoverError::ExceedsMax);
-            }
+/// Strict parsing helper: check that protocol `name`, which has `duplicate`
+/// set if it was already parsed, comes after the `previous` protocol name.
+fn check_strict_order<'a>(previous: &mut Option<&'a str>, name: &'a str, duplicate: bool)
+    -> Result<(), ProtoverError>
+{
+    if duplicate {
+        return Err(ProtoverError::DuplicateProtocol.in_protocol(name));
+    }
+    if let Some(last) = *previous {
+        if name < last {
+            return Err(ProtoverError::Unordered.in_protocol(name));
         }
-        Ok(proto_entry)
     }
+    *previous = Some(name);
+    Ok(())
 }
 
 /// Generate an implementation of `ToString` for either a `ProtoEntry` or an
@@ -239,13 +521,19 @@ macro_rules! impl_to_string_for_proto_entry {
     ($t:ty) => (
         impl ToString for $t {
             fn to_string(&self) -> String {
-                let mut parts: Vec<String> = Vec::new();
+                use std::fmt::Write;
+
+                let mut out: String = String::new();
 
+                // The protocols are already in order, by name.
                 for (protocol, versions) in self.iter() {
-                    parts.push(format!("{}={}", protocol.to_string(), versions.to_string()));
+                    if !out.is_empty() {
+                        out.push(' ');
+                    }
+                    // Writing to a String can't fail.
+                    let _ = write!(out, "{}={}", protocol, versions.to_string());
                 }
-                parts.sort_unstable();
-                parts.join(" ")
+                out
             }
         }
     )
@@ -257,18 +545,21 @@ impl_to_string_for_proto_entry!(UnvalidatedProtoEntry);
 /// A `ProtoEntry`, but whose `Protocols` can be any `UnknownProtocol`, not just
 /// the supported ones enumerated in `Protocols`.  The protocol versions are
 /// validated, however.
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct UnvalidatedProtoEntry(HashMap<UnknownProtocol, ProtoSet>);
+///
+/// As with `ProtoEntry`, the protocols are kept in order of name.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UnvalidatedProtoEntry(BTreeMap<UnknownProtocol, ProtoSet>);
 
 impl Default for UnvalidatedProtoEntry {
     fn default() -> UnvalidatedProtoEntry {
-        UnvalidatedProtoEntry( HashMap::new() )
+        UnvalidatedProtoEntry( BTreeMap::new() )
     }
 }
 
 impl UnvalidatedProtoEntry {
-    /// Get an iterator over the `Protocol`s and their `ProtoSet`s in this `ProtoEntry`.
-    pub fn iter(&self) -> hash_map::Iter<UnknownProtocol, ProtoSet> {
+    /// Get an iterator over the `UnknownProtocol`s and their `ProtoSet`s in
+    /// this `UnvalidatedProtoEntry`, in order of protocol name.
+    pub fn iter(&self) -> btree_map::Iter<UnknownProtocol, ProtoSet> {
         self.0.iter()
     }
 
@@ -297,6 +588,60 @@ impl UnvalidatedProtoEntry {
         total
     }
 
+    /// Parse a protocol list without validating the protocol names,
+    /// according to the given `ParseMode`.
+    ///
+    /// See `impl FromStr for UnvalidatedProtoEntry` for details of the
+    /// `Lenient` mode, and `ProtoEntry::parse_with()` for the additional
+    /// errors in `ParseMode::Strict`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use protover::UnvalidatedProtoEntry;
+    /// use protover::errors::ProtoverError;
+    /// use protover::protoset::ParseMode;
+    ///
+    /// assert!(UnvalidatedProtoEntry::parse_with("Cons=1 Doggo=3-5", ParseMode::Strict).is_ok());
+    ///
+    /// assert_eq!(Err(ProtoverError::Unparseable { at: 6, token: "+1".to_string() }
+    ///                .in_protocol("Doggo")),
+    ///            UnvalidatedProtoEntry::parse_with("Doggo=+1", ParseMode::Strict));
+    /// ```
+    pub fn parse_with(protocol_string: &str, mode: ParseMode) -> Result<Self, ProtoverError> {
+        let mut parsed: UnvalidatedProtoEntry = UnvalidatedProtoEntry::default();
+        let mut previous: Option<&str> = None;
+        let mut offset: usize = 0;
+
+        for subproto in protocol_string.split(' ') {
+            let at: usize = offset;
+            let unparseable = || ProtoverError::Unparseable { at, token: subproto.to_string() };
+            let mut parts = subproto.splitn(2, '=');
+
+            offset += subproto.len() + 1;
+
+            let name = match parts.next() {
+                Some("") => return Err(unparseable()),
+                Some(n) => n,
+                None => return Err(unparseable()),
+            };
+            let vers = match parts.next() {
+                Some(n) => n,
+                None => return Err(unparseable()),
+            };
+            let versions = ProtoSet::parse_with(vers, mode).map_err(|e| {
+                e.offset_by(at + name.len() + 1).in_protocol(name)
+            })?;
+            let protocol = UnknownProtocol::from_str(name)?;
+
+            if mode == ParseMode::Strict {
+                check_strict_order(&mut previous, name, parsed.get(&protocol).is_some())?;
+            }
+            parsed.insert(protocol, versions);
+        }
+        Ok(parsed)
+    }
+
     /// Determine if we support every protocol a client supports, and if not,
     /// determine which protocols we do not have support for.
     ///
@@ -318,11 +663,31 @@ impl UnvalidatedProtoEntry {
     /// assert_eq!("Wombat=9", &unsupported.unwrap().to_string());
     /// ```
     pub fn all_supported(&self) -> Option<UnvalidatedProtoEntry> {
+        self.unsupported_by(&supported_protocols().entry)
+    }
+
+    /// Determine which of the protocols and versions in this
+    /// `UnvalidatedProtoEntry` are not in `supported`.
+    ///
+    /// This is `all_supported()`, but checking against some set of supported
+    /// protocols other than our own.
+    ///
+    /// # Returns
+    ///
+    /// `None` if everything is supported, and otherwise `Some` of the
+    /// protocols and versions which aren't.
+    ///
+    /// # Examples
+    /// ```
+    /// use protover::{ProtoEntry, UnvalidatedProtoEntry};
+    ///
+    /// let supported: ProtoEntry = "Link=1-4".parse().unwrap();
+    /// let protocols: UnvalidatedProtoEntry = "Link=3-5 Wombat=9".parse().unwrap();
+    /// let unsupported: Option<UnvalidatedProtoEntry> = protocols.unsupported_by(&supported);
+    /// assert_eq!("Link=5 Wombat=9", &unsupported.unwrap().to_string());
+    /// ```
+    pub fn unsupported_by(&self, supported: &ProtoEntry) -> Option<UnvalidatedProtoEntry> {
         let mut unsupported: UnvalidatedProtoEntry = UnvalidatedProtoEntry::default();
-        let supported: ProtoEntry = match ProtoEntry::supported() {
-            Ok(x)  => x,
-            Err(_) => return None,
-        };
 
         for (protocol, versions) in self.iter() {
             let is_supported: Result<Protocol, ProtoverError> = protocol.0.parse();
@@ -341,7 +706,7 @@ impl UnvalidatedProtoEntry {
 
             let maybe_supported_versions: Option<&ProtoSet> = supported.get(&supported_protocol);
             let supported_versions: &ProtoSet;
-            let mut unsupported_versions: ProtoSet;
+            let unsupported_versions: ProtoSet;
 
             // If the protocol wasn't in the map, then we don't know about it
             // and don't support any of its versions.  Add its versions to the
@@ -354,8 +719,7 @@ impl UnvalidatedProtoEntry {
             } else {
                 supported_versions = maybe_supported_versions.unwrap();
             }
-            unsupported_versions = versions.clone();
-            unsupported_versions.retain(|x| !supported_versions.contains(x));
+            unsupported_versions = versions.difference(supported_versions);
 
             if !unsupported_versions.is_empty() {
                 unsupported.insert(protocol.clone(), unsupported_versions);
@@ -458,27 +822,23 @@ impl FromStr for UnvalidatedProtoEntry {
     /// * The protocol string does not follow the "protocol_name=version_list"
     ///   expected format, or
     /// * If the version string is malformed. See `impl FromStr for ProtoSet`.
+    ///   The error is wrapped in a `ProtoverError::InProtocol` naming the
+    ///   protocol, and any byte offset within it is relative to the start of
+    ///   `protocol_string`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use protover::UnvalidatedProtoEntry;
+    /// use protover::errors::ProtoverError;
+    ///
+    /// let err: ProtoverError = "Cons=1-2 Link=1-x".parse::<UnvalidatedProtoEntry>().unwrap_err();
+    ///
+    /// assert_eq!(err.to_string(),
+    ///            "In protocol \"Link\": The protover string was unparseable at byte 14: \"1-x\".");
+    /// ```
     fn from_str(protocol_string: &str) -> Result<UnvalidatedProtoEntry, ProtoverError> {
-        let mut parsed: UnvalidatedProtoEntry = UnvalidatedProtoEntry::default();
-
-        for subproto in protocol_string.split(' ') {
-            let mut parts = subproto.splitn(2, '=');
-
-            let name = match parts.next() {
-                Some("") => return Err(ProtoverError::Unparseable),
-                Some(n) => n,
-                None => return Err(ProtoverError::Unparseable),
-            };
-            let vers = match parts.next() {
-                Some(n) => n,
-                None => return Err(ProtoverError::Unparseable),
-            };
-            let versions = ProtoSet::from_str(vers)?;
-            let protocol = UnknownProtocol::from_str(name)?;
-
-            parsed.insert(protocol, versions);
-        }
-        Ok(parsed)
+        UnvalidatedProtoEntry::parse_with(protocol_string, ParseMode::Lenient)
     }
 }
 
@@ -494,25 +854,25 @@ impl From<ProtoEntry> for UnvalidatedProtoEntry {
     }
 }
 
-/// A mapping of protocols to a count of how many times each of their `Version`s
-/// were voted for or supported.
+/// A mapping of protocols to each of the `ProtoSet`s which were voted for or
+/// supported for them.
 ///
 /// # Warning
 ///
 /// The "protocols" are *not* guaranteed to be known/supported `Protocol`s, in
 /// order to allow new subprotocols to be introduced even if Directory
 /// Authorities don't yet know of them.
-pub struct ProtoverVote( HashMap<UnknownProtocol, HashMap<Version, usize>> );
+pub struct ProtoverVote( BTreeMap<UnknownProtocol, Vec<ProtoSet>> );
 
 impl Default for ProtoverVote {
     fn default() -> ProtoverVote {
-        ProtoverVote( HashMap::new() )
+        ProtoverVote( BTreeMap::new() )
     }
 }
 
 impl IntoIterator for ProtoverVote {
-    type Item = (UnknownProtocol, HashMap<Version, usize>);
-    type IntoIter = hash_map::IntoIter<UnknownProtocol, HashMap<Version, usize>>;
+    type Item = (UnknownProtocol, Vec<ProtoSet>);
+    type IntoIter = btree_map::IntoIter<UnknownProtocol, Vec<ProtoSet>>;
 
     fn into_iter(self) -> Self::IntoIter {
         self.0.into_iter()
@@ -521,7 +881,7 @@ impl IntoIterator for ProtoverVote {
 
 impl ProtoverVote {
     pub fn entry(&mut self, key: UnknownProtocol)
-        -> hash_map::Entry<UnknownProtocol, HashMap<Version, usize>>
+        -> btree_map::Entry<UnknownProtocol, Vec<ProtoSet>>
     {
         self.0.entry(key)
     }
@@ -560,36 +920,266 @@ impl ProtoverVote {
             // subprotocols *or* individual version numbers, i.e. more than
             // MAX_PROTOCOLS_TO_EXPAND, and does this *per vote*, we need to
             // match it's behaviour and ensure we're not allowing more than it
-            // would.
+            // would.  (We never expand anything ourselves: `len()` is
+            // computed from the ranges, so this check is cheap.)
             if vote.len() > MAX_PROTOCOLS_TO_EXPAND {
                 continue;
             }
 
             for (protocol, versions) in vote.iter() {
-                let supported_vers: &mut HashMap<Version, usize> =
-                    all_count.entry(protocol.clone()).or_insert(HashMap::new());
-
-                for version in versions.clone().expand() {
-                    let counter: &mut usize =
-                        supported_vers.entry(version).or_insert(0);
-                    *counter += 1;
-                }
+                all_count.entry(protocol.clone()).or_insert(Vec::new()).push(versions.clone());
             }
         }
 
-        for (protocol, mut versions) in all_count {
-            // Go through and remove versions that are less than the threshold
-            versions.retain(|_, count| *count as usize >= *threshold);
-
-            if versions.len() > 0 {
-                let voted_versions: Vec<Version> = versions.keys().cloned().collect();
-                let voted_protoset: ProtoSet = ProtoSet::from(voted_versions);
+        for (protocol, votes) in all_count {
+            let voted_protoset: ProtoSet = versions_with_at_least(&votes, *threshold);
 
+            if !voted_protoset.is_empty() {
                 final_output.insert(protocol, voted_protoset);
             }
         }
         final_output
     }
+
+    /// Find the total weight of the votes for each version of each protocol,
+    /// given `(vote, weight)` pairs.  The weights could be those of
+    /// authorities, or the bandwidths of relays, for example.
+    ///
+    /// As in `compute()`, votes which list too many versions are ignored; nor
+    /// do they count towards the total weight.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use protover::ProtoverVote;
+    ///
+    /// let votes = vec![("Cons=1-2 Link=1-5".parse().unwrap(), 2),
+    ///                  ("Link=3-4".parse().unwrap(), 1)];
+    /// let weights = ProtoverVote::weigh(&votes);
+    ///
+    /// assert_eq!(3, weights.total);
+    /// assert_eq!("Cons=1-2 2\nLink=1-2 2\nLink=3-4 3\nLink=5 2\n", weights.to_string());
+    /// ```
+    pub fn weigh(votes: &[(UnvalidatedProtoEntry, u64)]) -> ProtocolWeights {
+        let mut by_protocol: BTreeMap<&UnknownProtocol, Vec<(&ProtoSet, u64)>> = BTreeMap::new();
+        let mut weights: ProtocolWeights = ProtocolWeights::default();
+
+        for &(ref vote, weight) in votes {
+            // C_RUST_DIFFERS: See `compute()`.
+            if vote.len() > MAX_PROTOCOLS_TO_EXPAND {
+                continue;
+            }
+            weights.total = weights.total.saturating_add(weight);
+
+            for (protocol, versions) in vote.iter() {
+                by_protocol.entry(protocol).or_insert(Vec::new()).push((versions, weight));
+            }
+        }
+
+        for (protocol, votes) in by_protocol {
+            let ranges: Vec<VersionWeight> = weigh_versions(&votes);
+
+            if !ranges.is_empty() {
+                weights.weights.insert(protocol.clone(), ranges);
+            }
+        }
+        weights
+    }
+
+    /// Weighted protocol voting.
+    ///
+    /// Given `(vote, weight)` pairs and a `threshold` between 0 and 1, return
+    /// a new `UnvalidatedProtoEntry` encoding all of the protocols whose votes
+    /// have at least that fraction of the total weight.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use protover::ProtoverVote;
+    ///
+    /// let votes = vec![("Link=3-5".parse().unwrap(), 3),
+    ///                  ("Link=3-4".parse().unwrap(), 1),
+    ///                  ("Link=3".parse().unwrap(), 1)];
+    ///
+    /// assert_eq!("Link=3-5", ProtoverVote::compute_weighted(&votes, 0.5).to_string());
+    /// assert_eq!("Link=3-4", ProtoverVote::compute_weighted(&votes, 0.8).to_string());
+    /// assert_eq!("Link=3", ProtoverVote::compute_weighted(&votes, 1.0).to_string());
+    /// ```
+    pub fn compute_weighted(votes: &[(UnvalidatedProtoEntry, u64)], threshold: f64)
+        -> UnvalidatedProtoEntry
+    {
+        ProtoverVote::weigh(votes).at_least(threshold)
+    }
+}
+
+/// A range of versions of some protocol, and the total weight of the votes
+/// for them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VersionWeight {
+    pub low: Version,
+    pub high: Version,
+    pub weight: u64,
+}
+
+/// Voting helper: find the total weight of the `votes` for each `Version`.
+///
+/// This sweeps over the endpoints of every `(low, high)` range in the
+/// `votes`, keeping a running total of the weight of the votes which cover
+/// the current position, so its cost depends only upon the number of ranges
+/// and never upon how many versions they contain.
+///
+/// # Returns
+///
+/// The ranges of versions with some weight, in ascending order, each as long
+/// as possible.  Versions which no vote covers, or only votes with no weight,
+/// are omitted.  A weight too large for a `u64` is given as `u64::MAX`.
+fn weigh_versions(votes: &[(&ProtoSet, u64)]) -> Vec<VersionWeight> {
+    let mut edges: Vec<(u64, bool, u64)> = Vec::new();
+    let mut weights: Vec<VersionWeight> = Vec::new();
+
+    // Each range opens at its low, and closes just after its high.  We work
+    // in u64 so that `high + 1` cannot overflow.  Ranges are merged first so
+    // that a vote listing e.g. "1-2,2-3" only counts version 2 once.
+    for &(vote, weight) in votes {
+        for (low, high) in coalesce(vote.pairs.clone()) {
+            edges.push((low as u64, true, weight));
+            edges.push((high as u64 + 1, false, weight));
+        }
+    }
+    edges.sort_unstable();
+
+    // The running total is kept exactly, in a u128 which the weights of any
+    // number of votes we could hold can't overflow, since saturating it would
+    // throw off the subtractions.  Only the weights we record saturate.
+    let mut total: u128 = 0;
+    let mut start: u64 = 0;
+    let mut i: usize = 0;
+
+    while i < edges.len() {
+        let position: u64 = edges[i].0;
+
+        if total > 0 {
+            let low: Version = start as Version;
+            let high: Version = (position - 1) as Version;
+            let total: u64 = if total > u64::MAX as u128 { u64::MAX } else { total as u64 };
+
+            match weights.last_mut() {
+                Some(ref mut last) if last.weight == total && last.high as u64 + 1 == start => {
+                    last.high = high;
+                },
+                _ => weights.push(VersionWeight { low, high, weight: total }),
+            }
+        }
+
+        // Apply every edge at this position before looking at the total, so
+        // that a range ending where another begins doesn't split the output.
+        while i < edges.len() && edges[i].0 == position {
+            if edges[i].1 {
+                total += edges[i].2 as u128;
+            } else {
+                total -= edges[i].2 as u128;
+            }
+            i += 1;
+        }
+        start = position;
+    }
+    weights
+}
+
+/// Voting helper: find the `Version`s which are contained in at least
+/// `threshold` of the `votes`.
+fn versions_with_at_least(votes: &[ProtoSet], threshold: usize) -> ProtoSet {
+    // A threshold of zero is satisfied by every version which was voted for
+    // at all, just as in the C implementation.
+    let threshold: u64 = cmp::max(threshold, 1) as u64;
+    let counted: Vec<(&ProtoSet, u64)> = votes.iter().map(|vote| (vote, 1)).collect();
+    let pairs: Vec<(Version, Version)> = weigh_versions(&counted).into_iter()
+        .filter(|range| range.weight >= threshold)
+        .map(|range| (range.low, range.high))
+        .collect();
+
+    ProtoSet{ pairs: coalesce(pairs) }
+}
+
+/// The total weight of the votes for each version of each protocol, as
+/// computed by `ProtoverVote::weigh()`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProtocolWeights {
+    /// The total weight of all of the votes.
+    pub total: u64,
+    /// For each protocol, the ranges of its versions with some weight, in
+    /// ascending order.
+    pub weights: BTreeMap<UnknownProtocol, Vec<VersionWeight>>,
+}
+
+impl ProtocolWeights {
+    /// Get the total weight of the votes for `version` of `protocol`.
+    pub fn weight_of(&self, protocol: &UnknownProtocol, version: Version) -> u64 {
+        self.weights.get(protocol)
+            .and_then(|ranges| ranges.iter().find(|r| r.low <= version && version <= r.high))
+            .map_or(0, |range| range.weight)
+    }
+
+    /// Get the fraction of the total weight of the votes which is for
+    /// `version` of `protocol`, or 0 if the votes have no weight at all.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use protover::ProtoverVote;
+    ///
+    /// // Three relays, with their bandwidths.
+    /// let relays = vec![("Link=1-5".parse().unwrap(), 3000),
+    ///                   ("Link=1-4".parse().unwrap(), 500),
+    ///                   ("Link=1-5".parse().unwrap(), 1500)];
+    /// let weights = ProtoverVote::weigh(&relays);
+    ///
+    /// assert_eq!(0.9, weights.fraction_of(&"Link".parse().unwrap(), 5));
+    /// ```
+    pub fn fraction_of(&self, protocol: &UnknownProtocol, version: Version) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.weight_of(protocol, version) as f64 / self.total as f64
+    }
+
+    /// Find the versions of each protocol whose votes have at least
+    /// `threshold` of the total weight, where `threshold` is a fraction
+    /// between 0 and 1.  Versions must have some weight to be included, even
+    /// when `threshold` is 0.
+    pub fn at_least(&self, threshold: f64) -> UnvalidatedProtoEntry {
+        let mut output: UnvalidatedProtoEntry = UnvalidatedProtoEntry::default();
+        let needed: f64 = threshold * self.total as f64;
+
+        for (protocol, ranges) in self.weights.iter() {
+            let pairs: Vec<(Version, Version)> = ranges.iter()
+                .filter(|range| range.weight as f64 >= needed)
+                .map(|range| (range.low, range.high))
+                .collect();
+
+            if !pairs.is_empty() {
+                output.insert(protocol.clone(), ProtoSet{ pairs: coalesce(pairs) });
+            }
+        }
+        output
+    }
+}
+
+/// Write out the weights one range per line, as in `Link=1-4 5000`, in order
+/// of protocol name and then version.
+impl fmt::Display for ProtocolWeights {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (protocol, ranges) in self.weights.iter() {
+            for range in ranges.iter() {
+                if range.low == range.high {
+                    writeln!(f, "{}={} {}", protocol, range.low, range.weight)?;
+                } else {
+                    writeln!(f, "{}={}-{} {}", protocol, range.low, range.high, range.weight)?;
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 /// Returns a boolean indicating whether the given protocol and version is
@@ -607,11 +1197,8 @@ impl ProtoverVote {
 /// assert_eq!(true, is_supported);
 /// ```
 pub fn is_supported_here(proto: &Protocol, vers: &Version) -> bool {
-    let currently_supported: ProtoEntry = match ProtoEntry::supported() {
-        Ok(result) => result,
-        Err(_) => return false,
-    };
-    let supported_versions = match currently_supported.get(proto) {
+    let currently_supported: Arc<SupportedProtocols> = supported_protocols();
+    let supported_versions = match currently_supported.entry.get(proto) {
         Some(n) => n,
         None => return false,
     };
@@ -635,7 +1222,8 @@ pub fn is_supported_here(proto: &Protocol, vers: &Version) -> bool {
 /// This function returns the protocols that are supported by the version input,
 /// only for tor versions older than `FIRST_TOR_VERSION_TO_ADVERTISE_PROTOCOLS`
 /// (but not older than 0.2.4.19).  For newer tors (or older than 0.2.4.19), it
-/// returns an empty string.
+/// returns an empty string.  The versions and their protocols come from the
+/// table in `data/protocol_history.txt`; see the `history` module.
 ///
 /// # Note
 ///
@@ -643,24 +1231,9 @@ pub fn is_supported_here(proto: &Protocol, vers: &Version) -> bool {
 /// like to use this code in Rust, please see `compute_for_old_tor()`.
 //
 // C_RUST_COUPLED: src/rust/protover.c `compute_for_old_tor`
+// C_RUST_COUPLED: src/rust/protover/data/protocol_history.txt
 pub(crate) fn compute_for_old_tor_cstr(version: &str) -> &'static [u8] {
-    if c_tor_version_as_new_as(version, FIRST_TOR_VERSION_TO_ADVERTISE_PROTOCOLS) {
-        return NUL_BYTE;
-    }
-    if c_tor_version_as_new_as(version, "0.2.9.1-alpha") {
-        return b"Cons=1-2 Desc=1-2 DirCache=1 HSDir=1 HSIntro=3 HSRend=1-2 \
-                 Link=1-4 LinkAuth=1 Microdesc=1-2 Relay=1-2\0";
-    }
-    if c_tor_version_as_new_as(version, "0.2.7.5") {
-        return b"Cons=1-2 Desc=1-2 DirCache=1 HSDir=1 HSIntro=3 HSRend=1 \
-                 Link=1-4 LinkAuth=1 Microdesc=1-2 Relay=1-2\0";
-    }
-    if c_tor_version_as_new_as(version, "0.2.4.19") {
-        return b"Cons=1 Desc=1 DirCache=1 HSDir=1 HSIntro=3 HSRend=1 \
-                 Link=1-4 LinkAuth=1 Microdesc=1 Relay=1-2\0";
-    }
-
-    NUL_BYTE
+    inferred_protocols_with_nul(version).unwrap_or(NUL_BYTE)
 }
 
 /// Since older versions of Tor cannot infer their own subprotocols,
@@ -698,7 +1271,9 @@ pub fn compute_for_old_tor(version: &str) -> Result<&'static str, ProtoverError>
 
     // .from_utf8() fails with a Utf8Error if it couldn't validate the
     // utf-8, so convert that here into an Unparseable ProtoverError.
-    str::from_utf8(computed).or(Err(ProtoverError::Unparseable))
+    str::from_utf8(computed).map_err(|e| {
+        ProtoverError::Unparseable { at: e.valid_up_to(), token: String::new() }
+    })
 }
 
 #[cfg(test)]
@@ -827,4 +1402,174 @@ mod test {
         versions = "1-3,500";
         assert_eq!(String::from(versions), ProtoSet::from_str(&versions).unwrap().to_string());
     }
+
+    #[test]
+    fn test_weigh_bandwidth_fraction() {
+        let relays: Vec<(UnvalidatedProtoEntry, u64)> =
+            vec![("Link=1-5 Relay=1-2".parse().unwrap(), 6000),
+                 ("Link=1-4 Relay=1-2".parse().unwrap(), 3000),
+                 ("Link=1-5".parse().unwrap(), 1000)];
+        let weights: ProtocolWeights = ProtoverVote::weigh(&relays);
+        let link: UnknownProtocol = "Link".parse().unwrap();
+
+        assert_eq!(10000, weights.total);
+        assert_eq!(7000, weights.weight_of(&link, 5));
+        assert_eq!(10000, weights.weight_of(&link, 4));
+        assert_eq!(0, weights.weight_of(&link, 6));
+        assert_eq!(0.7, weights.fraction_of(&link, 5));
+        assert_eq!(0.0, weights.fraction_of(&"Wombat".parse().unwrap(), 1));
+        assert_eq!("Link=1-4 Relay=1-2", weights.at_least(0.75).to_string());
+        assert_eq!("Link=1-5 Relay=1-2", weights.at_least(0.7).to_string());
+    }
+
+    #[test]
+    fn test_weigh_ignores_zero_weights() {
+        let votes: Vec<(UnvalidatedProtoEntry, u64)> =
+            vec![("Link=1-2".parse().unwrap(), 0),
+                 ("Cons=1".parse().unwrap(), 2)];
+        let weights: ProtocolWeights = ProtoverVote::weigh(&votes);
+
+        assert_eq!(2, weights.total);
+        assert_eq!("Cons=1 2\n", weights.to_string());
+        assert_eq!("Cons=1", weights.at_least(0.0).to_string());
+    }
+
+    #[test]
+    fn test_weigh_saturates() {
+        let max: u64 = u64::MAX;
+        let votes: Vec<(UnvalidatedProtoEntry, u64)> =
+            vec![("Link=1-3".parse().unwrap(), max),
+                 ("Link=2-4".parse().unwrap(), max),
+                 ("Link=3".parse().unwrap(), 1)];
+        let weights: ProtocolWeights = ProtoverVote::weigh(&votes);
+        let link: UnknownProtocol = "Link".parse().unwrap();
+
+        assert_eq!(max, weights.total);
+        assert_eq!(max, weights.weight_of(&link, 1));
+        assert_eq!(max, weights.weight_of(&link, 3));
+        assert_eq!(max, weights.weight_of(&link, 4));
+        assert_eq!(format!("Link=1-4 {}\n", max), weights.to_string());
+    }
+
+    #[test]
+    fn test_compute_weighted_out_of_range_threshold() {
+        let votes: Vec<(UnvalidatedProtoEntry, u64)> =
+            vec![("Link=1-5".parse().unwrap(), 1)];
+
+        assert_eq!("", ProtoverVote::compute_weighted(&votes, 1.5).to_string());
+        assert_eq!("", ProtoverVote::compute_weighted(&votes, ::std::f64::NAN).to_string());
+        assert_eq!("", ProtoverVote::compute_weighted(&[], 0.5).to_string());
+    }
+
+    #[test]
+    fn test_weigh_skips_huge_votes() {
+        let votes: Vec<(UnvalidatedProtoEntry, u64)> =
+            vec![("Link=1-4294967294".parse().unwrap(), 10),
+                 ("Link=1-2".parse().unwrap(), 1)];
+        let weights: ProtocolWeights = ProtoverVote::weigh(&votes);
+
+        assert_eq!(1, weights.total);
+        assert_eq!("Link=1-2 1\n", weights.to_string());
+    }
+
+    #[test]
+    fn test_compute_vote_adjacent_ranges_merge() {
+        let votes: &[UnvalidatedProtoEntry] = &["Link=1-3".parse().unwrap(),
+                                                 "Link=4-6".parse().unwrap(),
+                                                 "Link=1-6".parse().unwrap()];
+
+        assert_eq!("Link=1-6", ProtoverVote::compute(votes, &2).to_string());
+    }
+
+    #[test]
+    fn test_compute_vote_huge_ranges_without_limit() {
+        let votes: &[ProtoSet] = &["1-4294967294".parse().unwrap(),
+                                   "0-4294967293".parse().unwrap(),
+                                   "7,4294967294".parse().unwrap()];
+
+        assert_eq!("1-4294967294", versions_with_at_least(votes, 2).to_string());
+        assert_eq!("7", versions_with_at_least(votes, 3).to_string());
+        assert_eq!("0-4294967294", versions_with_at_least(votes, 0).to_string());
+    }
+
+    #[test]
+    fn test_protocols_registry_is_sorted_by_name() {
+        for pair in PROTOCOLS.windows(2) {
+            assert!(pair[0].name < pair[1].name, "{} >= {}", pair[0].name, pair[1].name);
+        }
+    }
+
+    #[test]
+    fn test_protocols_registry_round_trips() {
+        for info in PROTOCOLS {
+            assert_eq!(info.name, info.protocol.to_string());
+            assert_eq!(Ok(info.protocol.clone()), info.name.parse());
+            assert!(info.supported.parse::<ProtoSet>().is_ok(), "{}", info.name);
+        }
+    }
+
+    /// The C ids must be those of `protocol_type_t`, which numbers its
+    /// protocols from 0.  test_protover.c checks, through the FFI, that each
+    /// is the protocol it names.
+    #[test]
+    fn test_protocols_registry_c_ids_are_dense() {
+        let mut c_ids: Vec<u32> = PROTOCOLS.iter().map(|info| info.c_id).collect();
+
+        c_ids.sort();
+        assert_eq!((0..PROTOCOLS.len() as u32).collect::<Vec<u32>>(), c_ids);
+    }
+
+    #[test]
+    fn test_compiled_in_protocols_are_canonical() {
+        assert_eq!(Ok(true), ::canonical::is_canonical(get_compiled_in_protocols()));
+    }
+
+    #[test]
+    fn test_supported_protocols_are_memoized() {
+        assert!(Arc::ptr_eq(&supported_protocols(), &supported_protocols()));
+        assert_eq!(Ok(supported_protocols().entry.clone()), get_supported_protocols().parse());
+        assert_eq!(Ok(supported_protocols().entry.clone()), ProtoEntry::supported());
+    }
+
+    #[test]
+    fn test_protocol_order_is_name_order() {
+        for pair in PROTOCOLS.windows(2) {
+            assert!(pair[0].protocol < pair[1].protocol);
+            assert!(UnknownProtocol::from(pair[0].protocol.clone()) <
+                    UnknownProtocol::from(pair[1].protocol.clone()));
+        }
+    }
+
+    #[test]
+    fn test_proto_entry_iterates_in_order() {
+        let entry: ProtoEntry = "Relay=1 Cons=2 LinkAuth=3 Link=4".parse().unwrap();
+        let names: Vec<&str> = entry.iter().map(|(protocol, _)| protocol.name()).collect();
+
+        assert_eq!(vec!["Cons", "Link", "LinkAuth", "Relay"], names);
+        assert_eq!("Cons=2 Link=4 LinkAuth=3 Relay=1", entry.to_string());
+    }
+
+    #[test]
+    fn test_unvalidated_proto_entry_iterates_in_order() {
+        let entry: UnvalidatedProtoEntry = "Zebra=1 Link=4 Aardvark=2 Link-x=3".parse().unwrap();
+        let names: Vec<String> = entry.iter().map(|(protocol, _)| protocol.to_string()).collect();
+
+        assert_eq!(vec!["Aardvark", "Link", "Link-x", "Zebra"], names);
+        assert_eq!("Aardvark=2 Link=4 Link-x=3 Zebra=1", entry.to_string());
+    }
+
+    #[test]
+    fn test_proto_entry_ordering() {
+        let older: UnvalidatedProtoEntry = "Cons=1 Link=1-4".parse().unwrap();
+        let newer: UnvalidatedProtoEntry = "Cons=1-2 Link=1-4".parse().unwrap();
+        let fewer: UnvalidatedProtoEntry = "Cons=1".parse().unwrap();
+
+        assert!(older < newer);
+        assert!(fewer < older);
+        assert!(UnvalidatedProtoEntry::default() < fewer);
+
+        let mut entries = vec![newer.clone(), older.clone(), fewer.clone()];
+        entries.sort();
+        assert_eq!(vec![fewer, older, newer], entries);
+    }
 }
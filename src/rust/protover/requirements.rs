@@ -0,0 +1,221 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Deciding whether we can keep running, given the protocols which the
+//! consensus requires and recommends.
+//!
+//! Each consensus lists, for clients and for relays, the protocols which they
+//! are required to support (those which don't should exit) and those which
+//! they're recommended to support (those which don't should warn their
+//! operators to upgrade).
+
+use std::fmt;
+
+use errors::ProtoverError;
+use protover::ProtoEntry;
+use protover::UnvalidatedProtoEntry;
+
+/// Whether we're checking the requirements for a client or for a relay.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Role {
+    Client,
+    Relay,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Role::Client => write!(f, "client"),
+            Role::Relay => write!(f, "relay"),
+        }
+    }
+}
+
+/// The outcome of checking some supported protocols against the consensus.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Verdict {
+    /// Every required and recommended protocol is supported.
+    Ok,
+    /// Every required protocol is supported, but these recommended ones
+    /// aren't.
+    MissingRecommended(UnvalidatedProtoEntry),
+    /// These required protocols aren't supported.
+    MissingRequired(UnvalidatedProtoEntry),
+}
+
+impl Verdict {
+    /// Determine if we should exit, because we're missing some required
+    /// protocol.
+    pub fn should_exit(&self) -> bool {
+        match *self {
+            Verdict::MissingRequired(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Describe the problem (if any) with running as a `role` which is missing
+    /// the protocols in this `Verdict`, in a form suitable for logging.
+    ///
+    /// C_RUST_COUPLED: src/or/protover.c `protover_check_requirements`
+    pub fn warning(&self, role: Role) -> Option<String> {
+        match *self {
+            Verdict::Ok => None,
+            Verdict::MissingRequired(ref missing) => Some(format!(
+                "At least one protocol listed as required in the consensus is \
+                 not supported by this version of Tor. You should upgrade. This \
+                 version of Tor will not work as a {} on the Tor network. The \
+                 missing protocols are: {}",
+                role, missing.to_string())),
+            Verdict::MissingRecommended(ref missing) => Some(format!(
+                "At least one protocol listed as recommended in the consensus \
+                 is not supported by this version of Tor. You should upgrade. \
+                 This version of Tor will eventually stop working as a {} on the \
+                 Tor network. The missing protocols are: {}",
+                role, missing.to_string())),
+        }
+    }
+}
+
+/// The protocols which a consensus requires and recommends, from its
+/// `required-client-protocols`, `recommended-client-protocols`,
+/// `required-relay-protocols` and `recommended-relay-protocols` lines.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ProtocolRequirements {
+    pub required_client: UnvalidatedProtoEntry,
+    pub recommended_client: UnvalidatedProtoEntry,
+    pub required_relay: UnvalidatedProtoEntry,
+    pub recommended_relay: UnvalidatedProtoEntry,
+}
+
+/// Parse one of the protocol lines of a consensus, which may be empty.
+///
+/// Callers acting on a consensus should treat a line which can't be parsed as
+/// requiring nothing, as tor does, so that one malformed line, or one in some
+/// future syntax, can't make every relay and client exit.
+pub(crate) fn parse_consensus_line(line: &str) -> Result<UnvalidatedProtoEntry, ProtoverError> {
+    if line.is_empty() {
+        return Ok(UnvalidatedProtoEntry::default());
+    }
+    line.parse()
+}
+
+impl ProtocolRequirements {
+    /// Parse the four protocol lines of a consensus.  An empty line requires
+    /// or recommends nothing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use protover::requirements::*;
+    ///
+    /// let requirements = ProtocolRequirements::from_consensus(
+    ///     "Link=4", "Link=4-5", "Link=4 Relay=2", "").unwrap();
+    /// assert!(requirements.recommended_relay.is_empty());
+    /// ```
+    pub fn from_consensus(
+        required_client: &str,
+        recommended_client: &str,
+        required_relay: &str,
+        recommended_relay: &str,
+    ) -> Result<ProtocolRequirements, ProtoverError> {
+        Ok(ProtocolRequirements {
+            required_client: parse_consensus_line(required_client)?,
+            recommended_client: parse_consensus_line(recommended_client)?,
+            required_relay: parse_consensus_line(required_relay)?,
+            recommended_relay: parse_consensus_line(recommended_relay)?,
+        })
+    }
+
+    /// Check whether a `role` which supports the protocols in `supported`
+    /// meets these requirements.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use protover::ProtoEntry;
+    /// use protover::requirements::*;
+    ///
+    /// let requirements = ProtocolRequirements::from_consensus(
+    ///     "Link=4", "Link=4-5", "Link=4 Relay=2", "").unwrap();
+    /// let supported: ProtoEntry = "Link=1-4 Relay=1-2".parse().unwrap();
+    ///
+    /// assert_eq!(Verdict::Ok, requirements.evaluate(Role::Relay, &supported));
+    ///
+    /// let verdict = requirements.evaluate(Role::Client, &supported);
+    /// assert!(!verdict.should_exit());
+    /// assert_eq!(Verdict::MissingRecommended("Link=5".parse().unwrap()), verdict);
+    /// ```
+    pub fn evaluate(&self, role: Role, supported: &ProtoEntry) -> Verdict {
+        let (required, recommended) = match role {
+            Role::Client => (&self.required_client, &self.recommended_client),
+            Role::Relay => (&self.required_relay, &self.recommended_relay),
+        };
+
+        if let Some(missing) = required.unsupported_by(supported) {
+            return Verdict::MissingRequired(missing);
+        }
+        if let Some(missing) = recommended.unsupported_by(supported) {
+            return Verdict::MissingRecommended(missing);
+        }
+        Verdict::Ok
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn requirements() -> ProtocolRequirements {
+        ProtocolRequirements::from_consensus(
+            "Cons=1-2 Link=4",
+            "Cons=1-2 Link=4-5 Wombat=1",
+            "Link=4 Relay=2",
+            "Link=4-5 Relay=2").unwrap()
+    }
+
+    #[test]
+    fn test_evaluate_ok() {
+        let supported: ProtoEntry = "Cons=1-2 Link=1-5 Relay=1-2".parse().unwrap();
+
+        assert_eq!(Verdict::Ok, requirements().evaluate(Role::Relay, &supported));
+        assert_eq!(None, Verdict::Ok.warning(Role::Relay));
+    }
+
+    #[test]
+    fn test_evaluate_missing_required() {
+        let supported: ProtoEntry = "Cons=1 Link=1-3 Relay=1".parse().unwrap();
+        let verdict: Verdict = requirements().evaluate(Role::Client, &supported);
+
+        assert!(verdict.should_exit());
+        assert_eq!(Verdict::MissingRequired("Cons=2 Link=4".parse().unwrap()), verdict);
+
+        let warning: String = verdict.warning(Role::Client).unwrap();
+        assert!(warning.contains("will not work as a client"));
+        assert!(warning.ends_with("The missing protocols are: Cons=2 Link=4"));
+    }
+
+    #[test]
+    fn test_evaluate_missing_recommended_includes_unknown() {
+        let supported: ProtoEntry = "Cons=1-2 Link=1-4".parse().unwrap();
+        let verdict: Verdict = requirements().evaluate(Role::Client, &supported);
+
+        assert!(!verdict.should_exit());
+        assert_eq!(Verdict::MissingRecommended("Link=5 Wombat=1".parse().unwrap()), verdict);
+        assert!(verdict.warning(Role::Client).unwrap()
+                .contains("will eventually stop working as a client"));
+    }
+
+    #[test]
+    fn test_evaluate_empty_requirements() {
+        let supported: ProtoEntry = "Link=1".parse().unwrap();
+        let requirements: ProtocolRequirements = ProtocolRequirements::default();
+
+        assert_eq!(Verdict::Ok, requirements.evaluate(Role::Client, &supported));
+        assert_eq!(Ok(requirements), ProtocolRequirements::from_consensus("", "", "", ""));
+    }
+
+    #[test]
+    fn test_from_consensus_unparseable() {
+        assert!(ProtocolRequirements::from_consensus("", "", "Link=1-x", "").is_err());
+    }
+}
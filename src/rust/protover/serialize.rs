@@ -0,0 +1,291 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Serialization of protocol lists with serde, when the `serde` feature is
+//! enabled.  It is off by default, so that tor itself is built without serde.
+//!
+//! A `ProtoSet` is serialized as a sequence of `[low, high]` pairs, and a
+//! `ProtoEntry` or `UnvalidatedProtoEntry` as a map from protocol names to
+//! `ProtoSet`s, in order of name.  So, in JSON, `"Link=1-5 LinkAuth=1,3"` is
+//!
+//! ```json
+//! {"Link":[[1,5]],"LinkAuth":[[1,1],[3,3]]}
+//! ```
+//!
+//! A `ProtoSet` may also be deserialized from its usual string form, such as
+//! `"1,3"`, and the `compact` module can be used with `#[serde(with)]` to
+//! serialize one that way too.  Deserializing checks everything which parsing
+//! the string form would, so anything which serializes deserializes to an
+//! equal value.
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+use serde::de;
+use serde::de::Deserialize;
+use serde::de::Deserializer;
+use serde::de::MapAccess;
+use serde::de::SeqAccess;
+use serde::de::Visitor;
+use serde::ser::Serialize;
+use serde::ser::SerializeMap;
+use serde::ser::SerializeSeq;
+use serde::ser::Serializer;
+
+use errors::ProtoverError;
+use protoset::ProtoSet;
+use protoset::Version;
+use protover::Protocol;
+use protover::ProtoEntry;
+use protover::UnknownProtocol;
+use protover::UnvalidatedProtoEntry;
+
+impl Serialize for ProtoSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.iter().len()))?;
+
+        for pair in self.iter() {
+            seq.serialize_element(pair)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for ProtoSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ProtoSet, D::Error> {
+        deserializer.deserialize_any(ProtoSetVisitor)
+    }
+}
+
+/// Serialize and deserialize a `ProtoSet` in its usual string form, such as
+/// `"1-5,7"`, rather than as a sequence of pairs, with
+/// `#[serde(with = "protover::serialize::compact")]`.
+pub mod compact {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(versions: &ProtoSet, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&versions.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ProtoSet, D::Error> {
+        deserializer.deserialize_str(ProtoSetVisitor)
+    }
+}
+
+impl Serialize for ProtoEntry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_entry(self.iter().map(|(p, v)| (p.to_string(), v)), serializer)
+    }
+}
+
+/// Unknown protocols can't be deserialized into a `ProtoEntry`.
+impl<'de> Deserialize<'de> for ProtoEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ProtoEntry, D::Error> {
+        deserializer.deserialize_map(EntryVisitor(PhantomData))
+    }
+}
+
+impl Serialize for UnvalidatedProtoEntry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_entry(self.iter().map(|(p, v)| (p.to_string(), v)), serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for UnvalidatedProtoEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<UnvalidatedProtoEntry, D::Error> {
+        deserializer.deserialize_map(EntryVisitor(PhantomData))
+    }
+}
+
+/// Visits either a sequence of `(low, high)` pairs, or a string, to build a
+/// `ProtoSet`.
+struct ProtoSetVisitor;
+
+impl<'de> Visitor<'de> for ProtoSetVisitor {
+    type Value = ProtoSet;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a list of [low, high] version pairs, or a version string")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ProtoSet, A::Error> {
+        let mut pairs: Vec<(Version, Version)> = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+
+        while let Some(pair) = seq.next_element()? {
+            pairs.push(pair);
+        }
+        ProtoSet::from_slice(&pairs).map_err(de::Error::custom)
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<ProtoSet, E> {
+        ProtoSet::from_str(s).map_err(de::Error::custom)
+    }
+}
+
+/// Serialize the `(name, versions)` pairs of a protocol list, which are in
+/// order of name, as a map.
+fn serialize_entry<'a, S, I>(iter: I, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer,
+          I: ExactSizeIterator<Item = (String, &'a ProtoSet)>,
+{
+    let mut map = serializer.serialize_map(Some(iter.len()))?;
+
+    for (name, versions) in iter {
+        map.serialize_entry(&name, versions)?;
+    }
+    map.end()
+}
+
+/// A protocol list which can be built from a map of names to `ProtoSet`s.
+trait FromNamedSets: Default {
+    /// Add the `versions` of the protocol called `name`.
+    ///
+    /// # Errors
+    ///
+    /// A `ProtoverError::DuplicateProtocol` if `name` is already present, or
+    /// any error from parsing `name`.
+    fn insert_named(&mut self, name: &str, versions: ProtoSet) -> Result<(), ProtoverError>;
+}
+
+impl FromNamedSets for ProtoEntry {
+    fn insert_named(&mut self, name: &str, versions: ProtoSet) -> Result<(), ProtoverError> {
+        let protocol: Protocol = name.parse().map_err(|e: ProtoverError| e.in_protocol(name))?;
+
+        if self.get(&protocol).is_some() {
+            return Err(ProtoverError::DuplicateProtocol.in_protocol(name));
+        }
+        self.insert(protocol, versions);
+        Ok(())
+    }
+}
+
+impl FromNamedSets for UnvalidatedProtoEntry {
+    fn insert_named(&mut self, name: &str, versions: ProtoSet) -> Result<(), ProtoverError> {
+        // These names couldn't have come from parsing a string, and wouldn't
+        // survive being turned back into one.
+        if name.is_empty() || name.contains(&[' ', '='][..]) {
+            return Err(ProtoverError::Unparseable { at: 0, token: name.to_string() });
+        }
+
+        let protocol: UnknownProtocol = name.parse()?;
+
+        if self.get(&protocol).is_some() {
+            return Err(ProtoverError::DuplicateProtocol.in_protocol(name));
+        }
+        self.insert(protocol, versions);
+        Ok(())
+    }
+}
+
+/// Visits a map of protocol names to `ProtoSet`s, to build a `T`.
+struct EntryVisitor<T>(PhantomData<T>);
+
+impl<'de, T: FromNamedSets> Visitor<'de> for EntryVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a map of protocol names to versions")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<T, A::Error> {
+        let mut entry: T = T::default();
+
+        while let Some((name, versions)) = map.next_entry::<String, ProtoSet>()? {
+            entry.insert_named(&name, versions).map_err(de::Error::custom)?;
+        }
+        Ok(entry)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json;
+
+    use super::*;
+
+    #[test]
+    fn test_protoset_as_pairs() {
+        let versions: ProtoSet = "1-3,5,7-9".parse().unwrap();
+        let json: String = serde_json::to_string(&versions).unwrap();
+
+        assert_eq!("[[1,3],[5,5],[7,9]]", json);
+        assert_eq!(versions, serde_json::from_str::<ProtoSet>(&json).unwrap());
+        assert_eq!("[]", serde_json::to_string(&ProtoSet::default()).unwrap());
+    }
+
+    #[test]
+    fn test_protoset_from_string() {
+        assert_eq!("1-3,5".parse::<ProtoSet>().unwrap(),
+                   serde_json::from_str::<ProtoSet>("\"1-3,5\"").unwrap());
+    }
+
+    #[test]
+    fn test_protoset_invalid() {
+        assert!(serde_json::from_str::<ProtoSet>("[[3,1]]").is_err());
+        assert!(serde_json::from_str::<ProtoSet>("[[1,5],[3,7]]").is_err());
+        assert!(serde_json::from_str::<ProtoSet>("[[1,4294967295]]").is_err());
+        assert!(serde_json::from_str::<ProtoSet>("[[1]]").is_err());
+        assert!(serde_json::from_str::<ProtoSet>("\"1-x\"").is_err());
+    }
+
+    #[test]
+    fn test_protoset_compact() {
+        #[derive(Debug, PartialEq)]
+        struct Compact(ProtoSet);
+
+        impl Serialize for Compact {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                compact::serialize(&self.0, serializer)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for Compact {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Compact, D::Error> {
+                compact::deserialize(deserializer).map(Compact)
+            }
+        }
+
+        let versions = Compact("1-3,5".parse().unwrap());
+        let json: String = serde_json::to_string(&versions).unwrap();
+
+        assert_eq!("\"1-3,5\"", json);
+        assert_eq!(versions, serde_json::from_str::<Compact>(&json).unwrap());
+        assert!(serde_json::from_str::<Compact>("[[1,3]]").is_err());
+    }
+
+    #[test]
+    fn test_proto_entry_is_ordered() {
+        let entry: ProtoEntry = "Relay=1-2 Link=1-5 LinkAuth=1,3 Cons=1-2".parse().unwrap();
+        let json: String = serde_json::to_string(&entry).unwrap();
+
+        assert_eq!("{\"Cons\":[[1,2]],\"Link\":[[1,5]],\"LinkAuth\":[[1,1],[3,3]],\
+                    \"Relay\":[[1,2]]}", json);
+        assert_eq!(entry, serde_json::from_str::<ProtoEntry>(&json).unwrap());
+    }
+
+    #[test]
+    fn test_proto_entry_unknown_protocol() {
+        assert!(serde_json::from_str::<ProtoEntry>("{\"Wombat\":[[1,1]]}").is_err());
+    }
+
+    #[test]
+    fn test_unvalidated_proto_entry_round_trip() {
+        let entry: UnvalidatedProtoEntry = "Wombat=9 Link=1-5 Doggo= Cons=1,3-4".parse().unwrap();
+        let json: String = serde_json::to_string(&entry).unwrap();
+
+        assert_eq!("{\"Cons\":[[1,1],[3,4]],\"Doggo\":[],\"Link\":[[1,5]],\
+                    \"Wombat\":[[9,9]]}", json);
+        assert_eq!(entry, serde_json::from_str::<UnvalidatedProtoEntry>(&json).unwrap());
+    }
+
+    #[test]
+    fn test_unvalidated_proto_entry_invalid() {
+        let parse = |json: &str| serde_json::from_str::<UnvalidatedProtoEntry>(json);
+
+        assert!(parse("{\"\":[[1,1]]}").is_err());
+        assert!(parse("{\"Link=1\":[[1,1]]}").is_err());
+        assert!(parse("{\"Li nk\":[[1,1]]}").is_err());
+        assert!(parse("{\"Link\":[[1,1]],\"Link\":[[2,2]]}").is_err());
+    }
+}
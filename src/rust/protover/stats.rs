@@ -0,0 +1,238 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Statistics on the protocols supported by a whole network, such as every
+//! relay in a consensus.
+//!
+//! Each relay counts once towards the number of relays supporting each of its
+//! protocol versions, and also contributes its weight (say, its consensus
+//! bandwidth weight), so that we can answer questions like "what fraction of
+//! relays, and of their bandwidth, supports `HSDir=2`?".
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use protoset::Version;
+use protover::ProtocolWeights;
+use protover::ProtoverVote;
+use protover::UnknownProtocol;
+use protover::UnvalidatedProtoEntry;
+use protover::VersionWeight;
+
+/// How many relays support a range of versions of some protocol, and their
+/// total weight.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VersionStats {
+    pub low: Version,
+    pub high: Version,
+    pub relays: u64,
+    pub weight: u64,
+}
+
+/// Protocol-support statistics over a set of relays.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProtocolStats {
+    /// The number of relays counted.
+    pub relays: u64,
+    /// The total weight of the relays counted.
+    pub total_weight: u64,
+    /// For each protocol, the ranges of its versions which some relay
+    /// supports, in ascending order, each as long as possible.
+    pub histograms: BTreeMap<UnknownProtocol, Vec<VersionStats>>,
+}
+
+/// Look up the weight of `version` in the output of `ProtoverVote::weigh()`
+/// for one protocol.
+fn weight_in(ranges: Option<&Vec<VersionWeight>>, version: u64) -> u64 {
+    ranges.and_then(|ranges| {
+        ranges.iter().find(|r| r.low as u64 <= version && version <= r.high as u64)
+    }).map_or(0, |range| range.weight)
+}
+
+impl ProtocolStats {
+    /// Gather statistics over `(protocols, weight)` pairs, one per relay.
+    ///
+    /// As in `ProtoverVote::weigh()`, relays which list too many versions
+    /// are left out entirely.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use protover::stats::ProtocolStats;
+    ///
+    /// let stats = ProtocolStats::from_relays(vec![
+    ///     ("HSDir=1-2 Relay=1-2".parse().unwrap(), 2000),
+    ///     ("HSDir=1 Relay=1-2".parse().unwrap(), 6000),
+    /// ]);
+    /// let hsdir = "HSDir".parse().unwrap();
+    ///
+    /// assert_eq!(0.5, stats.fraction_of_relays(&hsdir, 2));
+    /// assert_eq!(0.25, stats.fraction_of_weight(&hsdir, 2));
+    /// ```
+    pub fn from_relays<I>(relays: I) -> ProtocolStats
+        where I: IntoIterator<Item = (UnvalidatedProtoEntry, u64)>
+    {
+        let weighted: Vec<(UnvalidatedProtoEntry, u64)> = relays.into_iter().collect();
+        let counted: Vec<(UnvalidatedProtoEntry, u64)> =
+            weighted.iter().map(|&(ref protocols, _)| (protocols.clone(), 1)).collect();
+
+        let by_count: ProtocolWeights = ProtoverVote::weigh(&counted);
+        let by_weight: ProtocolWeights = ProtoverVote::weigh(&weighted);
+        let mut stats: ProtocolStats = ProtocolStats {
+            relays: by_count.total,
+            total_weight: by_weight.total,
+            histograms: BTreeMap::new(),
+        };
+
+        // Every relay with some weight is also counted, so the ranges by
+        // count cover those by weight, and we need only split them wherever
+        // either changes.
+        for (protocol, counts) in by_count.weights.iter() {
+            let weights: Option<&Vec<VersionWeight>> = by_weight.weights.get(protocol);
+            let mut edges: Vec<u64> = Vec::new();
+
+            for range in counts.iter().chain(weights.into_iter().flat_map(|w| w.iter())) {
+                edges.push(range.low as u64);
+                edges.push(range.high as u64 + 1);
+            }
+            edges.sort_unstable();
+            edges.dedup();
+
+            let histogram: Vec<VersionStats> = edges.windows(2)
+                .map(|edge| VersionStats {
+                    low: edge[0] as Version,
+                    high: (edge[1] - 1) as Version,
+                    relays: weight_in(Some(counts), edge[0]),
+                    weight: weight_in(weights, edge[0]),
+                })
+                .filter(|row| row.relays > 0)
+                .collect();
+
+            stats.histograms.insert(protocol.clone(), histogram);
+        }
+        stats
+    }
+
+    /// Get the statistics for `version` of `protocol`, as a `VersionStats`
+    /// covering just that version.
+    pub fn coverage(&self, protocol: &UnknownProtocol, version: Version) -> VersionStats {
+        let found: Option<&VersionStats> = self.histograms.get(protocol)
+            .and_then(|rows| rows.iter().find(|r| r.low <= version && version <= r.high));
+
+        VersionStats {
+            low: version,
+            high: version,
+            relays: found.map_or(0, |row| row.relays),
+            weight: found.map_or(0, |row| row.weight),
+        }
+    }
+
+    /// Get the fraction of the relays which support `version` of `protocol`,
+    /// or 0 if there are no relays.
+    pub fn fraction_of_relays(&self, protocol: &UnknownProtocol, version: Version) -> f64 {
+        fraction(self.coverage(protocol, version).relays, self.relays)
+    }
+
+    /// Get the fraction of the total weight of the relays which is that of
+    /// those supporting `version` of `protocol`, or 0 if they have no weight.
+    pub fn fraction_of_weight(&self, protocol: &UnknownProtocol, version: Version) -> f64 {
+        fraction(self.coverage(protocol, version).weight, self.total_weight)
+    }
+}
+
+fn fraction(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    part as f64 / total as f64
+}
+
+/// Write out the statistics as a table, with a row for each range of versions
+/// of each protocol, in order of protocol name and then version.
+impl fmt::Display for ProtocolStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<12} {:>8} {:>8} {:>8} {:>14} {:>8}",
+                 "Protocol", "Versions", "Relays", "%Relays", "Weight", "%Weight")?;
+
+        for (protocol, rows) in self.histograms.iter() {
+            for row in rows.iter() {
+                let versions: String = if row.low == row.high {
+                    row.low.to_string()
+                } else {
+                    format!("{}-{}", row.low, row.high)
+                };
+
+                writeln!(f, "{:<12} {:>8} {:>8} {:>7.2}% {:>14} {:>7.2}%",
+                         protocol.to_string(), versions,
+                         row.relays, 100.0 * fraction(row.relays, self.relays),
+                         row.weight, 100.0 * fraction(row.weight, self.total_weight))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn relays(list: &[(&str, u64)]) -> ProtocolStats {
+        ProtocolStats::from_relays(list.iter().map(|&(protocols, weight)| {
+            (protocols.parse().unwrap(), weight)
+        }))
+    }
+
+    #[test]
+    fn test_histogram_splits_on_count_and_weight() {
+        let stats: ProtocolStats = relays(&[("Link=1-5", 10),
+                                            ("Link=1-4", 0),
+                                            ("Link=3-5", 30)]);
+        let rows: &Vec<VersionStats> = &stats.histograms[&"Link".parse().unwrap()];
+
+        assert_eq!(3, stats.relays);
+        assert_eq!(40, stats.total_weight);
+        assert_eq!(&vec![VersionStats { low: 1, high: 2, relays: 2, weight: 10 },
+                         VersionStats { low: 3, high: 4, relays: 3, weight: 40 },
+                         VersionStats { low: 5, high: 5, relays: 2, weight: 40 }],
+                   rows);
+    }
+
+    #[test]
+    fn test_coverage_fractions() {
+        let stats: ProtocolStats = relays(&[("Relay=1-2 HSDir=1-2", 3000),
+                                            ("Relay=1-2 HSDir=1", 1000),
+                                            ("Relay=1", 1000),
+                                            ("Cons=1", 5000)]);
+        let relay: UnknownProtocol = "Relay".parse().unwrap();
+
+        assert_eq!(0.5, stats.fraction_of_relays(&relay, 2));
+        assert_eq!(0.4, stats.fraction_of_weight(&relay, 2));
+        assert_eq!(0.25, stats.fraction_of_relays(&"HSDir".parse().unwrap(), 2));
+        assert_eq!(0.0, stats.fraction_of_relays(&relay, 3));
+        assert_eq!(0.0, stats.fraction_of_weight(&"Wombat".parse().unwrap(), 1));
+        assert_eq!(VersionStats { low: 1, high: 1, relays: 3, weight: 5000 },
+                   stats.coverage(&relay, 1));
+    }
+
+    #[test]
+    fn test_no_relays() {
+        let stats: ProtocolStats = relays(&[]);
+
+        assert_eq!(0.0, stats.fraction_of_relays(&"Link".parse().unwrap(), 1));
+        assert_eq!(1, stats.to_string().lines().count());
+    }
+
+    #[test]
+    fn test_table() {
+        let stats: ProtocolStats = relays(&[("Link=1-5", 3), ("Link=1-4", 1)]);
+        let table: String = stats.to_string();
+        let lines: Vec<&str> = table.lines().collect();
+
+        assert_eq!(3, lines.len());
+        assert!(lines[0].starts_with("Protocol"));
+        assert_eq!(vec!["Link", "1-4", "2", "100.00%", "4", "100.00%"],
+                   lines[1].split_whitespace().collect::<Vec<&str>>());
+        assert_eq!(vec!["Link", "5", "1", "50.00%", "3", "75.00%"],
+                   lines[2].split_whitespace().collect::<Vec<&str>>());
+    }
+}
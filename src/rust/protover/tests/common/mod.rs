@@ -0,0 +1,18 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Helpers shared by the property tests.
+
+/// A small xorshift generator, so that the property tests are reproducible
+/// and don't need any external crates.
+pub struct XorShift(pub u64);
+
+impl XorShift {
+    /// Get the next number, reduced modulo `n`.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Tests that the FFI functions warn about the input they reject, as their C
+//! equivalents do.
+
+extern crate libc;
+extern crate protover;
+extern crate smartlist;
+extern crate tor_log;
+extern crate tor_log_testing;
+
+use std::ffi::CStr;
+use std::ffi::CString;
+use std::ptr;
+
+use libc::c_char;
+use libc::c_void;
+
+use protover::ffi::protover_all_supported;
+use protover::ffi::protover_check_requirements;
+use protover::ffi::protover_compute_vote;
+use smartlist::Stringlist;
+use tor_log::LogSeverity;
+use tor_log::LD_NET;
+use tor_log_testing::*;
+
+/// tor's allocator, which the FFI uses for the strings it returns.
+#[no_mangle]
+pub unsafe extern "C" fn tor_malloc_(size: usize) -> *mut c_void {
+    libc::malloc(size)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tor_free_(ptr: *mut c_void) {
+    libc::free(ptr)
+}
+
+/// Compute a vote on `votes` with a threshold of 1, freeing the result.
+fn compute_vote(votes: &[&[u8]]) -> String {
+    let votes: Vec<CString> = votes.iter().map(|v| CString::new(*v).unwrap()).collect();
+    let pointers: Vec<*const c_char> = votes.iter().map(|v| v.as_ptr()).collect();
+    let list: Stringlist = Stringlist {
+        list: pointers.as_ptr(),
+        num_used: pointers.len() as i32,
+        capacity: pointers.len() as i32,
+    };
+    let result: *mut c_char = protover_compute_vote(&list, 1);
+    let vote: String = unsafe { CStr::from_ptr(result) }.to_str().unwrap().to_string();
+
+    unsafe { tor_free_(result as *mut c_void) };
+    vote
+}
+
+#[test]
+fn all_supported_warns_about_unparseable_list() {
+    let list: CString = CString::new("Link=1-").unwrap();
+    let mut missing: *mut c_char = ptr::null_mut();
+
+    setup_capture_of_logs(LogSeverity::Info);
+
+    assert_eq!(1, protover_all_supported(list.as_ptr(), &mut missing));
+    assert!(missing.is_null());
+
+    let logs: Vec<SavedLogEntry> = saved_logs();
+    assert_eq!(1, logs.len());
+    assert_eq!(LogSeverity::Warn, logs[0].severity);
+    assert_eq!(LD_NET, logs[0].domain);
+    assert_eq!("protover_all_supported", logs[0].funcname);
+    assert_eq!("Received an unparseable protocol list \"Link=1-\" from the consensus",
+               logs[0].message);
+
+    teardown_capture_of_logs();
+}
+
+#[test]
+fn all_supported_is_quiet_about_parseable_list() {
+    let list: CString = CString::new("Link=1-2 Wombat=9").unwrap();
+    let mut missing: *mut c_char = ptr::null_mut();
+
+    setup_capture_of_logs(LogSeverity::Info);
+
+    assert_eq!(0, protover_all_supported(list.as_ptr(), &mut missing));
+    assert!(!saved_log_has_entry());
+
+    unsafe { tor_free_(missing as *mut c_void) };
+    teardown_capture_of_logs();
+}
+
+#[test]
+fn compute_vote_warns_about_each_unparseable_vote() {
+    setup_capture_of_logs(LogSeverity::Info);
+
+    assert_eq!("Cons=1 Link=1-2", compute_vote(&[b"Link=1-2", b"Cons=1 Link=1-", b"Cons=\xff",
+                                                b"Cons=1"]));
+
+    assert_eq!(2, saved_log_n_entries());
+    assert!(saved_log_has_message("I failed with parsing a protocol list from an authority. \
+                                   The offending string was: \"Cons=1 Link=1-\""));
+    assert!(saved_log_has_message("I failed with parsing a protocol list from an authority. \
+                                   The offending string was: \"Cons=\\xff\""));
+
+    teardown_capture_of_logs();
+}
+
+#[test]
+fn compute_vote_warns_about_vote_with_too_many_protocols() {
+    setup_capture_of_logs(LogSeverity::Info);
+
+    assert_eq!("Cons=1", compute_vote(&[b"Link=1-40000 Relay=1-40000", b"Cons=1"]));
+
+    assert_eq!(1, saved_log_n_entries());
+    assert!(saved_log_has_message_containing("I got too many protocols."));
+    assert!(saved_log_has_message_containing("\"Link=1-40000 Relay=1-40000\""));
+
+    teardown_capture_of_logs();
+}
+
+#[test]
+fn check_requirements_warns_about_unparseable_line_and_ignores_it() {
+    let required: CString = CString::new("Link=1-x").unwrap();
+    let mut warning: *mut c_char = ptr::null_mut();
+
+    setup_capture_of_logs(LogSeverity::Info);
+
+    assert_eq!(0, protover_check_requirements(required.as_ptr(), ptr::null(),
+                                              required.as_ptr(), ptr::null(), 0,
+                                              &mut warning));
+    assert!(warning.is_null());
+
+    // Only the relay line is looked at.
+    assert_eq!(1, saved_log_n_entries());
+    assert!(saved_log_has_message("Received an unparseable protocol list \"Link=1-x\" \
+                                   from the consensus"));
+
+    teardown_capture_of_logs();
+}
+
+#[test]
+fn check_requirements_evaluates_without_warning_out() {
+    let required: CString = CString::new("Link=1,999").unwrap();
+
+    setup_capture_of_logs(LogSeverity::Info);
+
+    assert_eq!(1, protover_check_requirements(required.as_ptr(), ptr::null(), ptr::null(),
+                                              ptr::null(), 1, ptr::null_mut()));
+    assert_eq!(0, protover_check_requirements(ptr::null(), required.as_ptr(), ptr::null(),
+                                              ptr::null(), 1, ptr::null_mut()));
+    assert!(!saved_log_has_entry());
+
+    teardown_capture_of_logs();
+}
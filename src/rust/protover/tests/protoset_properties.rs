@@ -0,0 +1,190 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Property tests for `ProtoSet`: every way of constructing one must give the
+//! same canonical form, whatever its input.  The inputs come from a small
+//! fixed-seed generator, so that failures are reproducible and the tests
+//! need no external crates.
+
+extern crate protover;
+
+mod common;
+
+use protover::errors::ProtoverError;
+use protover::protoset::ProtoSet;
+use protover::protoset::Version;
+
+use common::XorShift;
+
+/// How many random cases to try in each test.
+const CASES: usize = 5000;
+
+/// Get a version, usually a small one, so that ranges often overlap or touch,
+/// but sometimes one near the top of the range.
+fn random_version(rng: &mut XorShift) -> Version {
+    match rng.below(10) {
+        0 => 4294967294 - rng.below(4) as Version,
+        _ => rng.below(24) as Version,
+    }
+}
+
+/// Get a list of up to five `(low, high)` pairs, which may be in any order,
+/// and may overlap or even be backwards.
+fn random_pairs(rng: &mut XorShift) -> Vec<(Version, Version)> {
+    (0..rng.below(6)).map(|_| {
+        let low: Version = random_version(rng);
+
+        match rng.below(8) {
+            0 => (low, low.saturating_sub(1 + rng.below(3) as Version)),
+            _ => (low, low.saturating_add(rng.below(5) as Version).min(4294967294)),
+        }
+    }).collect()
+}
+
+/// Get a list of up to ten versions, in any order, with duplicates.
+fn random_versions(rng: &mut XorShift) -> Vec<Version> {
+    (0..rng.below(11)).map(|_| random_version(rng)).collect()
+}
+
+/// Get a `ProtoSet`, built in one of several ways.
+fn random_protoset(rng: &mut XorShift) -> ProtoSet {
+    loop {
+        let protoset: Result<ProtoSet, ProtoverError> = match rng.below(3) {
+            0 => ProtoSet::from_slice(&random_pairs(rng)),
+            1 => Ok(ProtoSet::from(random_versions(rng))),
+            _ => {
+                let pieces: Vec<String> = random_pairs(rng).iter().map(|&(low, high)| {
+                    if low == high { low.to_string() } else { format!("{}-{}", low, high) }
+                }).collect();
+
+                pieces.join(",").parse()
+            },
+        };
+        if let Ok(protoset) = protoset {
+            return protoset;
+        }
+    }
+}
+
+/// Check that the pairs of `protoset` are sorted, well-formed, don't
+/// overlap, and aren't adjacent, so that each set has only one form.
+fn assert_canonical(protoset: &ProtoSet) {
+    let pairs: Vec<(Version, Version)> = protoset.iter().cloned().collect();
+
+    for &(low, high) in pairs.iter() {
+        assert!(low <= high, "{:?} has a backwards pair", pairs);
+        assert!(high < u32::max_value(), "{:?} includes u32::MAX", pairs);
+    }
+    for window in pairs.windows(2) {
+        assert!(window[0].1 as u64 + 1 < window[1].0 as u64,
+                "{:?} isn't sorted and disjoint, with gaps", pairs);
+    }
+}
+
+/// Expand a list of pairs, of which some may be backwards, by brute force.
+fn expand_pairs(pairs: &[(Version, Version)]) -> Vec<Version> {
+    let mut versions: Vec<Version> = Vec::new();
+
+    for &(low, high) in pairs.iter() {
+        versions.extend((low as u64..high as u64 + 1).map(|v| v as Version));
+    }
+    versions
+}
+
+#[test]
+fn protoset_constructors_are_canonical() {
+    let mut rng: XorShift = XorShift(0x9e37_79b9_7f4a_7c15);
+
+    for _ in 0..CASES {
+        let a: ProtoSet = random_protoset(&mut rng);
+        let b: ProtoSet = random_protoset(&mut rng);
+        let mut retained: ProtoSet = a.clone();
+        let modulus: Version = rng.below(3) as Version + 2;
+
+        retained.retain(|v| v % modulus != 0);
+
+        assert_canonical(&a);
+        assert_canonical(&a.union(&b));
+        assert_canonical(&a.intersection(&b));
+        assert_canonical(&a.difference(&b));
+        assert_canonical(&a.symmetric_difference(&b));
+        assert_canonical(&retained);
+    }
+}
+
+#[test]
+fn protoset_from_slice_accepts_exactly_disjoint_pairs() {
+    let mut rng: XorShift = XorShift(0x2545_f491_4f6c_dd1d);
+
+    for _ in 0..CASES {
+        let mut pairs: Vec<(Version, Version)> = random_pairs(&mut rng);
+        let result: Result<ProtoSet, ProtoverError> = ProtoSet::from_slice(&pairs);
+
+        pairs.sort();
+        pairs.dedup();
+
+        let mut expanded: Vec<Version> = expand_pairs(&pairs);
+        let total: usize = expanded.len();
+
+        expanded.sort();
+        expanded.dedup();
+
+        let backwards: bool = pairs.iter().any(|&(low, high)| low > high);
+        let overlapping: bool = expanded.len() < total;
+
+        match result {
+            Ok(ref protoset) => {
+                assert!(!backwards && !overlapping, "{:?} was accepted", pairs);
+                assert_eq!(expanded, protoset.clone().expand());
+            },
+            Err(_) => assert!(backwards || overlapping, "{:?} was rejected", pairs),
+        }
+    }
+}
+
+#[test]
+fn protoset_from_vec_and_into_vec_are_inverses() {
+    let mut rng: XorShift = XorShift(0xd1b5_4a32_d192_ed03);
+
+    for _ in 0..CASES {
+        let mut versions: Vec<Version> = random_versions(&mut rng);
+        let protoset: ProtoSet = ProtoSet::from(versions.clone());
+
+        versions.sort();
+        versions.dedup();
+
+        let expanded: Vec<Version> = protoset.clone().into();
+
+        assert_eq!(versions, expanded);
+
+        let protoset: ProtoSet = random_protoset(&mut rng);
+        let expanded: Vec<Version> = protoset.clone().into();
+
+        assert_eq!(protoset, ProtoSet::from(expanded));
+    }
+}
+
+#[test]
+fn protoset_len_is_expanded_length() {
+    let mut rng: XorShift = XorShift(0xbf58_476d_1ce4_e5b9);
+
+    for _ in 0..CASES {
+        let protoset: ProtoSet = random_protoset(&mut rng);
+        let pairs: Vec<(Version, Version)> = protoset.iter().cloned().collect();
+
+        assert_eq!(expand_pairs(&pairs).len(), protoset.len());
+        assert_eq!(protoset.len(), protoset.clone().expand().len());
+        assert_eq!(protoset.len() == 0, protoset.is_empty());
+    }
+}
+
+#[test]
+fn protoset_string_round_trip() {
+    let mut rng: XorShift = XorShift(0x94d0_49bb_1331_11eb);
+
+    for _ in 0..CASES {
+        let protoset: ProtoSet = random_protoset(&mut rng);
+
+        assert_eq!(Ok(protoset.clone()), protoset.to_string().parse::<ProtoSet>());
+    }
+}
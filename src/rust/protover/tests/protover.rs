@@ -7,6 +7,7 @@ use protover::ProtoEntry;
 use protover::ProtoverVote;
 use protover::UnvalidatedProtoEntry;
 use protover::errors::ProtoverError;
+use protover::protoset::ParseMode;
 
 #[test]
 fn parse_protocol_with_single_proto_and_single_version() {
@@ -383,12 +384,116 @@ fn protover_all_supported_should_return_empty_string_for_weird_thing() {
 fn protover_unvalidatedprotoentry_should_err_entirely_unparseable_things() {
     let proto: Result<UnvalidatedProtoEntry, ProtoverError> = "Fribble".parse();
 
-    assert_eq!(Err(ProtoverError::Unparseable), proto);
+    assert_eq!(Err(ProtoverError::Unparseable { at: 0, token: "Fribble".to_string() }), proto);
 }
 
 #[test]
 fn protover_all_supported_over_maximum_limit() {
     let proto: Result<UnvalidatedProtoEntry, ProtoverError> = "Sleen=0-4294967295".parse();
 
-    assert_eq!(Err(ProtoverError::ExceedsMax), proto);
+    assert_eq!(Err(ProtoverError::InProtocol { protocol: "Sleen".to_string(),
+                                               error: Box::new(ProtoverError::ExceedsMax) }),
+               proto);
+}
+
+#[test]
+fn protover_all_supported_with_known_protocol_and_huge_range() {
+    let proto: UnvalidatedProtoEntry = "Link=1-4294967294".parse().unwrap();
+    let result: String = proto.all_supported().unwrap().to_string();
+
+    assert_eq!(result, "Link=6-4294967294".to_string());
+}
+
+#[test]
+fn protover_compute_vote_with_wide_ranges() {
+    let protocols: &[UnvalidatedProtoEntry] = &[
+        "Link=1-65000".parse().unwrap(),
+        "Link=3-64999".parse().unwrap(),
+        "Link=2-5,7".parse().unwrap(),
+    ];
+
+    assert_eq!("Link=2-64999", ProtoverVote::compute(protocols, &2).to_string());
+    assert_eq!("Link=3-5,7", ProtoverVote::compute(protocols, &3).to_string());
+    assert_eq!("", ProtoverVote::compute(protocols, &4).to_string());
+}
+
+// Like the C implementation, votes which would expand to more than
+// MAX_PROTOCOLS_TO_EXPAND versions are ignored entirely.
+#[test]
+fn protover_compute_vote_ignores_votes_exceeding_limit() {
+    let protocols: &[UnvalidatedProtoEntry] = &[
+        "Link=1-4294967294".parse().unwrap(),
+        "Link=2-5,7".parse().unwrap(),
+    ];
+
+    assert_eq!("Link=2-5,7", ProtoverVote::compute(protocols, &1).to_string());
+    assert_eq!("", ProtoverVote::compute(protocols, &2).to_string());
+}
+
+#[test]
+fn protover_unvalidatedprotoentry_error_names_protocol_and_offset() {
+    let proto: Result<UnvalidatedProtoEntry, ProtoverError> = "Cons=1-2 Link=1,2-x,5".parse();
+    let expected = ProtoverError::Unparseable { at: 16, token: "2-x".to_string() };
+
+    assert_eq!(Err(ProtoverError::InProtocol { protocol: "Link".to_string(),
+                                               error: Box::new(expected) }),
+               proto);
+}
+
+#[test]
+fn protover_protoentry_error_names_unknown_protocol() {
+    let proto: Result<ProtoEntry, ProtoverError> = "Cons=1 Ducks=5-7".parse();
+
+    assert_eq!(Err(ProtoverError::InProtocol { protocol: "Ducks".to_string(),
+                                               error: Box::new(ProtoverError::UnknownProtocol) }),
+               proto);
+}
+
+#[test]
+fn protover_unvalidatedprotoentry_error_for_entry_without_equals() {
+    let proto: Result<UnvalidatedProtoEntry, ProtoverError> = "Cons=1 Link Relay=2".parse();
+
+    assert_eq!(Err(ProtoverError::Unparseable { at: 7, token: "Link".to_string() }), proto);
+}
+
+#[test]
+fn protover_strict_parse_accepts_supported_protocols() {
+    let supported: String = protover::get_supported_protocols();
+
+    assert!(ProtoEntry::parse_with(&supported, ParseMode::Strict).is_ok());
+    assert!(UnvalidatedProtoEntry::parse_with(&supported, ParseMode::Strict).is_ok());
+}
+
+#[test]
+fn protover_strict_parse_rejects_duplicate_protocols() {
+    let proto: Result<UnvalidatedProtoEntry, ProtoverError> =
+        UnvalidatedProtoEntry::parse_with("Link=1 Wombat=2 Wombat=3", ParseMode::Strict);
+
+    assert_eq!(Err(ProtoverError::DuplicateProtocol.in_protocol("Wombat")), proto);
+}
+
+#[test]
+fn protover_strict_parse_rejects_unordered_protocols() {
+    let proto: Result<UnvalidatedProtoEntry, ProtoverError> =
+        UnvalidatedProtoEntry::parse_with("Wombat=2 Link=1", ParseMode::Strict);
+
+    assert_eq!(Err(ProtoverError::Unordered.in_protocol("Link")), proto);
+}
+
+#[test]
+fn protover_strict_parse_reports_offset_of_bad_versions() {
+    let proto: Result<ProtoEntry, ProtoverError> =
+        ProtoEntry::parse_with("Cons=1-2 Link=1,,3", ParseMode::Strict);
+    let expected = ProtoverError::Unparseable { at: 16, token: "".to_string() };
+
+    assert_eq!(Err(expected.in_protocol("Link")), proto);
+}
+
+#[test]
+fn protover_lenient_parse_keeps_last_duplicate_protocol() {
+    let proto: UnvalidatedProtoEntry =
+        UnvalidatedProtoEntry::parse_with("Wombat=2 Link=1 Wombat=3", ParseMode::Lenient).unwrap();
+
+    assert_eq!("Link=1 Wombat=3", proto.to_string());
+    assert_eq!(Ok(proto), "Wombat=2 Link=1 Wombat=3".parse());
 }
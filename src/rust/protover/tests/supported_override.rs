@@ -0,0 +1,49 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+// The supported protocols override is process-wide, so it's tested in its own
+// test binary, lest it change what the other tests see as supported.
+
+extern crate protover;
+
+use protover::errors::ProtoverError;
+use protover::*;
+
+#[test]
+fn supported_protocols_override() {
+    let compiled_in: &str = get_compiled_in_protocols();
+
+    assert_eq!(compiled_in, get_supported_protocols());
+    assert!(is_supported_here(&Protocol::Link, &5));
+
+    // Disabling some versions is reflected everywhere.
+    assert_eq!(Ok(()), set_supported_protocols_override(Some("Link=1-4 HSIntro=3 Cons=1-2")));
+    assert_eq!("Cons=1-2 HSIntro=3 Link=1-4", get_supported_protocols());
+    assert!(!is_supported_here(&Protocol::Link, &5));
+    assert!(is_supported_here(&Protocol::Link, &4));
+    assert!(!is_supported_here(&Protocol::HSIntro, &4));
+    assert!(!is_supported_here(&Protocol::Relay, &1));
+    assert_eq!(None, ProtoEntry::supported().unwrap().get(&Protocol::Relay));
+
+    let unsupported: UnvalidatedProtoEntry = "Link=5".parse().unwrap();
+    assert_eq!("Link=5", unsupported.all_supported().unwrap().to_string());
+
+    // Versions we weren't compiled to support can't be enabled, and a failed
+    // attempt leaves the override as it was.
+    assert_eq!(Err(ProtoverError::NotSupportedHere.in_protocol("Link")),
+               set_supported_protocols_override(Some("Link=1-6")));
+    assert_eq!(Err(ProtoverError::NotSupportedHere.in_protocol("Padding")),
+               set_supported_protocols_override(Some("Padding=1")));
+    assert!(set_supported_protocols_override(Some("Link=1-x")).is_err());
+    assert_eq!("Cons=1-2 HSIntro=3 Link=1-4", get_supported_protocols());
+
+    // We may support nothing at all.
+    assert_eq!(Ok(()), set_supported_protocols_override(Some("")));
+    assert_eq!("", get_supported_protocols());
+    assert!(!is_supported_here(&Protocol::Link, &1));
+
+    // Removing the override restores the compiled-in protocols.
+    assert_eq!(Ok(()), set_supported_protocols_override(None));
+    assert_eq!(compiled_in, get_supported_protocols());
+    assert!(is_supported_here(&Protocol::Link, &5));
+}
@@ -0,0 +1,116 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Differential tests for `ProtoverVote`: sweeping over range endpoints must
+//! agree with counting every version individually.
+
+extern crate protover;
+
+mod common;
+
+use std::collections::HashMap;
+
+use protover::ProtoverVote;
+use protover::UnknownProtocol;
+use protover::UnvalidatedProtoEntry;
+use protover::protoset::ProtoSet;
+use protover::protoset::Version;
+
+use common::XorShift;
+
+/// The largest vote which `ProtoverVote::compute` will expand, as in
+/// protover.rs.
+const MAX_PROTOCOLS_TO_EXPAND: usize = 1 << 16;
+
+/// The vote-counting implementation which `ProtoverVote::compute` used
+/// before it swept over range endpoints: expand every vote, and count
+/// each version individually.
+fn compute_by_expansion(proto_entries: &[UnvalidatedProtoEntry], threshold: usize)
+    -> UnvalidatedProtoEntry
+{
+    let mut all_count: HashMap<UnknownProtocol, HashMap<Version, usize>> = HashMap::new();
+    let mut final_output: UnvalidatedProtoEntry = UnvalidatedProtoEntry::default();
+
+    for vote in proto_entries {
+        if vote.len() > MAX_PROTOCOLS_TO_EXPAND {
+            continue;
+        }
+        for (protocol, versions) in vote.iter() {
+            let supported_vers: &mut HashMap<Version, usize> =
+                all_count.entry(protocol.clone()).or_insert(HashMap::new());
+
+            for version in versions.clone().expand() {
+                *supported_vers.entry(version).or_insert(0) += 1;
+            }
+        }
+    }
+    for (protocol, mut versions) in all_count {
+        versions.retain(|_, count| *count >= threshold);
+
+        if versions.len() > 0 {
+            let voted_versions: Vec<Version> = versions.keys().cloned().collect();
+
+            final_output.insert(protocol, ProtoSet::from(voted_versions));
+        }
+    }
+    final_output
+}
+
+/// Get a random vote on a few protocols, with overlapping ranges.
+fn random_vote(rng: &mut XorShift) -> UnvalidatedProtoEntry {
+    let names: [&str; 4] = ["Cons", "Link", "LinkAuth", "Wombat"];
+    let mut vote: UnvalidatedProtoEntry = UnvalidatedProtoEntry::default();
+
+    for name in names.iter() {
+        if rng.below(3) == 0 {
+            continue;
+        }
+        let mut versions: Vec<Version> = Vec::new();
+
+        for _ in 0..rng.below(4) {
+            let low: Version = rng.below(20) as Version;
+            let high: Version = low + rng.below(6) as Version;
+
+            versions.extend(low..high + 1);
+        }
+        vote.insert(name.parse::<UnknownProtocol>().unwrap(), ProtoSet::from(versions));
+    }
+    vote
+}
+
+#[test]
+fn compute_vote_matches_expansion() {
+    let mut rng: XorShift = XorShift(0x5eed_70f0_7e57_c0de);
+
+    for _ in 0..2000 {
+        let votes: Vec<UnvalidatedProtoEntry> =
+            (0..rng.below(7)).map(|_| random_vote(&mut rng)).collect();
+        let threshold: usize = rng.below(8) as usize;
+
+        let swept: UnvalidatedProtoEntry = ProtoverVote::compute(&votes, &threshold);
+        let expanded: UnvalidatedProtoEntry = compute_by_expansion(&votes, threshold);
+
+        assert_eq!(swept.to_string(), expanded.to_string(),
+                   "votes {:?} with threshold {}",
+                   votes.iter().map(|v| v.to_string()).collect::<Vec<String>>(),
+                   threshold);
+    }
+}
+
+#[test]
+fn compute_weighted_equal_weights_matches_compute() {
+    let mut rng: XorShift = XorShift(0x3e16_4700_dead_beef);
+
+    for _ in 0..1000 {
+        let votes: Vec<UnvalidatedProtoEntry> =
+            (0..rng.below(6) + 1).map(|_| random_vote(&mut rng)).collect();
+        let threshold: usize = rng.below(votes.len() as u64) as usize + 1;
+        let weighted: Vec<(UnvalidatedProtoEntry, u64)> =
+            votes.iter().map(|vote| (vote.clone(), 5)).collect();
+        // Halfway between counts, so that rounding can't matter.
+        let fraction: f64 = (threshold as f64 - 0.5) / votes.len() as f64;
+
+        assert_eq!(ProtoverVote::compute(&votes, &threshold).to_string(),
+                   ProtoverVote::compute_weighted(&weighted, fraction).to_string());
+    }
+}
@@ -0,0 +1,199 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Advertising the channel types and transports which a relay speaks.
+//!
+//! Besides the usual TLS channels, relays in this fork may be configured
+//! (with the `ChannelType` option) to use DUAL, PCTCP or IMUX channels, and
+//! (with `UsingQuic`) to carry their connections over QUIC sockets.  Which of
+//! these a relay speaks depends upon how it is configured rather than upon
+//! its code, so they are not part of the static supported protocols string.
+//! Instead, they are advertised as the `ChanType` and `Quic` subprotocols,
+//! computed here from the running configuration.
+//!
+//! Each `ChanType` version is numbered as the corresponding C
+//! `channel_type_t`.  The `ChannelType` option only chooses which kind of
+//! channel a relay opens: it accepts channels of every type we know, so it
+//! advertises all of them.  A relay which doesn't advertise any `ChanType` at
+//! all (such as one running a stock tor) speaks only TLS.
+
+use canonical::canonicalize;
+use protoset::ProtoSet;
+use protoset::Version;
+use protover::get_supported_protocols;
+use protover::Protocol;
+use protover::ProtoEntry;
+use protover::UnvalidatedProtoEntry;
+
+pub use tor_options::ChannelType;
+
+/// The version of the `Quic` subprotocol which we speak when `UsingQuic` is
+/// set.
+const QUIC_VERSION: Version = 1;
+
+/// Get the version of the `ChanType` subprotocol which signifies
+/// `channel_type`: the number of the corresponding C `channel_type_t`.
+fn chan_type_version(channel_type: ChannelType) -> Version {
+    channel_type as Version
+}
+
+/// The versions of the `ChanType` subprotocol which we accept channels of:
+/// all of them, from `ChannelType::Tls` to `ChannelType::Imux`.
+fn accepted_chan_types() -> ProtoSet {
+    let low: Version = chan_type_version(ChannelType::Tls);
+    let high: Version = chan_type_version(ChannelType::Imux);
+
+    // The pair is in order, so this can't fail.
+    ProtoSet::from_slice(&[(low, high)]).unwrap_or_default()
+}
+
+/// The parts of a relay's configuration which determine the transports it
+/// speaks.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TransportConfig {
+    /// The `ChannelType` option, or `None` if it isn't one we recognise.
+    pub channel_type: Option<ChannelType>,
+    /// The `UsingQuic` option.
+    pub using_quic: bool,
+}
+
+/// Compute the transport subprotocols which a relay with the given
+/// configuration should advertise.
+///
+/// # Examples
+///
+/// ```
+/// use protover::transport::*;
+///
+/// let config = TransportConfig { channel_type: Some(ChannelType::Imux),
+///                                using_quic: true };
+/// let entry = transport_protocols(&config);
+///
+/// assert_eq!(Some(&"1-4".parse().unwrap()), entry.get(&protover::Protocol::ChanType));
+/// assert_eq!(Some(&"1".parse().unwrap()), entry.get(&protover::Protocol::Quic));
+/// ```
+pub fn transport_protocols(config: &TransportConfig) -> ProtoEntry {
+    let mut entry: ProtoEntry = ProtoEntry::default();
+
+    if config.channel_type.is_some() {
+        entry.insert(Protocol::ChanType, accepted_chan_types());
+    }
+    if config.using_quic {
+        entry.insert(Protocol::Quic, ProtoSet::from(vec![QUIC_VERSION]));
+    }
+    entry
+}
+
+/// Get the protocols which a relay with the given configuration supports: the
+/// static ones from `get_supported_protocols()`, together with those from
+/// `transport_protocols()`, in canonical form.
+///
+/// # Examples
+///
+/// ```
+/// use protover::transport::*;
+///
+/// let config = TransportConfig { channel_type: Some(ChannelType::Tls),
+///                                using_quic: false };
+/// let supported = supported_protocols_for(&config);
+///
+/// assert!(supported.starts_with("ChanType=1-4 Cons=1-2 "));
+/// assert!(!supported.contains("Quic"));
+/// ```
+pub fn supported_protocols_for(config: &TransportConfig) -> String {
+    let supported: String = get_supported_protocols();
+    let transports: String = transport_protocols(config).to_string();
+
+    if transports.is_empty() {
+        return supported;
+    }
+
+    // Both halves are under our control, so this can't fail.
+    canonicalize(&format!("{} {}", supported, transports)).unwrap_or(supported)
+}
+
+/// Determine if a relay which advertises `protocols` speaks the given
+/// `channel_type`.
+///
+/// A relay which doesn't list the `ChanType` subprotocol at all is assumed to
+/// speak only TLS.  If `protocols` is unparseable, we assume nothing.
+///
+/// # Examples
+///
+/// ```
+/// use protover::transport::*;
+///
+/// assert!(supports_channel_type("ChanType=4 Link=1-5", ChannelType::Imux));
+/// assert!(!supports_channel_type("ChanType=4 Link=1-5", ChannelType::Tls));
+/// assert!(supports_channel_type("Link=1-5", ChannelType::Tls));
+/// assert!(!supports_channel_type("Link=1-5", ChannelType::Imux));
+/// ```
+pub fn supports_channel_type(protocols: &str, channel_type: ChannelType) -> bool {
+    let entry: UnvalidatedProtoEntry = match protocols.parse() {
+        Ok(n) => n,
+        Err(_) => return false,
+    };
+
+    match entry.get(&Protocol::ChanType.into()) {
+        Some(versions) => versions.contains(&chan_type_version(channel_type)),
+        None => channel_type == ChannelType::Tls,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_chan_type_version() {
+        assert_eq!(1, chan_type_version(ChannelType::Tls));
+        assert_eq!(4, chan_type_version(ChannelType::Imux));
+    }
+
+    #[test]
+    fn test_transport_protocols_advertises_every_channel_type() {
+        let config = TransportConfig { channel_type: Some(ChannelType::Dual),
+                                       using_quic: false };
+        let supported: String = supported_protocols_for(&config);
+
+        for channel_type in &[ChannelType::Tls, ChannelType::Dual,
+                              ChannelType::Pctcp, ChannelType::Imux] {
+            assert!(supports_channel_type(&supported, *channel_type));
+        }
+    }
+
+    #[test]
+    fn test_transport_protocols_unknown_channel_type() {
+        let config = TransportConfig { channel_type: None, using_quic: false };
+
+        assert!(transport_protocols(&config).is_empty());
+        assert_eq!(get_supported_protocols(), supported_protocols_for(&config));
+    }
+
+    #[test]
+    fn test_supported_protocols_for_is_canonical() {
+        let config = TransportConfig { channel_type: Some(ChannelType::Pctcp),
+                                       using_quic: true };
+        let supported: String = supported_protocols_for(&config);
+
+        assert_eq!(Ok(true), ::canonical::is_canonical(&supported));
+        assert!(supported.starts_with("ChanType=1-4 Cons=1-2 "));
+        assert!(supported.contains(" Microdesc=1-2 Quic=1 Relay=1-2"));
+    }
+
+    #[test]
+    fn test_supports_channel_type_lists_several() {
+        let protocols: &str = "ChanType=1-2,4 Link=1-5";
+
+        assert!(supports_channel_type(protocols, ChannelType::Tls));
+        assert!(supports_channel_type(protocols, ChannelType::Dual));
+        assert!(!supports_channel_type(protocols, ChannelType::Pctcp));
+        assert!(supports_channel_type(protocols, ChannelType::Imux));
+    }
+
+    #[test]
+    fn test_supports_channel_type_unparseable() {
+        assert!(!supports_channel_type("ChanType=x", ChannelType::Tls));
+        assert!(!supports_channel_type("Link=1-x", ChannelType::Tls));
+    }
+}
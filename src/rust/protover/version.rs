@@ -0,0 +1,606 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Parsing and comparing Tor version numbers, as found in the `platform`
+//! lines of router descriptors.
+//!
+//! This is a port of `tor_version_parse()`, `tor_version_parse_platform()`,
+//! `tor_version_compare()` and `tor_version_as_new_as()` from
+//! `src/or/routerparse.c`, and aims to give the same answers for all inputs,
+//! quirks included.  See `version-spec.txt` for the version format.
+
+use std::cmp;
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use errors::ProtoverError;
+
+/// The maximum length of a status tag, plus one for C's NUL byte.
+///
+/// C_RUST_COUPLED: src/or/or.h `MAX_STATUS_TAG_LEN`
+const MAX_STATUS_TAG_LEN: usize = 32;
+
+/// The maximum length of a version within a platform string, plus one for C's
+/// NUL byte.
+///
+/// C_RUST_COUPLED: src/or/routerparse.c `tor_version_parse_platform`
+const MAX_PLATFORM_VERSION_LEN: usize = 128;
+
+/// The maximum length of a git tag, in bytes.
+///
+/// C_RUST_COUPLED: src/common/crypto.h `DIGEST_LEN`
+const MAX_GIT_TAG_LEN: usize = 20;
+
+/// The release status of a Tor version, in ascending order.
+///
+/// C_RUST_COUPLED: src/or/or.h `tor_version_t`
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum VersionStatus {
+    /// A pre-release, as in the old `0.1.2pre3` format.
+    Pre,
+    /// A release candidate, as in the old `0.1.2rc3` format.
+    Rc,
+    /// A release, which all versions in the post-0.1 format are.
+    Release,
+}
+
+/// A Tor version, as in `0.2.9.1-alpha (git-71ef3d7ed64eb4d5)`.
+///
+/// C_RUST_COUPLED: src/or/or.h `tor_version_t`
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct TorVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub micro: u32,
+    pub status: VersionStatus,
+    pub patchlevel: u32,
+    /// The status tag, as in `alpha` or `rc-dev`, or `""` if there isn't one.
+    /// Like C, we keep at most `MAX_STATUS_TAG_LEN - 1` bytes of it.
+    pub status_tag: String,
+    /// The subversion revision, as in `(r1234)`, or 0 if there isn't one.
+    pub svn_revision: i32,
+    /// The decoded git tag, as in `(git-71ef3d7ed64eb4d5)`, or empty if there
+    /// isn't one.
+    pub git_tag: Vec<u8>,
+}
+
+impl Default for TorVersion {
+    fn default() -> TorVersion {
+        TorVersion {
+            major: 0,
+            minor: 0,
+            micro: 0,
+            status: VersionStatus::Release,
+            patchlevel: 0,
+            status_tag: String::new(),
+            svn_revision: 0,
+            git_tag: Vec::new(),
+        }
+    }
+}
+
+/// Is `b` one of the bytes which C's `find_whitespace()` stops at?
+fn is_c_whitespace_or_end(b: u8) -> bool {
+    match b {
+        b'\0' | b'#' | b' ' | b'\r' | b'\n' | b'\t' => true,
+        _ => false,
+    }
+}
+
+/// Port of C's `find_whitespace()`: the index of the first whitespace (or
+/// comment) in `s`, starting from `at`.
+fn find_whitespace(s: &[u8], at: usize) -> usize {
+    s[at..].iter().position(|b| is_c_whitespace_or_end(*b)).map_or(s.len(), |i| at + i)
+}
+
+/// Port of C's `eat_whitespace()`: the index of the first byte of `s`,
+/// starting from `at`, which isn't whitespace or within a comment.
+fn eat_whitespace(s: &[u8], at: usize) -> usize {
+    let mut i: usize = at;
+
+    while i < s.len() {
+        match s[i] {
+            b' ' | b'\t' | b'\n' | b'\r' => i += 1,
+            b'#' => {
+                i += 1;
+                while i < s.len() && s[i] != b'\n' {
+                    i += 1;
+                }
+            },
+            _ => break,
+        }
+    }
+    i
+}
+
+/// Port of C's `strtol()` for the svn revision, as converted to an `int` on
+/// the LP64 platforms tor usually runs on: any leading whitespace and a sign
+/// are skipped, overflows saturate to a `long`, and then are truncated.
+fn strtol_as_int(s: &[u8]) -> i32 {
+    let mut i: usize = 0;
+    let mut negative: bool = false;
+    let mut value: i64 = 0;
+
+    while i < s.len() && (s[i] == b' ' || (s[i] >= b'\t' && s[i] <= b'\r')) {
+        i += 1;
+    }
+    if i < s.len() && (s[i] == b'-' || s[i] == b'+') {
+        negative = s[i] == b'-';
+        i += 1;
+    }
+    while i < s.len() && s[i].is_ascii_digit() {
+        let digit: i64 = (s[i] - b'0') as i64;
+
+        value = if negative {
+            value.saturating_mul(10).saturating_sub(digit)
+        } else {
+            value.saturating_mul(10).saturating_add(digit)
+        };
+        i += 1;
+    }
+    value as i32
+}
+
+/// Parse the decimal number at the start of `s[at..]`, which must be no
+/// greater than `i32::MAX`, returning it and the index just after it.
+fn parse_number(s: &[u8], at: usize) -> Result<(u32, usize), ProtoverError> {
+    let end: usize = s[at..].iter().position(|b| !b.is_ascii_digit()).map_or(s.len(), |i| at + i);
+    let unparseable = || ProtoverError::Unparseable {
+        at,
+        token: String::from_utf8_lossy(&s[at..find_whitespace(s, at)]).into_owned(),
+    };
+
+    if end == at {
+        return Err(unparseable());
+    }
+
+    let mut value: u64 = 0;
+
+    for digit in &s[at..end] {
+        value = value * 10 + (digit - b'0') as u64;
+        if value > i32::MAX as u64 {
+            return Err(unparseable());
+        }
+    }
+    Ok((value as u32, end))
+}
+
+/// Decode a string of hex digits, or return `None` if it isn't one.
+fn decode_hex(s: &[u8]) -> Option<Vec<u8>> {
+    fn nibble(b: u8) -> Option<u8> {
+        match b {
+            b'0'..=b'9' => Some(b - b'0'),
+            b'a'..=b'f' => Some(b - b'a' + 10),
+            b'A'..=b'F' => Some(b - b'A' + 10),
+            _ => None,
+        }
+    }
+
+    s.chunks(2).map(|pair| Some(nibble(pair[0])? << 4 | nibble(pair[1])?)).collect()
+}
+
+impl TorVersion {
+    /// Parse a Tor version, as C's `tor_version_parse()` would: an optional
+    /// `"Tor "`, then `NUM.NUM[.NUM[(pre|rc|.)NUM]][-tag]`, optionally
+    /// followed by an svn revision or a git tag.  Any text after that is
+    /// ignored.
+    fn parse_bytes(s: &[u8]) -> Result<TorVersion, ProtoverError> {
+        let mut out: TorVersion = TorVersion::default();
+        let mut cp: usize = 0;
+
+        // Like C, stop at the first NUL byte.
+        let s: &[u8] = match s.iter().position(|b| *b == b'\0') {
+            Some(nul) => &s[..nul],
+            None => s,
+        };
+        let at = |i: usize| s.get(i).cloned().unwrap_or(b'\0');
+        let unparseable = |i: usize| ProtoverError::Unparseable {
+            at: i,
+            token: String::from_utf8_lossy(&s[i..find_whitespace(s, i)]).into_owned(),
+        };
+
+        if s.len() >= 4 && s[..4].eq_ignore_ascii_case(b"Tor ") {
+            cp = 4;
+        }
+
+        let (major, next) = parse_number(s, cp)?;
+        out.major = major;
+        cp = next;
+        if at(cp) != b'.' {
+            return Err(unparseable(cp));
+        }
+        let (minor, next) = parse_number(s, cp + 1)?;
+        out.minor = minor;
+        cp = next;
+
+        let mut has_patchlevel: bool = false;
+
+        match at(cp) {
+            b'\0' => return Ok(out),
+            b'-' => (),
+            b'.' => {
+                let (micro, next) = parse_number(s, cp + 1)?;
+                out.micro = micro;
+                cp = next;
+
+                if at(cp) == b'\0' {
+                    return Ok(out);
+                } else if at(cp) == b'.' {
+                    cp += 1;
+                    has_patchlevel = true;
+                } else if at(cp) == b'-' {
+                    // A status tag follows.
+                } else if s[cp..].starts_with(b"pre") {
+                    out.status = VersionStatus::Pre;
+                    cp += 3;
+                    has_patchlevel = true;
+                } else if s[cp..].starts_with(b"rc") {
+                    out.status = VersionStatus::Rc;
+                    cp += 2;
+                    has_patchlevel = true;
+                } else {
+                    return Err(unparseable(cp));
+                }
+            },
+            _ => return Err(unparseable(cp)),
+        }
+
+        if has_patchlevel {
+            let (patchlevel, next) = parse_number(s, cp)?;
+            out.patchlevel = patchlevel;
+            cp = next;
+        }
+
+        // The status tag.
+        if at(cp) == b'-' || at(cp) == b'.' {
+            cp += 1;
+        }
+        let eos: usize = find_whitespace(s, cp);
+
+        // C copies the whole rest of the string when the tag is too long,
+        // truncated to fit.
+        let tag: &[u8] = if eos - cp >= MAX_STATUS_TAG_LEN {
+            &s[cp..cmp::min(s.len(), cp + MAX_STATUS_TAG_LEN - 1)]
+        } else {
+            &s[cp..eos]
+        };
+        out.status_tag = String::from_utf8_lossy(tag).into_owned();
+        cp = eat_whitespace(s, eos);
+
+        if s[cp..].starts_with(b"(r") {
+            out.svn_revision = strtol_as_int(&s[cp + 2..]);
+        } else if s[cp..].starts_with(b"(git-") {
+            let close_paren: usize = match s[cp..].iter().position(|b| *b == b')') {
+                Some(i) => cp + i,
+                None => return Err(unparseable(cp)),
+            };
+            let hex: &[u8] = &s[cp + 5..close_paren];
+
+            if hex.is_empty() || hex.len() % 2 == 1 || hex.len() > MAX_GIT_TAG_LEN * 2 {
+                return Err(unparseable(cp));
+            }
+            out.git_tag = match decode_hex(hex) {
+                Some(tag) => tag,
+                None => return Err(unparseable(cp)),
+            };
+        }
+
+        Ok(out)
+    }
+
+    /// Extract the Tor version from the `platform` line of a router
+    /// descriptor, as C's `tor_version_parse_platform()` would (when not
+    /// `strict`).
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is `None` if the platform isn't some
+    /// version of Tor, and whose `Err` value is a `ProtoverError` if it is but
+    /// the version can't be parsed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use protover::version::TorVersion;
+    ///
+    /// let version = TorVersion::from_platform("Tor 0.2.9.1-alpha on Linux").unwrap().unwrap();
+    /// assert_eq!("0.2.9.1-alpha", version.to_string());
+    ///
+    /// assert_eq!(Ok(None), TorVersion::from_platform("Arti 0.1.0 on Linux"));
+    /// assert!(TorVersion::from_platform("Tor 0.2.x on Linux").is_err());
+    /// ```
+    pub fn from_platform(platform: &str) -> Result<Option<TorVersion>, ProtoverError> {
+        let s: &[u8] = platform.as_bytes();
+
+        if !s.starts_with(b"Tor ") {
+            return Ok(None);
+        }
+
+        let start: usize = eat_whitespace(s, 3);
+        if start >= s.len() || s[start] == b'\0' {
+            return Err(ProtoverError::Unparseable { at: start, token: String::new() });
+        }
+
+        let mut end: usize = find_whitespace(s, start);
+        let next: usize = eat_whitespace(s, end);
+        if s[next..].starts_with(b"(r") || s[next..].starts_with(b"(git-") {
+            end = find_whitespace(s, next);
+        }
+
+        if end - start + 1 >= MAX_PLATFORM_VERSION_LEN {
+            return Err(ProtoverError::Unparseable {
+                at: start,
+                token: String::from_utf8_lossy(&s[start..end]).into_owned(),
+            });
+        }
+
+        TorVersion::parse_bytes(&s[start..end])
+            .map(Some)
+            .map_err(|e| e.offset_by(start))
+    }
+
+    /// Determine if this version is at least as new as `cutoff`, comparing
+    /// them as C does, with `compare_like_c()`.
+    pub fn is_as_new_as(&self, cutoff: &TorVersion) -> bool {
+        self.compare_like_c(cutoff) != Ordering::Less
+    }
+
+    /// Compare this version with `other` as C's `tor_version_compare()` does.
+    ///
+    /// C compares svn revisions by their difference, modulo 2^32, so two
+    /// revisions which differ by 2^31 or more compare the wrong way around;
+    /// we do the same.  That isn't transitive, so `Ord` compares svn
+    /// revisions in the usual way instead.
+    ///
+    /// C_RUST_COUPLED: src/or/routerparse.c `tor_version_compare`
+    pub fn compare_like_c(&self, other: &TorVersion) -> Ordering {
+        let svn_difference: i32 =
+            (self.svn_revision as u32).wrapping_sub(other.svn_revision as u32) as i32;
+
+        self.compare_with_svn(other, svn_difference.cmp(&0))
+    }
+
+    /// Compare this version with `other`, field by field, taking `svn` as the
+    /// comparison of their svn revisions.
+    fn compare_with_svn(&self, other: &TorVersion, svn: Ordering) -> Ordering {
+        self.major.cmp(&other.major)
+            .then(self.minor.cmp(&other.minor))
+            .then(self.micro.cmp(&other.micro))
+            .then(self.status.cmp(&other.status))
+            .then(self.patchlevel.cmp(&other.patchlevel))
+            .then(self.status_tag.as_bytes().cmp(other.status_tag.as_bytes()))
+            .then(svn)
+            .then(self.git_tag.len().cmp(&other.git_tag.len()))
+            .then(self.git_tag.cmp(&other.git_tag))
+    }
+}
+
+impl FromStr for TorVersion {
+    type Err = ProtoverError;
+
+    /// Parse a Tor version, such as `"0.3.3.5-rc"` or `"Tor 0.2.4.19"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TorVersion::parse_bytes(s.as_bytes())
+    }
+}
+
+/// Order Tor versions as C's `tor_version_compare()` does, except that svn
+/// revisions are compared as plain numbers, so that this is a total order.
+/// Use `TorVersion::compare_like_c()` to compare them exactly as C does.
+impl Ord for TorVersion {
+    fn cmp(&self, other: &TorVersion) -> Ordering {
+        self.compare_with_svn(other, self.svn_revision.cmp(&other.svn_revision))
+    }
+}
+
+impl PartialOrd for TorVersion {
+    fn partial_cmp(&self, other: &TorVersion) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Write a version in the usual format, as in `0.2.9.1-alpha`.  Versions
+/// which were parsed from the old `pre` and `rc` formats are written in the
+/// new format, and svn revisions and git tags are omitted.
+impl fmt::Display for TorVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}.{}", self.major, self.minor, self.micro, self.patchlevel)?;
+        if !self.status_tag.is_empty() {
+            write!(f, "-{}", self.status_tag)?;
+        }
+        Ok(())
+    }
+}
+
+/// Determine if the Tor version in a router's `platform` line is at least as
+/// new as the version `cutoff`, as C's `tor_version_as_new_as()` does.
+///
+/// In particular, a platform which isn't Tor, or whose version can't be
+/// parsed, is assumed to be new enough; and nothing is as new as a `cutoff`
+/// which can't be parsed.
+///
+/// # Examples
+///
+/// ```
+/// use protover::version::is_as_new_as;
+///
+/// assert!(is_as_new_as("Tor 0.2.9.1-alpha on Linux", "0.2.7.5"));
+/// assert!(!is_as_new_as("Tor 0.2.7.4-rc on Linux", "0.2.7.5"));
+/// assert!(is_as_new_as("Arti 0.0.1", "0.2.7.5"));
+/// ```
+///
+/// C_RUST_COUPLED: src/or/routerparse.c `tor_version_as_new_as`
+pub fn is_as_new_as(platform: &str, cutoff: &str) -> bool {
+    match cutoff.parse() {
+        Ok(cutoff_version) => platform_is_as_new_as(platform, &cutoff_version),
+        Err(_) => false,
+    }
+}
+
+/// As `is_as_new_as()`, but with a `cutoff` which has already been parsed.
+pub fn platform_is_as_new_as(platform: &str, cutoff: &TorVersion) -> bool {
+    match TorVersion::from_platform(platform) {
+        Ok(Some(version)) => version.is_as_new_as(cutoff),
+        // Nonstandard or unparseable versions; be safe and say yes.
+        Ok(None) | Err(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn version(major: u32, minor: u32, micro: u32, status: VersionStatus, patchlevel: u32,
+               status_tag: &str) -> TorVersion {
+        TorVersion {
+            major, minor, micro, status, patchlevel,
+            status_tag: status_tag.to_string(),
+            svn_revision: 0,
+            git_tag: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse_formats() {
+        use self::VersionStatus::*;
+
+        assert_eq!(Ok(version(0, 3, 0, Release, 0, "")), "0.3".parse());
+        assert_eq!(Ok(version(0, 3, 0, Release, 0, "dev")), "0.3-dev".parse());
+        assert_eq!(Ok(version(0, 1, 2, Release, 0, "")), "0.1.2".parse());
+        assert_eq!(Ok(version(0, 1, 2, Pre, 3, "")), "0.1.2pre3".parse());
+        assert_eq!(Ok(version(0, 1, 2, Rc, 3, "cvs")), "0.1.2rc3-cvs".parse());
+        assert_eq!(Ok(version(0, 1, 2, Release, 3, "")), "Tor 0.1.2.3".parse());
+        assert_eq!(Ok(version(0, 2, 9, Release, 1, "alpha")), "0.2.9.1-alpha".parse());
+        assert_eq!(Ok(version(0, 2, 9, Release, 1, "alpha-dev")), "tor 0.2.9.1-alpha-dev".parse());
+        assert_eq!(Ok(version(1, 2, 3, Release, 4, "stable")), "1.2.3.4.stable".parse());
+        assert_eq!(Ok(version(0, 1, 2, Release, 0, "x")), "0.1.2-x on Linux".parse());
+    }
+
+    #[test]
+    fn test_parse_failures() {
+        assert!("".parse::<TorVersion>().is_err());
+        assert!("0".parse::<TorVersion>().is_err());
+        assert!("0.".parse::<TorVersion>().is_err());
+        assert!("a.b.c.d".parse::<TorVersion>().is_err());
+        assert!("0.1.2x3".parse::<TorVersion>().is_err());
+        assert!("0.1 on Linux".parse::<TorVersion>().is_err());
+        assert!("0.1.2.-3".parse::<TorVersion>().is_err());
+        assert!("0.1.2.2147483648".parse::<TorVersion>().is_err());
+        assert!("0.1.2.3 (git-)".parse::<TorVersion>().is_err());
+        assert!("0.1.2.3 (git-abc)".parse::<TorVersion>().is_err());
+        assert!("0.1.2.3 (git-abcx)".parse::<TorVersion>().is_err());
+        assert!("0.1.2.3 (git-abcd".parse::<TorVersion>().is_err());
+    }
+
+    #[test]
+    fn test_parse_error_position() {
+        assert_eq!(Err(ProtoverError::Unparseable { at: 5, token: "x3".to_string() }),
+                   "0.1.2x3".parse::<TorVersion>());
+        assert_eq!(Err(ProtoverError::Unparseable { at: 10, token: "x".to_string() }),
+                   TorVersion::from_platform("Tor 0.2.9.x on Linux"));
+        assert_eq!(Err(ProtoverError::Unparseable { at: 8, token: "x".to_string() }),
+                   TorVersion::from_platform("Tor 0.2.x on Linux"));
+    }
+
+    #[test]
+    fn test_parse_svn_and_git() {
+        let svn: TorVersion = "0.1.2.3-alpha (r1234)".parse().unwrap();
+        assert_eq!(1234, svn.svn_revision);
+
+        let svn: TorVersion = "0.1.2.3 (r-5)".parse().unwrap();
+        assert_eq!(-5, svn.svn_revision);
+
+        let svn: TorVersion = "0.1.2.3 (rgarbage)".parse().unwrap();
+        assert_eq!(0, svn.svn_revision);
+
+        let svn: TorVersion = "0.1.2.3 (r99999999999999999999)".parse().unwrap();
+        assert_eq!(-1, svn.svn_revision);
+
+        let git: TorVersion = "0.2.9.1-alpha (git-71ef3D7e)".parse().unwrap();
+        assert_eq!(vec![0x71, 0xef, 0x3d, 0x7e], git.git_tag);
+    }
+
+    #[test]
+    fn test_parse_long_status_tag() {
+        let long: TorVersion = "0.1.2.3-abcdefghijklmnopqrstuvwxyz0123456789 and more".parse().unwrap();
+        assert_eq!("abcdefghijklmnopqrstuvwxyz01234", long.status_tag);
+
+        let long: TorVersion = "0.1.2.3-abcdefghijklmnopqrstuvwxyz01234 (r5)".parse().unwrap();
+        assert_eq!("abcdefghijklmnopqrstuvwxyz01234", long.status_tag);
+        assert_eq!(5, long.svn_revision);
+    }
+
+    #[test]
+    fn test_from_platform() {
+        assert_eq!(Ok(None), TorVersion::from_platform("tor 0.2.9.1"));
+        assert_eq!(Ok(None), TorVersion::from_platform("Tor"));
+        assert!(TorVersion::from_platform("Tor ").is_err());
+        assert!(TorVersion::from_platform("Tor    ").is_err());
+
+        let version = TorVersion::from_platform("Tor 0.2.4.1 (r1234) on Linux").unwrap().unwrap();
+        assert_eq!(1234, version.svn_revision);
+
+        let version = TorVersion::from_platform("Tor 0.2.9.9 (git-fade) on Linux").unwrap().unwrap();
+        assert_eq!(vec![0xfa, 0xde], version.git_tag);
+
+        let long: String = format!("Tor 0.2.9.1-{}", "a".repeat(200));
+        assert!(TorVersion::from_platform(&long).is_err());
+    }
+
+    #[test]
+    fn test_ordering() {
+        let ascending: &[&str] = &[
+            "0.0.9pre1", "0.0.9rc1", "0.0.9", "0.0.9.1",
+            "0.1.2.3-alpha", "0.1.2.3-alpha (r10)", "0.1.2.3-alpha (r11)", "0.1.2.3-beta",
+            "0.2.9.1-alpha", "0.2.9.1-alpha (git-00)", "0.2.9.1-alpha (git-ff)",
+            "0.2.9.1-alpha (git-0000)", "0.2.10.1", "0.3.3.5-rc", "1.0.0.0",
+        ];
+        let versions: Vec<TorVersion> = ascending.iter().map(|v| v.parse().unwrap()).collect();
+
+        for pair in versions.windows(2) {
+            assert!(pair[0] < pair[1], "{:?} >= {:?}", pair[0], pair[1]);
+            assert!(pair[1].is_as_new_as(&pair[0]));
+            assert!(!pair[0].is_as_new_as(&pair[1]));
+        }
+    }
+
+    #[test]
+    fn test_compare_svn_revisions_like_c() {
+        let low: TorVersion = "0.1.2.3 (r-2147483648)".parse().unwrap();
+        let high: TorVersion = "0.1.2.3 (r1)".parse().unwrap();
+
+        assert_eq!(Ordering::Greater, low.compare_like_c(&high));
+        assert!(low.is_as_new_as(&high));
+        assert_eq!(Ordering::Less, low.cmp(&high));
+    }
+
+    #[test]
+    fn test_ordering_svn_revisions_is_total() {
+        let mut versions: Vec<TorVersion> = ["0.1.2.3 (r2147483647)", "0.1.2.3 (r0)",
+                                             "0.1.2.3 (r-2147483648)"]
+            .iter().map(|v| v.parse().unwrap()).collect();
+
+        // C's comparison goes round in a circle here.
+        assert_eq!(Ordering::Less, versions[2].compare_like_c(&versions[1]));
+        assert_eq!(Ordering::Less, versions[1].compare_like_c(&versions[0]));
+        assert_eq!(Ordering::Greater, versions[2].compare_like_c(&versions[0]));
+
+        versions.sort();
+        assert_eq!(vec![-2147483648, 0, 2147483647],
+                   versions.iter().map(|v| v.svn_revision).collect::<Vec<i32>>());
+    }
+
+    #[test]
+    fn test_is_as_new_as() {
+        assert!(is_as_new_as("Tor 0.2.9.1-alpha on Linux", "0.2.9.1-alpha"));
+        assert!(is_as_new_as("Tor 0.3.0.1 on Linux", "0.2.9.1-alpha"));
+        assert!(!is_as_new_as("Tor 0.2.8.9 on Linux", "0.2.9.1-alpha"));
+        assert!(!is_as_new_as("Tor 0.2.9.1-alpha on Linux", "0.2.9.1-beta"));
+
+        assert!(is_as_new_as("Tor 0.2.x on Linux", "0.2.9.1-alpha"));
+        assert!(is_as_new_as("Tor ", "0.2.9.1-alpha"));
+        assert!(is_as_new_as("Arti", "0.2.9.1-alpha"));
+        assert!(!is_as_new_as("Tor 0.2.9.1", "0.2.x"));
+    }
+}
@@ -0,0 +1,198 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Print a table of the protocols supported by the relays in a consensus,
+//! by number of relays and by consensus weight.
+//!
+//! Usage: `protover_stats [FILE]`, where `FILE` is a consensus, such as a
+//! `cached-microdesc-consensus`, or `-` (the default) for standard input.
+
+extern crate protover;
+
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::process;
+
+use protover::UnvalidatedProtoEntry;
+use protover::history::protocols_for_platform;
+use protover::stats::ProtocolStats;
+
+/// One router status entry from a consensus.
+#[derive(Debug, Default)]
+struct Relay {
+    /// The platform from the `v` line, if any.
+    platform: Option<String>,
+    /// The protocols from the `pr` line, if any.
+    protocols: Option<String>,
+    /// The `Bandwidth` from the `w` line, or 0.
+    weight: u64,
+}
+
+impl Relay {
+    /// Get the protocols which this relay supports: those it lists, or else
+    /// those which we know its version supports, or else none.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the relay's `pr` line is unparseable.
+    fn supported(&self) -> Option<UnvalidatedProtoEntry> {
+        if let Some(ref protocols) = self.protocols {
+            if protocols.is_empty() {
+                return Some(UnvalidatedProtoEntry::default());
+            }
+            return protocols.parse().ok();
+        }
+        Some(self.platform.as_ref()
+             .and_then(|platform| protocols_for_platform(platform))
+             .map_or_else(UnvalidatedProtoEntry::default,
+                          |entry| entry.protocols.clone().into()))
+    }
+}
+
+/// Find the router status entries in the `consensus` document.
+fn relays_in(consensus: &str) -> Vec<Relay> {
+    let mut relays: Vec<Relay> = Vec::new();
+
+    for line in consensus.lines() {
+        let (keyword, args) = match line.find(' ') {
+            Some(space) => (&line[..space], line[space + 1..].trim()),
+            None => (line, ""),
+        };
+
+        if keyword == "r" {
+            relays.push(Relay::default());
+            continue;
+        }
+        if keyword == "directory-footer" {
+            break;
+        }
+
+        let relay: &mut Relay = match relays.last_mut() {
+            Some(relay) => relay,
+            None => continue,
+        };
+
+        match keyword {
+            "v" => relay.platform = Some(args.to_string()),
+            "pr" => relay.protocols = Some(args.to_string()),
+            "w" => {
+                relay.weight = args.split_whitespace()
+                    .filter_map(|kv| if kv.starts_with("Bandwidth=") { Some(&kv[10..]) } else { None })
+                    .next()
+                    .and_then(|bandwidth| bandwidth.parse().ok())
+                    .unwrap_or(0);
+            },
+            _ => (),
+        }
+    }
+    relays
+}
+
+fn read_input(path: &str) -> io::Result<String> {
+    let mut contents: String = String::new();
+
+    if path == "-" {
+        io::stdin().read_to_string(&mut contents)?;
+    } else {
+        File::open(path)?.read_to_string(&mut contents)?;
+    }
+    Ok(contents)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    if args.len() > 2 || args.iter().skip(1).any(|arg| arg == "-h" || arg == "--help") {
+        eprintln!("usage: {} [cached-microdesc-consensus]", args[0]);
+        process::exit(2);
+    }
+
+    let path: &str = args.get(1).map_or("-", |path| path.as_str());
+    let consensus: String = match read_input(path) {
+        Ok(consensus) => consensus,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        },
+    };
+
+    let relays: Vec<Relay> = relays_in(&consensus);
+    let mut unparseable: usize = 0;
+    let stats: ProtocolStats = ProtocolStats::from_relays(relays.iter().filter_map(|relay| {
+        let supported = relay.supported().map(|protocols| (protocols, relay.weight));
+
+        if supported.is_none() {
+            unparseable += 1;
+        }
+        supported
+    }));
+
+    println!("{} relays, total weight {}", stats.relays, stats.total_weight);
+    if unparseable > 0 {
+        println!("{} relays skipped, with unparseable protocols", unparseable);
+    }
+    println!();
+    print!("{}", stats);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONSENSUS: &'static str = "\
+network-status-version 3 microdesc
+vote-status consensus
+r moria1 lpXfw1/+uGEym58asExGOXAgzjE 2018-04-20 01:02:03 128.31.0.34 9101 9131
+m 3nRvwMpmEIKu5Uj6Ty9HRS6k9w9L4Iw3t5GDmRHkvlE
+s Authority Fast Running Stable V2Dir Valid
+v Tor 0.3.4.0-alpha-dev
+pr Cons=1-2 Desc=1-2 DirCache=1-2 HSDir=1-2 HSIntro=3-4 HSRend=1-2 Link=1-5 LinkAuth=1,3 Microdesc=1-2 Relay=1-2
+w Bandwidth=20
+r oldie AAAAAAAAAAAAAAAAAAAAAAAAAAA 2018-04-20 01:02:03 10.0.0.1 9001 0
+s Running Valid
+v Tor 0.2.8.12
+w Bandwidth=80 Unmeasured=1
+r broken BBBBBBBBBBBBBBBBBBBBBBBBBBB 2018-04-20 01:02:03 10.0.0.2 9001 0
+pr Link=1-
+w Bandwidth=5
+directory-footer
+bandwidth-weights Wbd=0
+";
+
+    #[test]
+    fn test_relays_in_consensus() {
+        let relays: Vec<Relay> = relays_in(CONSENSUS);
+
+        assert_eq!(3, relays.len());
+        assert_eq!(Some("Tor 0.3.4.0-alpha-dev".to_string()), relays[0].platform);
+        assert_eq!(20, relays[0].weight);
+        assert_eq!(None, relays[1].protocols);
+        assert_eq!(80, relays[1].weight);
+    }
+
+    #[test]
+    fn test_supported_protocols() {
+        let relays: Vec<Relay> = relays_in(CONSENSUS);
+        let oldie: UnvalidatedProtoEntry = relays[1].supported().unwrap();
+
+        assert!(relays[0].supported().unwrap().supports_protocol(&"Link".parse().unwrap(), &5));
+        assert!(oldie.supports_protocol(&"Link".parse().unwrap(), &4));
+        assert!(!oldie.supports_protocol(&"HSRend".parse().unwrap(), &2));
+        assert_eq!(None, relays[2].supported());
+    }
+
+    #[test]
+    fn test_stats_over_consensus() {
+        let stats: ProtocolStats = ProtocolStats::from_relays(
+            relays_in(CONSENSUS).iter()
+                .filter_map(|relay| relay.supported().map(|p| (p, relay.weight))));
+        let link = "Link".parse().unwrap();
+
+        assert_eq!(2, stats.relays);
+        assert_eq!(0.5, stats.fraction_of_relays(&link, 5));
+        assert_eq!(0.2, stats.fraction_of_weight(&link, 5));
+        assert_eq!(1.0, stats.fraction_of_weight(&link, 4));
+    }
+}
@@ -0,0 +1,77 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Benchmarks for reading the strings in a smartlist, by copying them into a
+//! `Vec<String>` or by borrowing them.  Run them with `cargo bench --features
+//! bench`.
+//!
+//! These time themselves, rather than using the unstable `test` crate, so
+//! that they build with a stable compiler.
+
+extern crate libc;
+extern crate smartlist;
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use std::ffi::CString;
+use std::mem;
+use std::ptr;
+use std::time::Duration;
+use std::time::Instant;
+
+use libc::c_char;
+
+use smartlist::*;
+
+use common::*;
+
+/// How many times to read the votes in each sample.
+const ITERATIONS: usize = 100_000;
+
+/// How many times to time each benchmark, keeping the fastest.
+const SAMPLES: usize = 5;
+
+/// Return `x`, in a way which the optimiser can't see through, so that it
+/// can't skip computing `x`.
+fn black_box<T>(x: T) -> T {
+    unsafe {
+        let y: T = ptr::read_volatile(&x);
+
+        mem::forget(x);
+        y
+    }
+}
+
+/// Time `f` `SAMPLES` times, after running it once to warm up, and report
+/// the fastest time for each of its `ITERATIONS`.
+fn bench<F: FnMut()>(name: &str, mut f: F) {
+    f();
+
+    let fastest: Duration = (0..SAMPLES).map(|_| {
+        let start: Instant = Instant::now();
+
+        f();
+        start.elapsed()
+    }).min().unwrap();
+    let nanos: u64 = fastest.as_secs() * 1_000_000_000 + fastest.subsec_nanos() as u64;
+
+    println!("bench {}: {} ns per iteration", name, nanos / ITERATIONS as u64);
+}
+
+fn main() {
+    let votes: Vec<CString> = authority_votes();
+    let pointers: Vec<*const c_char> = votes.iter().map(|v| v.as_ptr()).collect();
+    let list: Stringlist = stringlist(&pointers);
+
+    bench("copied_votes", || {
+        for _ in 0..ITERATIONS {
+            black_box(copied_length(&list));
+        }
+    });
+    bench("borrowed_votes", || {
+        for _ in 0..ITERATIONS {
+            black_box(borrowed_length(&list));
+        }
+    });
+}
@@ -3,6 +3,8 @@
 
 extern crate libc;
 
+mod raw;
 mod smartlist;
 
+pub use raw::*;
 pub use smartlist::*;
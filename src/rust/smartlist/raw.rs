@@ -0,0 +1,525 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Views of tor's `smartlist_t` of any kind of pointer, for reading and
+//! changing lists which C owns, and for building new lists to give to C.
+//!
+//! Unlike `Stringlist::get_list()`, these don't copy anything: a
+//! `SmartlistRef` borrows the elements of the C list, and a `SmartlistMut`
+//! changes the C list itself, through tor's `smartlist_add()` and
+//! `smartlist_del()`.
+
+use std::cmp;
+use std::ffi::CStr;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::Index;
+use std::ptr;
+use std::slice;
+use std::str::Utf8Error;
+
+use libc::{c_char, c_int, c_void};
+
+use smartlist::Stringlist;
+
+/// tor's `smartlist_t`, a growable array of `capacity` pointers, of which the
+/// first `num_used` are in use.
+///
+/// C_RUST_COUPLED: src/common/container.h `smartlist_t`
+#[repr(C)]
+pub struct RawSmartlist {
+    pub list: *mut *mut c_void,
+    pub num_used: c_int,
+    pub capacity: c_int,
+}
+
+/// The capacity of a new smartlist.
+///
+/// C_RUST_COUPLED: src/common/container.c `SMARTLIST_DEFAULT_CAPACITY`
+const SMARTLIST_DEFAULT_CAPACITY: usize = 16;
+
+#[cfg(not(test))]
+extern "C" {
+    fn tor_malloc_(size: usize) -> *mut c_void;
+    fn smartlist_add(sl: *mut RawSmartlist, element: *mut c_void);
+    fn smartlist_del(sl: *mut RawSmartlist, idx: c_int);
+}
+
+// Defined only for tests, so that we don't need to link to tor C files.
+// These use the system allocator, and otherwise behave as tor's do.
+#[cfg(test)]
+unsafe extern "C" fn tor_malloc_(size: usize) -> *mut c_void {
+    ::libc::malloc(size)
+}
+
+#[cfg(test)]
+unsafe extern "C" fn smartlist_add(sl: *mut RawSmartlist, element: *mut c_void) {
+    let sl: &mut RawSmartlist = &mut *sl;
+
+    if sl.num_used == sl.capacity {
+        let higher: c_int = sl.capacity * 2;
+        let size: usize = higher as usize * mem::size_of::<*mut c_void>();
+
+        sl.list = ::libc::realloc(sl.list as *mut c_void, size) as *mut *mut c_void;
+        ptr::write_bytes(sl.list.offset(sl.capacity as isize), 0, (higher - sl.capacity) as usize);
+        sl.capacity = higher;
+    }
+    *sl.list.offset(sl.num_used as isize) = element;
+    sl.num_used += 1;
+}
+
+#[cfg(test)]
+unsafe extern "C" fn smartlist_del(sl: *mut RawSmartlist, idx: c_int) {
+    let sl: &mut RawSmartlist = &mut *sl;
+
+    assert!(0 <= idx && idx < sl.num_used);
+    sl.num_used -= 1;
+    *sl.list.offset(idx as isize) = *sl.list.offset(sl.num_used as isize);
+    *sl.list.offset(sl.num_used as isize) = ptr::null_mut();
+}
+
+/// A borrowed, read-only view of a C smartlist whose elements are pointers
+/// to `T`.
+///
+/// C smartlists may hold NULL pointers.  `len()` counts them, `get()` gives
+/// `None` for them, and `iter()` skips them.
+pub struct SmartlistRef<'a, T: 'a> {
+    elements: &'a [*mut T],
+}
+
+impl<'a, T> SmartlistRef<'a, T> {
+    /// View the C smartlist `sl`, or an empty list if `sl` is NULL.
+    ///
+    /// # Safety
+    ///
+    /// `sl` must be NULL or point to a valid smartlist, whose elements are
+    /// each NULL or point to a valid `T`.  Neither the list nor its elements
+    /// may be changed or freed for the lifetime `'a`.
+    pub unsafe fn from_raw(sl: *const RawSmartlist) -> SmartlistRef<'a, T> {
+        if sl.is_null() || (*sl).list.is_null() || (*sl).num_used <= 0 {
+            return SmartlistRef { elements: &[] };
+        }
+        SmartlistRef {
+            elements: slice::from_raw_parts((*sl).list as *const *mut T, (*sl).num_used as usize),
+        }
+    }
+
+    /// Get the number of elements, including any NULL ones.
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Get the element at `index`, or `None` if it is NULL or out of range.
+    pub fn get(&self, index: usize) -> Option<&'a T> {
+        self.elements.get(index).and_then(|element| unsafe { element.as_ref() })
+    }
+
+    /// Get an iterator over the elements, skipping any NULL ones.
+    pub fn iter(&self) -> Iter<'a, T> {
+        Iter { elements: self.elements.iter() }
+    }
+
+    /// Get the elements as the pointers which C stored.
+    pub fn as_ptrs(&self) -> &'a [*mut T] {
+        self.elements
+    }
+}
+
+impl<'a> SmartlistRef<'a, c_char> {
+    /// Get the string at `index`, as it is, whatever its encoding, or `None`
+    /// if it is NULL or out of range.
+    pub fn get_cstr(&self, index: usize) -> Option<&'a CStr> {
+        match self.elements.get(index) {
+            Some(element) if !element.is_null() => Some(unsafe { CStr::from_ptr(*element) }),
+            _ => None,
+        }
+    }
+
+    /// Get an iterator over the strings, as they are, whatever their
+    /// encoding, skipping any NULL ones.
+    pub fn cstrs(&self) -> CStrs<'a> {
+        CStrs { elements: self.elements.iter() }
+    }
+
+    /// Get an iterator over the strings, as `&str`s, skipping any NULL ones.
+    /// Each string which isn't UTF-8 is given as an error, so that callers
+    /// can skip it, or give up on the whole list.
+    pub fn strs(&self) -> Strs<'a> {
+        Strs { cstrs: self.cstrs() }
+    }
+}
+
+// Derived impls would require T: Clone, but we only copy the pointers.
+impl<'a, T> Clone for SmartlistRef<'a, T> {
+    fn clone(&self) -> SmartlistRef<'a, T> {
+        SmartlistRef { elements: self.elements }
+    }
+}
+
+impl<'a, T> Copy for SmartlistRef<'a, T> {}
+
+/// Index into the list, panicking if `index` is out of range or its element
+/// is NULL.
+impl<'a, T> Index<usize> for SmartlistRef<'a, T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        match self.get(index) {
+            Some(element) => element,
+            None => panic!("no element at index {} of a smartlist of length {}",
+                           index, self.len()),
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for SmartlistRef<'a, T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+/// A `Stringlist` is a smartlist of strings, so it can be viewed as one
+/// without copying.
+impl<'a> From<&'a Stringlist> for SmartlistRef<'a, c_char> {
+    fn from(sl: &'a Stringlist) -> SmartlistRef<'a, c_char> {
+        // A Stringlist is laid out as a RawSmartlist, and we are given it by
+        // C, so the same contract holds.
+        unsafe { SmartlistRef::from_raw(sl as *const Stringlist as *const RawSmartlist) }
+    }
+}
+
+/// An iterator over the non-NULL elements of a `SmartlistRef`.
+pub struct Iter<'a, T: 'a> {
+    elements: slice::Iter<'a, *mut T>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        while let Some(element) = self.elements.next() {
+            // The SmartlistRef's contract is that each non-NULL element is
+            // valid for 'a.
+            if let Some(element) = unsafe { element.as_ref() } {
+                return Some(element);
+            }
+        }
+        None
+    }
+}
+
+/// An iterator over the non-NULL strings of a `SmartlistRef<c_char>`.
+pub struct CStrs<'a> {
+    elements: slice::Iter<'a, *mut c_char>,
+}
+
+impl<'a> Iterator for CStrs<'a> {
+    type Item = &'a CStr;
+
+    fn next(&mut self) -> Option<&'a CStr> {
+        while let Some(element) = self.elements.next() {
+            if !element.is_null() {
+                return Some(unsafe { CStr::from_ptr(*element) });
+            }
+        }
+        None
+    }
+}
+
+/// An iterator over the non-NULL strings of a `SmartlistRef<c_char>`, as
+/// `&str`s, if they are UTF-8.
+pub struct Strs<'a> {
+    cstrs: CStrs<'a>,
+}
+
+impl<'a> Iterator for Strs<'a> {
+    type Item = Result<&'a str, Utf8Error>;
+
+    fn next(&mut self) -> Option<Result<&'a str, Utf8Error>> {
+        self.cstrs.next().map(|s| s.to_str())
+    }
+}
+
+/// A mutable view of a C smartlist whose elements are pointers to `T`, which
+/// changes it through tor's smartlist functions.
+pub struct SmartlistMut<'a, T: 'a> {
+    sl: &'a mut RawSmartlist,
+    elements: PhantomData<&'a mut T>,
+}
+
+impl<'a, T> SmartlistMut<'a, T> {
+    /// Change the C smartlist `sl`, or return `None` if `sl` is NULL.
+    ///
+    /// # Safety
+    ///
+    /// As for `SmartlistRef::from_raw()`, and additionally, nothing else may
+    /// read or change the list for the lifetime `'a`.
+    pub unsafe fn from_raw(sl: *mut RawSmartlist) -> Option<SmartlistMut<'a, T>> {
+        sl.as_mut().map(|sl| SmartlistMut { sl: sl, elements: PhantomData })
+    }
+
+    /// Get a read-only view of the list as it is now.
+    pub fn as_ref(&self) -> SmartlistRef<T> {
+        unsafe { SmartlistRef::from_raw(self.sl) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.as_ref().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.as_ref().is_empty()
+    }
+
+    /// Add `element` to the end of the list, with `smartlist_add()`.
+    ///
+    /// # Safety
+    ///
+    /// `element` must be NULL or point to a valid `T` which lives as long as
+    /// it is in the list.  Whoever frees the list's elements must be able to
+    /// free it.
+    pub unsafe fn push(&mut self, element: *mut T) {
+        smartlist_add(self.sl, element as *mut c_void);
+    }
+
+    /// Remove the element at `index`, with `smartlist_del()`, which moves the
+    /// last element into its place.
+    ///
+    /// # Returns
+    ///
+    /// The removed element, which is now the caller's to free, or `None` if
+    /// `index` is out of range.
+    pub fn remove(&mut self, index: usize) -> Option<*mut T> {
+        let removed: *mut T = match self.as_ref().as_ptrs().get(index) {
+            Some(element) => *element,
+            None => return None,
+        };
+
+        unsafe { smartlist_del(self.sl, index as c_int) };
+        Some(removed)
+    }
+}
+
+impl<'a> SmartlistMut<'a, c_char> {
+    /// Add a copy of `string`, allocated with `tor_malloc_()` so that C can
+    /// free it with `tor_free()`, to the end of the list.
+    pub fn push_cstr(&mut self, string: &CStr) {
+        let copy: *mut c_char = copy_cstr(string);
+
+        unsafe { self.push(copy) };
+    }
+}
+
+/// Copy `string` into memory from `tor_malloc_()`.
+fn copy_cstr(string: &CStr) -> *mut c_char {
+    let bytes: &[u8] = string.to_bytes_with_nul();
+
+    unsafe {
+        let copy: *mut c_char = tor_malloc_(bytes.len()) as *mut c_char;
+
+        ptr::copy_nonoverlapping(bytes.as_ptr() as *const c_char, copy, bytes.len());
+        copy
+    }
+}
+
+/// Allocate a new C smartlist holding `elements`, in order, which C must
+/// free with `smartlist_free()`.  The list owns nothing more than the
+/// pointers, just as it would if C had built it.
+///
+/// # Returns
+///
+/// The new list, or NULL if there are too many elements for a smartlist.
+pub fn smartlist_from_vec<T>(elements: Vec<*mut T>) -> *mut RawSmartlist {
+    if elements.len() > c_int::max_value() as usize {
+        return ptr::null_mut();
+    }
+
+    let capacity: usize = cmp::max(elements.len(), SMARTLIST_DEFAULT_CAPACITY);
+    let size: usize = match capacity.checked_mul(mem::size_of::<*mut c_void>()) {
+        Some(n) => n,
+        None => return ptr::null_mut(),
+    };
+
+    let num_used: c_int = elements.len() as c_int;
+
+    unsafe {
+        let sl = tor_malloc_(mem::size_of::<RawSmartlist>()) as *mut RawSmartlist;
+        let list = tor_malloc_(size) as *mut *mut c_void;
+
+        // As smartlist_new() does, zero the unused part of the list.
+        ptr::write_bytes(list, 0, capacity);
+        for (i, element) in elements.into_iter().enumerate() {
+            *list.offset(i as isize) = element as *mut c_void;
+        }
+        ptr::write(sl, RawSmartlist { list: list, num_used: num_used, capacity: capacity as c_int });
+        sl
+    }
+}
+
+/// Allocate a new C smartlist holding copies of `strings`, in order, which C
+/// must free with `SMARTLIST_FOREACH(sl, char *, cp, tor_free(cp))` and then
+/// `smartlist_free()`.
+///
+/// The strings are copied byte for byte, whatever their encoding.
+///
+/// # Returns
+///
+/// The new list, or NULL if there are too many strings for a smartlist.
+pub fn smartlist_from_cstrs<S: AsRef<CStr>>(strings: &[S]) -> *mut RawSmartlist {
+    if strings.len() > c_int::max_value() as usize {
+        return ptr::null_mut();
+    }
+    smartlist_from_vec(strings.iter().map(|s| copy_cstr(s.as_ref())).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::ffi::CString;
+
+    use libc::free;
+
+    /// Free a list from `smartlist_from_vec()`, as `smartlist_free()` would.
+    unsafe fn free_smartlist(sl: *mut RawSmartlist) {
+        free((*sl).list as *mut c_void);
+        free(sl as *mut c_void);
+    }
+
+    fn c_strings(strings: &[&[u8]]) -> Vec<CString> {
+        strings.iter().map(|s| CString::new(*s).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_smartlist_ref_null() {
+        let sl: SmartlistRef<u32> = unsafe { SmartlistRef::from_raw(ptr::null()) };
+
+        assert_eq!(0, sl.len());
+        assert!(sl.is_empty());
+        assert_eq!(None, sl.get(0));
+        assert_eq!(0, sl.iter().count());
+    }
+
+    #[test]
+    fn test_smartlist_ref_elements() {
+        let mut numbers: Vec<u32> = vec![7, 8, 9];
+        let mut pointers: Vec<*mut u32> = numbers.iter_mut().map(|n| n as *mut u32).collect();
+        pointers.insert(1, ptr::null_mut());
+
+        let raw = RawSmartlist {
+            list: pointers.as_mut_ptr() as *mut *mut c_void,
+            num_used: 4,
+            capacity: 4,
+        };
+        let sl: SmartlistRef<u32> = unsafe { SmartlistRef::from_raw(&raw) };
+
+        assert_eq!(4, sl.len());
+        assert_eq!(Some(&7), sl.get(0));
+        assert_eq!(None, sl.get(1));
+        assert_eq!(None, sl.get(4));
+        assert_eq!(9, sl[3]);
+        assert_eq!(vec![7, 8, 9], sl.iter().cloned().collect::<Vec<u32>>());
+        assert_eq!(24, sl.into_iter().sum::<u32>());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_smartlist_ref_index_null() {
+        let mut pointers: Vec<*mut u32> = vec![ptr::null_mut()];
+        let raw = RawSmartlist {
+            list: pointers.as_mut_ptr() as *mut *mut c_void,
+            num_used: 1,
+            capacity: 1,
+        };
+        let sl: SmartlistRef<u32> = unsafe { SmartlistRef::from_raw(&raw) };
+
+        sl[0];
+    }
+
+    #[test]
+    fn test_smartlist_ref_cstrs_are_lossless() {
+        let strings: Vec<CString> = c_strings(&[b"Link=1-4", b"\xff\xfe", b""]);
+        let pointers: Vec<*const c_char> = strings.iter().map(|s| s.as_ptr()).collect();
+        let stringlist = Stringlist { list: pointers.as_ptr(), num_used: 3, capacity: 3 };
+        let sl: SmartlistRef<c_char> = SmartlistRef::from(&stringlist);
+
+        assert_eq!(strings.iter().map(|s| s.as_c_str()).collect::<Vec<&CStr>>(),
+                   sl.cstrs().collect::<Vec<&CStr>>());
+        assert_eq!(Some(strings[1].as_c_str()), sl.get_cstr(1));
+        assert_eq!(None, sl.get_cstr(3));
+
+        let strs: Vec<Result<&str, Utf8Error>> = sl.strs().collect();
+
+        assert_eq!(3, strs.len());
+        assert_eq!(Ok("Link=1-4"), strs[0]);
+        assert!(strs[1].is_err());
+        assert_eq!(Ok(""), strs[2]);
+    }
+
+    #[test]
+    fn test_smartlist_from_vec() {
+        let mut numbers: Vec<u32> = (0..20).collect();
+        let pointers: Vec<*mut u32> = numbers.iter_mut().map(|n| n as *mut u32).collect();
+        let raw: *mut RawSmartlist = smartlist_from_vec(pointers);
+
+        unsafe {
+            assert_eq!(20, (*raw).num_used);
+            assert_eq!(20, (*raw).capacity);
+        }
+
+        let sl: SmartlistRef<u32> = unsafe { SmartlistRef::from_raw(raw) };
+
+        assert_eq!(numbers, sl.iter().cloned().collect::<Vec<u32>>());
+        unsafe { free_smartlist(raw) };
+
+        let empty: *mut RawSmartlist = smartlist_from_vec::<u32>(Vec::new());
+
+        unsafe {
+            assert_eq!(0, (*empty).num_used);
+            assert_eq!(16, (*empty).capacity);
+            free_smartlist(empty);
+        }
+    }
+
+    #[test]
+    fn test_smartlist_mut_push_remove() {
+        let strings: Vec<CString> = c_strings(&[b"Cons=1-2", b"Desc=1-2"]);
+        let raw: *mut RawSmartlist = smartlist_from_cstrs(&strings);
+
+        {
+            let mut sl: SmartlistMut<c_char> = unsafe { SmartlistMut::from_raw(raw).unwrap() };
+
+            for i in 0..20 {
+                sl.push_cstr(&CString::new(format!("Link={}", i)).unwrap());
+            }
+            assert_eq!(22, sl.len());
+            assert_eq!(Some(CString::new("Link=19").unwrap().as_c_str()), sl.as_ref().get_cstr(21));
+
+            // The last element moves into the place of the removed one.
+            let removed: *mut c_char = sl.remove(0).unwrap();
+
+            assert_eq!(b"Cons=1-2", unsafe { CStr::from_ptr(removed) }.to_bytes());
+            unsafe { free(removed as *mut c_void) };
+            assert_eq!(21, sl.len());
+            assert_eq!(b"Link=19", sl.as_ref().get_cstr(0).unwrap().to_bytes());
+            assert_eq!(None, sl.remove(21));
+
+            for element in sl.as_ref().as_ptrs() {
+                unsafe { free(*element as *mut c_void) };
+            }
+        }
+        unsafe { free_smartlist(raw) };
+    }
+
+    #[test]
+    fn test_smartlist_mut_null() {
+        assert!(unsafe { SmartlistMut::<u32>::from_raw(ptr::null_mut()) }.is_none());
+    }
+}
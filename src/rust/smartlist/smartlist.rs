@@ -1,9 +1,12 @@
 // Copyright (c) 2016-2017, The Tor Project, Inc. */
 // See LICENSE for licensing information */
 
-use std::slice;
-use libc::{c_char, c_int};
-use std::ffi::CStr;
+use libc::c_char;
+use libc::c_int;
+
+use raw::CStrs;
+use raw::SmartlistRef;
+use raw::Strs;
 
 /// Smartlists are a type used in C code in tor to define a collection of a
 /// generic type, which has a capacity and a number used. Each Smartlist
@@ -22,38 +25,33 @@ pub struct Stringlist {
     pub capacity: c_int,
 }
 
-impl Smartlist<String> for Stringlist {
-    fn get_list(&self) -> Vec<String> {
-        let empty: Vec<String> = Vec::new();
-        let mut rust_list: Vec<String> = Vec::new();
-
-        if self.list.is_null() || self.num_used == 0 {
-            return empty;
-        }
-
-        // unsafe, as we need to extract the smartlist list into a vector of
-        // pointers, and then transform each element into a Rust string.
-        let elems: &[*const c_char] =
-            unsafe { slice::from_raw_parts(self.list, self.num_used as usize) };
-
-        for elem in elems.iter() {
-            if elem.is_null() {
-                continue;
-            }
-
-            // unsafe, as we need to create a cstring from the referenced
-            // element
-            let c_string = unsafe { CStr::from_ptr(*elem) };
+impl Stringlist {
+    /// Borrow the strings in this list, without copying them.
+    pub fn as_smartlist_ref(&self) -> SmartlistRef<c_char> {
+        SmartlistRef::from(self)
+    }
 
-            let r_string = match c_string.to_str() {
-                Ok(n) => n,
-                Err(_) => return empty,
-            };
+    /// Get an iterator over the strings in this list, as `&CStr`s borrowed
+    /// from it, skipping any NULL ones.
+    pub fn cstrs(&self) -> CStrs {
+        self.as_smartlist_ref().cstrs()
+    }
 
-            rust_list.push(String::from(r_string));
-        }
+    /// Get an iterator over the strings in this list, as `&str`s borrowed
+    /// from it, or errors for any which aren't UTF-8, skipping any NULL ones.
+    pub fn strs(&self) -> Strs {
+        self.as_smartlist_ref().strs()
+    }
+}
 
-        rust_list
+/// Copy every string in the list, or return an empty `Vec` if any of them
+/// isn't UTF-8.  Use `strs()` instead to avoid copying.
+impl Smartlist<String> for Stringlist {
+    fn get_list(&self) -> Vec<String> {
+        self.strs()
+            .map(|s| s.map(String::from))
+            .collect::<Result<Vec<String>, _>>()
+            .unwrap_or_default()
     }
 }
 
@@ -110,6 +108,28 @@ mod test {
             let data = sl.get_list();
             assert_eq!("a", &data[0]);
             assert_eq!("b", &data[1]);
+
+            let borrowed: Vec<&str> = sl.strs().map(|s| s.unwrap()).collect();
+            assert_eq!(vec!["a", "b"], borrowed);
         }
     }
+
+    #[test]
+    fn test_get_list_not_utf8() {
+        use std::ffi::CString;
+        use libc::c_char;
+
+        use super::Smartlist;
+        use super::Stringlist;
+
+        let c_strings = vec![CString::new("a").unwrap(), CString::new(&b"\xff"[..]).unwrap()];
+        let p_args: Vec<*const c_char> = c_strings.iter().map(|arg| arg.as_ptr()).collect();
+        let sl = Stringlist { list: p_args.as_ptr(), num_used: 2, capacity: 2 };
+
+        // Copying gives up on the whole list, but borrowing can skip just
+        // the string which isn't UTF-8.
+        assert!(sl.get_list().is_empty());
+        assert_eq!(vec!["a"], sl.strs().filter_map(|s| s.ok()).collect::<Vec<&str>>());
+        assert_eq!(2, sl.cstrs().count());
+    }
 }
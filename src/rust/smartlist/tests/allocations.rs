@@ -0,0 +1,66 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Checks, with an allocator which counts its allocations, that borrowing the
+//! strings in a smartlist doesn't copy them.
+
+extern crate libc;
+extern crate smartlist;
+
+mod common;
+
+use std::alloc::GlobalAlloc;
+use std::alloc::Layout;
+use std::alloc::System;
+use std::ffi::CString;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use libc::c_char;
+
+use smartlist::*;
+
+use common::*;
+
+/// The system allocator, counting how many allocations are made through it.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// How many allocations `f` makes.
+fn allocations<F: FnOnce()>(f: F) -> usize {
+    let before: usize = ALLOCATIONS.load(Ordering::SeqCst);
+
+    f();
+    ALLOCATIONS.load(Ordering::SeqCst) - before
+}
+
+#[test]
+fn borrowing_votes_does_not_allocate() {
+    let votes: Vec<CString> = authority_votes();
+    let pointers: Vec<*const c_char> = votes.iter().map(|v| v.as_ptr()).collect();
+    let list: Stringlist = stringlist(&pointers);
+    let mut copied: usize = 0;
+    let mut borrowed: usize = 0;
+
+    let copying: usize = allocations(|| copied = copied_length(&list));
+    let borrowing: usize = allocations(|| borrowed = borrowed_length(&list));
+
+    assert_eq!(copied, borrowed);
+    assert!(copying > votes.len(), "copying made only {} allocations", copying);
+    assert_eq!(0, borrowing);
+}
@@ -0,0 +1,56 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! The smartlist of votes shared by the allocation test and the benchmark.
+
+use std::ffi::CString;
+
+use libc::c_char;
+
+use smartlist::*;
+
+/// The protocol lists voted for by each of the nine directory authorities.
+pub fn authority_votes() -> Vec<CString> {
+    let votes: &[&str] = &[
+        "Cons=1-2 Desc=1-2 DirCache=1-2 HSDir=1-2 HSIntro=3-4 HSRend=1-2 \
+         Link=1-5 LinkAuth=1,3 Microdesc=1-2 Relay=1-2",
+        "Cons=1-2 Desc=1-2 DirCache=1-2 HSDir=1-2 HSIntro=3-4 HSRend=1-2 \
+         Link=1-5 LinkAuth=1,3 Microdesc=1-2 Relay=1-2",
+        "Cons=1-2 Desc=1-2 DirCache=1-2 HSDir=1-2 HSIntro=3-4 HSRend=1-2 \
+         Link=1-5 LinkAuth=1,3 Microdesc=1-2 Relay=1-2",
+        "Cons=1-2 Desc=1-2 DirCache=1-2 HSDir=1-2 HSIntro=3-4 HSRend=1-2 \
+         Link=1-5 LinkAuth=1,3 Microdesc=1-2 Relay=1-2",
+        "Cons=1-2 Desc=1-2 DirCache=1-2 HSDir=1-2 HSIntro=3-4 HSRend=1-2 \
+         Link=1-5 LinkAuth=1,3 Microdesc=1-2 Relay=1-2",
+        "Cons=1-2 Desc=1-2 DirCache=1-2 HSDir=1-2 HSIntro=3 HSRend=1-2 \
+         Link=1-4 LinkAuth=1,3 Microdesc=1-2 Relay=1-2",
+        "Cons=1-2 Desc=1-2 DirCache=1-2 HSDir=1-2 HSIntro=3 HSRend=1-2 \
+         Link=1-4 LinkAuth=1,3 Microdesc=1-2 Relay=1-2",
+        "Cons=1-2 Desc=1-2 DirCache=1 HSDir=1 HSIntro=3 HSRend=1-2 \
+         Link=1-4 LinkAuth=1 Microdesc=1-2 Relay=1-2",
+        "Cons=1 Desc=1 DirCache=1 HSDir=1 HSIntro=3 HSRend=1 \
+         Link=1-4 LinkAuth=1 Microdesc=1 Relay=1-2",
+    ];
+
+    votes.iter().map(|v| CString::new(*v).unwrap()).collect()
+}
+
+/// Make a `Stringlist` of `strings`, as C would pass it to Rust.
+pub fn stringlist(strings: &[*const c_char]) -> Stringlist {
+    Stringlist {
+        list: strings.as_ptr(),
+        num_used: strings.len() as i32,
+        capacity: strings.len() as i32,
+    }
+}
+
+/// Total up the lengths of the votes by copying them, as
+/// `protover_compute_vote()` used to.
+pub fn copied_length(votes: &Stringlist) -> usize {
+    votes.get_list().iter().map(|v| v.len()).sum()
+}
+
+/// Total up the lengths of the votes, borrowing them.
+pub fn borrowed_length(votes: &Stringlist) -> usize {
+    votes.strs().filter_map(|v| v.ok()).map(|v| v.len()).sum()
+}
@@ -0,0 +1,206 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! A global allocator which makes every allocation in Rust through tor's
+//! allocator, so that tor's memory accounting and out-of-memory handling,
+//! and the OpenBSD malloc option, apply to Rust code too.
+//!
+//! This is only built with the `global_allocator` feature.  The `tor_rust`
+//! library, which is linked into tor, makes it Rust's global allocator when
+//! built with its own `global_allocator` feature, which tor's build enables
+//! when `TOR_RUST_FEATURES=global_allocator` is given to make.
+//! Nothing else built from these crates is linked with tor's C code, so they
+//! use the system allocator.
+
+use std::alloc::GlobalAlloc;
+use std::alloc::Layout;
+use std::cmp;
+use std::mem;
+use std::ptr;
+
+use libc::c_void;
+
+/// The alignment which `malloc()` guarantees for any allocation at least
+/// that large: 8 bytes on 32-bit platforms, and 16 on 64-bit ones.
+const MIN_ALIGN: usize = 2 * mem::size_of::<usize>();
+
+/// The size at which tor's allocation functions fail assertions.
+///
+/// C_RUST_COUPLED: src/common/torint.h `SIZE_T_CEILING`
+const SIZE_T_CEILING: usize = (isize::max_value() - 16) as usize;
+
+#[cfg(not(test))]
+extern "C" {
+    fn tor_malloc_(size: usize) -> *mut c_void;
+    fn tor_realloc_(ptr: *mut c_void, size: usize) -> *mut c_void;
+    fn tor_free_(ptr: *mut c_void);
+}
+
+// Defined only for tests, used for testing purposes, so that we don't need
+// to link to tor C files. Uses the system allocator
+#[cfg(test)]
+unsafe extern "C" fn tor_malloc_(size: usize) -> *mut c_void {
+    use libc::malloc;
+    malloc(size)
+}
+
+#[cfg(test)]
+unsafe extern "C" fn tor_realloc_(ptr: *mut c_void, size: usize) -> *mut c_void {
+    use libc::realloc;
+    realloc(ptr, size)
+}
+
+#[cfg(test)]
+unsafe extern "C" fn tor_free_(ptr: *mut c_void) {
+    use libc::free;
+    free(ptr)
+}
+
+/// An allocator which allocates through `tor_malloc_()`, `tor_realloc_()`
+/// and `tor_free_()`.
+///
+/// Those never return NULL: if tor runs out of memory, it logs an error and
+/// exits.  An allocation of `SIZE_T_CEILING` bytes or more would fail an
+/// assertion, so instead it fails here, as it would with the system
+/// allocator.
+pub struct TorAllocator;
+
+/// Whether `malloc()` alone aligns an allocation of `size` bytes well enough
+/// for `align`.
+fn malloc_aligns(size: usize, align: usize) -> bool {
+    align <= MIN_ALIGN && align <= size
+}
+
+unsafe impl GlobalAlloc for TorAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if malloc_aligns(layout.size(), layout.align()) {
+            if layout.size() >= SIZE_T_CEILING {
+                return ptr::null_mut();
+            }
+            return tor_malloc_(layout.size()) as *mut u8;
+        }
+
+        // Allocate enough to align the result, with room before it to
+        // remember where the allocation really started.  Since malloc()'s
+        // result is aligned to MIN_ALIGN, and `align` is a multiple of that,
+        // there are always at least MIN_ALIGN bytes of room.
+        let align: usize = cmp::max(layout.align(), MIN_ALIGN);
+        let size: usize = match layout.size().checked_add(align) {
+            Some(n) if n < SIZE_T_CEILING => n,
+            _ => return ptr::null_mut(),
+        };
+        let allocated: *mut u8 = tor_malloc_(size) as *mut u8;
+        if allocated.is_null() {
+            return ptr::null_mut();
+        }
+        let offset: usize = align - (allocated as usize & (align - 1));
+        let aligned: *mut u8 = allocated.offset(offset as isize);
+
+        *(aligned as *mut *mut u8).offset(-1) = allocated;
+        aligned
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if malloc_aligns(layout.size(), layout.align()) {
+            tor_free_(ptr as *mut c_void);
+        } else {
+            tor_free_(*(ptr as *mut *mut u8).offset(-1) as *mut c_void);
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if malloc_aligns(layout.size(), layout.align()) &&
+            malloc_aligns(new_size, layout.align())
+        {
+            if new_size >= SIZE_T_CEILING {
+                return ptr::null_mut();
+            }
+            return tor_realloc_(ptr as *mut c_void, new_size) as *mut u8;
+        }
+
+        let new_layout: Layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let new_ptr: *mut u8 = self.alloc(new_layout);
+
+        if !new_ptr.is_null() {
+            ptr::copy_nonoverlapping(ptr, new_ptr, cmp::min(layout.size(), new_size));
+            self.dealloc(ptr, layout);
+        }
+        new_ptr
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::alloc::GlobalAlloc;
+    use std::alloc::Layout;
+    use std::slice;
+
+    use global_allocator::TorAllocator;
+    use global_allocator::SIZE_T_CEILING;
+
+    /// Allocate with `layout`, check the alignment, and fill the allocation
+    /// with `fill`.
+    unsafe fn alloc_filled(layout: Layout, fill: u8) -> *mut u8 {
+        let ptr: *mut u8 = TorAllocator.alloc(layout);
+
+        assert!(!ptr.is_null());
+        assert_eq!(0, ptr as usize % layout.align(), "{:?} misaligned", layout);
+        slice::from_raw_parts_mut(ptr, layout.size()).iter_mut().for_each(|b| *b = fill);
+        ptr
+    }
+
+    #[test]
+    fn test_alloc_and_dealloc() {
+        for &size in [1, 3, 8, 16, 17, 100, 4096].iter() {
+            for &align in [1, 2, 8, 16, 32, 64, 4096].iter() {
+                let layout: Layout = Layout::from_size_align(size, align).unwrap();
+
+                unsafe {
+                    let ptr: *mut u8 = alloc_filled(layout, 0xab);
+                    TorAllocator.dealloc(ptr, layout);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_realloc_keeps_contents() {
+        for &align in [1, 8, 16, 64].iter() {
+            for &(old_size, new_size) in [(1, 100), (100, 1), (8, 4096), (4096, 24)].iter() {
+                let layout: Layout = Layout::from_size_align(old_size, align).unwrap();
+
+                unsafe {
+                    let ptr: *mut u8 = alloc_filled(layout, 0xcd);
+                    let new_ptr: *mut u8 = TorAllocator.realloc(ptr, layout, new_size);
+
+                    assert!(!new_ptr.is_null());
+                    assert_eq!(0, new_ptr as usize % align);
+
+                    let kept: &[u8] = slice::from_raw_parts(new_ptr, old_size.min(new_size));
+                    assert!(kept.iter().all(|b| *b == 0xcd));
+
+                    TorAllocator.dealloc(new_ptr,
+                                         Layout::from_size_align(new_size, align).unwrap());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_alloc_too_large() {
+        let layout: Layout = Layout::from_size_align(SIZE_T_CEILING, 1).unwrap();
+
+        unsafe {
+            assert!(TorAllocator.alloc(layout).is_null());
+
+            let layout: Layout = Layout::from_size_align(SIZE_T_CEILING - 32, 32).unwrap();
+            assert!(TorAllocator.alloc(layout).is_null());
+
+            let layout: Layout = Layout::from_size_align(16, 8).unwrap();
+            let ptr: *mut u8 = alloc_filled(layout, 0);
+
+            assert!(TorAllocator.realloc(ptr, layout, SIZE_T_CEILING).is_null());
+            TorAllocator.dealloc(ptr, layout);
+        }
+    }
+}
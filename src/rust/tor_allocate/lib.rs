@@ -5,11 +5,15 @@
 //! using tor's specified allocator. In doing so, this can be later freed
 //! from C.
 //!
-//! This is currently a temporary solution, we will later use tor's allocator
-//! by default for any allocation that occurs in Rust. However, as this will
-//! stabalize in 2018, we can use this as a temporary measure.
+//! With the `global_allocator` feature, every allocation that occurs in Rust
+//! uses tor's allocator, through `TorAllocator`.
 
 extern crate libc;
 
 mod tor_allocate;
 pub use tor_allocate::*;
+
+#[cfg(feature = "global_allocator")]
+mod global_allocator;
+#[cfg(feature = "global_allocator")]
+pub use global_allocator::*;
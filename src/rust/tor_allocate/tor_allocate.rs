@@ -3,10 +3,15 @@
 
 use libc::{c_char, c_void};
 use std::{ptr, slice, mem};
+use std::error::Error;
+use std::ffi::CStr;
+use std::fmt;
+use std::ops::Deref;
 
 #[cfg(not(test))]
 extern "C" {
     fn tor_malloc_(size: usize) -> *mut c_void;
+    fn tor_free_(ptr: *mut c_void);
 }
 
 // Defined only for tests, used for testing purposes, so that we don't need
@@ -17,6 +22,231 @@ unsafe extern "C" fn tor_malloc_(size: usize) -> *mut c_void {
     malloc(size)
 }
 
+#[cfg(test)]
+unsafe extern "C" fn tor_free_(ptr: *mut c_void) {
+    use libc::free;
+    free(ptr)
+}
+
+/// Allocate `size` bytes with tor_malloc_, and copy `bytes` into the start
+/// of them.  `size` must be at least one, and at least `bytes.len()`.
+fn allocate_and_copy(bytes: &[u8], size: usize) -> *mut u8 {
+    let dest = unsafe { tor_malloc_(size) as *mut u8 };
+
+    assert!(!dest.is_null());
+    unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), dest, bytes.len()) };
+    dest
+}
+
+/// An error from making a `TorOwnedCString` of bytes with a NUL in them.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct InteriorNulError {
+    position: usize,
+}
+
+impl InteriorNulError {
+    /// The position of the first NUL byte.
+    pub fn nul_position(&self) -> usize {
+        self.position
+    }
+}
+
+impl fmt::Display for InteriorNulError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NUL byte found in string at position {}", self.position)
+    }
+}
+
+impl Error for InteriorNulError {}
+
+/// A NUL-terminated string allocated with tor_malloc_, which can be handed
+/// to C with `into_raw()` for C to free with `tor_free()`, or taken back from
+/// C with `from_raw()`.  It is freed with tor_free_ when dropped.
+///
+/// Unlike a `CString`, whose memory is Rust's to free, this is safe to return
+/// from an FFI function whose caller frees the result.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate libc;
+/// # extern crate tor_allocate;
+/// use tor_allocate::TorOwnedCString;
+/// #
+/// # // Stand in for tor's allocator, since we aren't linked with it.
+/// # #[no_mangle]
+/// # pub unsafe extern "C" fn tor_malloc_(size: usize) -> *mut libc::c_void {
+/// #     libc::malloc(size)
+/// # }
+/// # #[no_mangle]
+/// # pub unsafe extern "C" fn tor_free_(ptr: *mut libc::c_void) {
+/// #     libc::free(ptr)
+/// # }
+///
+/// # fn main() {
+/// let owned: TorOwnedCString = TorOwnedCString::new("Link=1-5").unwrap();
+/// assert_eq!(Ok("Link=1-5"), owned.to_str());
+///
+/// // C would free this with tor_free().
+/// let ptr = owned.into_raw();
+/// # unsafe { TorOwnedCString::from_raw(ptr) };
+/// # }
+/// ```
+pub struct TorOwnedCString {
+    ptr: *mut c_char,
+}
+
+impl TorOwnedCString {
+    /// Copy `bytes` into a new C string.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InteriorNulError` if `bytes` contains a NUL byte, which C
+    /// would take as the end of the string.
+    pub fn new<T: AsRef<[u8]>>(bytes: T) -> Result<TorOwnedCString, InteriorNulError> {
+        let bytes: &[u8] = bytes.as_ref();
+
+        if let Some(position) = bytes.iter().position(|&b| b == 0) {
+            return Err(InteriorNulError { position });
+        }
+        // A slice can't be as long as usize::MAX, so this doesn't overflow.
+        let dest: *mut u8 = allocate_and_copy(bytes, bytes.len() + 1);
+
+        unsafe { *dest.offset(bytes.len() as isize) = 0 };
+        Ok(TorOwnedCString { ptr: dest as *mut c_char })
+    }
+
+    /// Take ownership of a NUL-terminated string, such as one from C, which
+    /// was allocated with `tor_malloc()`.  Returns `None` if `ptr` is NULL.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be NULL or a NUL-terminated string allocated with
+    /// `tor_malloc()`, which nothing else will free or use afterwards.
+    pub unsafe fn from_raw(ptr: *mut c_char) -> Option<TorOwnedCString> {
+        if ptr.is_null() {
+            return None;
+        }
+        Some(TorOwnedCString { ptr })
+    }
+
+    /// Hand over this string, usually to C, which must free it with
+    /// `tor_free()`.
+    pub fn into_raw(self) -> *mut c_char {
+        let ptr: *mut c_char = self.ptr;
+
+        mem::forget(self);
+        ptr
+    }
+
+    /// Borrow this string as a `CStr`.
+    pub fn as_c_str(&self) -> &CStr {
+        unsafe { CStr::from_ptr(self.ptr) }
+    }
+}
+
+/// An empty string.
+impl Default for TorOwnedCString {
+    fn default() -> TorOwnedCString {
+        TorOwnedCString::from(<&CStr>::default())
+    }
+}
+
+impl<'a> From<&'a CStr> for TorOwnedCString {
+    fn from(src: &'a CStr) -> TorOwnedCString {
+        let bytes: &[u8] = src.to_bytes_with_nul();
+
+        TorOwnedCString { ptr: allocate_and_copy(bytes, bytes.len()) as *mut c_char }
+    }
+}
+
+impl Deref for TorOwnedCString {
+    type Target = CStr;
+
+    fn deref(&self) -> &CStr {
+        self.as_c_str()
+    }
+}
+
+impl Drop for TorOwnedCString {
+    fn drop(&mut self) {
+        unsafe { tor_free_(self.ptr as *mut c_void) };
+    }
+}
+
+impl fmt::Debug for TorOwnedCString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_c_str(), f)
+    }
+}
+
+/// A buffer of bytes allocated with tor_malloc_, which can be handed to C
+/// with `into_raw_parts()` for C to free with `tor_free()`, or taken back
+/// from C with `from_raw_parts()`.  It is freed with tor_free_ when dropped.
+pub struct TorOwnedBytes {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl TorOwnedBytes {
+    /// Take ownership of `len` bytes at `ptr`, such as a buffer from C, which
+    /// was allocated with `tor_malloc()`.  Returns `None` if `ptr` is NULL.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be NULL, or point to at least `len` bytes allocated with
+    /// `tor_malloc()`, which nothing else will free or use afterwards.
+    pub unsafe fn from_raw_parts(ptr: *mut u8, len: usize) -> Option<TorOwnedBytes> {
+        if ptr.is_null() {
+            return None;
+        }
+        Some(TorOwnedBytes { ptr, len })
+    }
+
+    /// Hand over this buffer and its length, usually to C, which must free
+    /// it with `tor_free()`.  The buffer is never NULL, even if it's empty.
+    pub fn into_raw_parts(self) -> (*mut u8, usize) {
+        let parts: (*mut u8, usize) = (self.ptr, self.len);
+
+        mem::forget(self);
+        parts
+    }
+}
+
+impl<'a> From<&'a [u8]> for TorOwnedBytes {
+    fn from(src: &'a [u8]) -> TorOwnedBytes {
+        // tor_malloc_(0) would allocate a byte anyway, but ask for one, so
+        // that the system allocator stand-in for tests doesn't return NULL.
+        TorOwnedBytes { ptr: allocate_and_copy(src, src.len().max(1)), len: src.len() }
+    }
+}
+
+impl From<Vec<u8>> for TorOwnedBytes {
+    fn from(src: Vec<u8>) -> TorOwnedBytes {
+        TorOwnedBytes::from(&src[..])
+    }
+}
+
+impl Deref for TorOwnedBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for TorOwnedBytes {
+    fn drop(&mut self) {
+        unsafe { tor_free_(self.ptr as *mut c_void) };
+    }
+}
+
+impl fmt::Debug for TorOwnedBytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
 /// Allocate memory using tor_malloc_ and copy an existing string into the
 /// allocated buffer, returning a pointer that can later be called in C.
 ///
@@ -28,6 +258,8 @@ unsafe extern "C" fn tor_malloc_(size: usize) -> *mut c_void {
 ///
 /// A `*mut c_char` that should be freed by tor_free in C
 ///
+/// Prefer `TorOwnedCString`, which can't silently truncate a string with a
+/// NUL in it, or leak it if it isn't handed to C.
 pub fn allocate_and_copy_string(src: &String) -> *mut c_char {
     let bytes: &[u8] = src.as_bytes();
 
@@ -94,4 +326,77 @@ mod test {
 
         unsafe { free(allocated_empty as *mut c_void) };
     }
+
+    #[test]
+    fn test_tor_owned_c_string() {
+        use std::ffi::CStr;
+        use std::ffi::CString;
+
+        use tor_allocate::InteriorNulError;
+        use tor_allocate::TorOwnedCString;
+
+        let from_string = TorOwnedCString::new(String::from("foo bar biz")).unwrap();
+        assert_eq!(Ok("foo bar biz"), from_string.to_str());
+
+        let from_vec = TorOwnedCString::new(vec![b'a', b'b']).unwrap();
+        assert_eq!(b"ab\0", from_vec.to_bytes_with_nul());
+
+        let c_string = CString::new("Link=1-5").unwrap();
+        let from_c_str = TorOwnedCString::from(c_string.as_c_str());
+        assert_eq!(c_string.as_c_str(), &*from_c_str);
+        assert_eq!("\"Link=1-5\"", format!("{:?}", from_c_str));
+
+        let empty = TorOwnedCString::default();
+        assert_eq!(Ok(""), empty.to_str());
+
+        let error: InteriorNulError = TorOwnedCString::new("a\0b").unwrap_err();
+        assert_eq!(1, error.nul_position());
+
+        // Hand it over to C, and take it back.
+        let ptr = from_string.into_raw();
+        assert_eq!(Ok("foo bar biz"), unsafe { CStr::from_ptr(ptr) }.to_str());
+
+        let reclaimed = unsafe { TorOwnedCString::from_raw(ptr) }.unwrap();
+        assert_eq!(Ok("foo bar biz"), reclaimed.to_str());
+
+        assert!(unsafe { TorOwnedCString::from_raw(::std::ptr::null_mut()) }.is_none());
+    }
+
+    #[test]
+    fn test_tor_owned_c_string_from_c() {
+        use libc::{malloc, c_char};
+        use std::ptr;
+
+        use tor_allocate::TorOwnedCString;
+
+        // A string as C would allocate it, with tor_malloc().
+        let ptr = unsafe { malloc(4) as *mut c_char };
+        unsafe { ptr::copy_nonoverlapping(b"foo\0".as_ptr() as *const c_char, ptr, 4) };
+
+        let owned = unsafe { TorOwnedCString::from_raw(ptr) }.unwrap();
+        assert_eq!(Ok("foo"), owned.to_str());
+    }
+
+    #[test]
+    fn test_tor_owned_bytes() {
+        use tor_allocate::TorOwnedBytes;
+
+        let from_vec = TorOwnedBytes::from(vec![1, 0, 2]);
+        assert_eq!(&[1, 0, 2], &*from_vec);
+        assert_eq!("[1, 0, 2]", format!("{:?}", from_vec));
+
+        let empty = TorOwnedBytes::from(&b""[..]);
+        assert!(empty.is_empty());
+
+        let (ptr, len) = empty.into_raw_parts();
+        assert!(!ptr.is_null());
+        assert_eq!(0, len);
+        unsafe { TorOwnedBytes::from_raw_parts(ptr, len) };
+
+        let (ptr, len) = from_vec.into_raw_parts();
+        let reclaimed = unsafe { TorOwnedBytes::from_raw_parts(ptr, len) }.unwrap();
+        assert_eq!(&[1, 0, 2], &*reclaimed);
+
+        assert!(unsafe { TorOwnedBytes::from_raw_parts(::std::ptr::null_mut(), 3) }.is_none());
+    }
 }
@@ -0,0 +1,25 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Logging from Rust through tor's log subsystem.
+//!
+//! The `log_warn!`, `log_notice!`, `log_info!` and `log_debug!` macros (and
+//! `log_err!` and `log_fn!`) work like their C namesakes, with one of tor's
+//! log domains, but take the name of the function they are used in, since
+//! Rust has no `__FUNCTION__`, and Rust format strings.  Like C's
+//! `log_fn_ratelim()`, `log_fn_ratelim!` and the `log_*_ratelim!` macros use
+//! a `RateLimit` to limit how often a message can appear.
+//!
+//! Tests aren't linked with tor's C logging, so they use the
+//! `tor_log_testing` crate, which stands in for it, and can capture
+//! messages.
+
+extern crate libc;
+
+#[macro_use]
+mod tor_log;
+#[macro_use]
+mod ratelim;
+
+pub use tor_log::*;
+pub use ratelim::*;
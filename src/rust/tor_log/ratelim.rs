@@ -0,0 +1,203 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Rate-limiting messages which could otherwise flood the logs.
+
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+/// How many calls a `RateLimit` counts before it stops counting, and just
+/// says there were over that many.
+///
+/// C_RUST_COUPLED: src/common/util.h `RATELIM_TOOMANY`
+pub const RATELIM_TOOMANY: usize = 16 * 1000 * 1000;
+
+/// A `RateLimit` remembers how often an event is occurring, and how often
+/// it's allowed to occur, like C's `ratelim_t`.  Typical usage is something
+/// like:
+///
+/// ```
+/// #[macro_use]
+/// extern crate tor_log;
+///
+/// use tor_log::RateLimit;
+/// use tor_log::LD_GENERAL;
+/// #
+/// # #[no_mangle]
+/// # pub static log_global_min_severity_: i32 = 5;
+/// # #[no_mangle]
+/// # pub extern "C" fn log_fn_(_: i32, _: u32, _: *const i8, _: *const i8, _: *const i8) {}
+/// # fn possibly_very_frequent_event() -> bool { true }
+///
+/// # fn main() {
+/// if possibly_very_frequent_event() {
+///     static WARNING_LIMIT: RateLimit = RateLimit::new(300);
+///
+///     log_warn_ratelim!(&WARNING_LIMIT, LD_GENERAL, main, "The event occurred!");
+/// }
+/// # }
+/// ```
+///
+/// C_RUST_COUPLED: src/common/util.c `rate_limit_is_ready()`,
+/// `rate_limit_log()`
+pub struct RateLimit {
+    /// How many seconds must pass between events which are allowed.
+    rate: usize,
+    /// When the last event which was allowed happened, in seconds since the
+    /// epoch.
+    last_allowed: AtomicUsize,
+    /// How many events have not been allowed since then.
+    n_calls_since_last_time: AtomicUsize,
+}
+
+impl RateLimit {
+    /// Make a `RateLimit` which allows an event every `rate` seconds.
+    pub const fn new(rate: u32) -> RateLimit {
+        RateLimit {
+            rate: rate as usize,
+            last_allowed: AtomicUsize::new(0),
+            n_calls_since_last_time: AtomicUsize::new(0),
+        }
+    }
+
+    /// If this is ready at `now`, return the number of calls (including this
+    /// one!) since it was last ready.  Otherwise return 0.
+    fn is_ready(&self, now: i64) -> usize {
+        // Times before the epoch are treated as the epoch.
+        let now: usize = if now < 0 { 0 } else { now as usize };
+        let last_allowed: usize = self.last_allowed.load(Ordering::SeqCst);
+
+        // If another thread gets here first, it gets the event, and we count
+        // this one as not allowed.
+        if last_allowed.saturating_add(self.rate) <= now &&
+            self.last_allowed.compare_exchange(last_allowed, now, Ordering::SeqCst,
+                                               Ordering::SeqCst).is_ok()
+        {
+            return self.n_calls_since_last_time.swap(0, Ordering::SeqCst) + 1;
+        }
+
+        // Count this call, unless there have already been too many.
+        let mut n: usize = self.n_calls_since_last_time.load(Ordering::SeqCst);
+
+        while n <= RATELIM_TOOMANY {
+            match self.n_calls_since_last_time.compare_exchange_weak(n, n + 1, Ordering::SeqCst,
+                                                                    Ordering::SeqCst) {
+                Ok(_) => break,
+                Err(current) => n = current,
+            }
+        }
+        0
+    }
+
+    /// If this is ready at `now`, a time in seconds since the epoch, return a
+    /// string saying how many messages were suppressed, suitable to append to
+    /// a log message.  Otherwise return `None`.
+    pub fn rate_limit_log(&self, now: i64) -> Option<String> {
+        match self.is_ready(now) {
+            0 => None,
+            1 => Some(String::new()),
+            n => {
+                let opt_over: &str = if n >= RATELIM_TOOMANY { "over " } else { "" };
+
+                Some(format!(" [{}{} similar message(s) suppressed in last {} seconds]",
+                             opt_over, n - 1, self.rate))
+            },
+        }
+    }
+
+    /// As `rate_limit_log()`, at the current time.
+    pub fn rate_limit_log_now(&self) -> Option<String> {
+        let now: i64 = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(n) => n.as_secs() as i64,
+            Err(_) => 0,
+        };
+
+        self.rate_limit_log(now)
+    }
+}
+
+/// As `log_fn!`, but use `ratelim`, a `&RateLimit`, to control how often
+/// messages can appear, like C's `log_fn_ratelim()`.  A message that was
+/// allowed says how many were suppressed before it.
+#[macro_export]
+macro_rules! log_fn_ratelim {
+    ($ratelim:expr, $severity:expr, $domain:expr, $funcname:ident, $($arg:tt)+) => ({
+        let ratelim: &$crate::RateLimit = $ratelim;
+        let severity: $crate::LogSeverity = $severity;
+
+        if $crate::log_is_enabled(severity) {
+            if let Some(suppressed) = ratelim.rate_limit_log_now() {
+                let message: String = format!($($arg)+) + &suppressed;
+
+                $crate::log_message(severity, $domain, stringify!($funcname), &message);
+            }
+        }
+    })
+}
+
+/// As `log_warn!`, but rate-limited, like `log_fn_ratelim!`.
+#[macro_export]
+macro_rules! log_warn_ratelim {
+    ($ratelim:expr, $domain:expr, $funcname:ident, $($arg:tt)+) => (
+        log_fn_ratelim!($ratelim, $crate::LogSeverity::Warn, $domain, $funcname, $($arg)+)
+    )
+}
+
+/// As `log_notice!`, but rate-limited, like `log_fn_ratelim!`.
+#[macro_export]
+macro_rules! log_notice_ratelim {
+    ($ratelim:expr, $domain:expr, $funcname:ident, $($arg:tt)+) => (
+        log_fn_ratelim!($ratelim, $crate::LogSeverity::Notice, $domain, $funcname, $($arg)+)
+    )
+}
+
+/// As `log_info!`, but rate-limited, like `log_fn_ratelim!`.
+#[macro_export]
+macro_rules! log_info_ratelim {
+    ($ratelim:expr, $domain:expr, $funcname:ident, $($arg:tt)+) => (
+        log_fn_ratelim!($ratelim, $crate::LogSeverity::Info, $domain, $funcname, $($arg)+)
+    )
+}
+
+/// As `log_debug!`, but rate-limited, like `log_fn_ratelim!`.
+#[macro_export]
+macro_rules! log_debug_ratelim {
+    ($ratelim:expr, $domain:expr, $funcname:ident, $($arg:tt)+) => (
+        log_fn_ratelim!($ratelim, $crate::LogSeverity::Debug, $domain, $funcname, $($arg)+)
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit_log() {
+        let limit: RateLimit = RateLimit::new(300);
+
+        assert_eq!(Some(String::new()), limit.rate_limit_log(1000));
+        assert_eq!(None, limit.rate_limit_log(1001));
+        assert_eq!(None, limit.rate_limit_log(1299));
+        assert_eq!(Some(" [2 similar message(s) suppressed in last 300 seconds]".to_string()),
+                   limit.rate_limit_log(1300));
+        assert_eq!(None, limit.rate_limit_log(1300));
+        assert_eq!(Some(" [1 similar message(s) suppressed in last 300 seconds]".to_string()),
+                   limit.rate_limit_log(2000));
+        assert_eq!(Some(String::new()), limit.rate_limit_log(2300));
+    }
+
+    #[test]
+    fn test_rate_limit_too_many() {
+        let limit: RateLimit = RateLimit::new(300);
+
+        limit.n_calls_since_last_time.store(RATELIM_TOOMANY, Ordering::SeqCst);
+        assert_eq!(None, limit.rate_limit_log(0));
+        assert_eq!(None, limit.rate_limit_log(0));
+        assert_eq!(RATELIM_TOOMANY + 1, limit.n_calls_since_last_time.load(Ordering::SeqCst));
+        assert_eq!(Some(format!(" [over {} similar message(s) suppressed in last 300 seconds]",
+                                RATELIM_TOOMANY + 1)),
+                   limit.rate_limit_log(300));
+    }
+}
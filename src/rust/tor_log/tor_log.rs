@@ -0,0 +1,212 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Severities, domains, and the macros which log with them.
+
+use std::borrow::Cow;
+use std::ffi::CString;
+
+use libc::c_char;
+use libc::c_int;
+
+/// How severe a message is.  The more severe, the lower its number, so a
+/// severity is "at least" another if it is less than or equal to it.
+///
+/// C_RUST_COUPLED: src/common/torlog.h `LOG_ERR`, `LOG_WARN`, `LOG_NOTICE`,
+/// `LOG_INFO`, `LOG_DEBUG` (which are syslog's values, where it exists)
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum LogSeverity {
+    Err = 3,
+    Warn = 4,
+    Notice = 5,
+    Info = 6,
+    Debug = 7,
+}
+
+/// A set of tor's log domains, saying what a message is about.
+///
+/// C_RUST_COUPLED: src/common/torlog.h `log_domain_mask_t`
+pub type LogDomain = u32;
+
+// C_RUST_COUPLED: src/common/torlog.h `LD_*`
+pub const LD_GENERAL: LogDomain = 1 << 0;
+pub const LD_CRYPTO: LogDomain = 1 << 1;
+pub const LD_NET: LogDomain = 1 << 2;
+pub const LD_CONFIG: LogDomain = 1 << 3;
+pub const LD_FS: LogDomain = 1 << 4;
+pub const LD_PROTOCOL: LogDomain = 1 << 5;
+pub const LD_MM: LogDomain = 1 << 6;
+pub const LD_HTTP: LogDomain = 1 << 7;
+pub const LD_APP: LogDomain = 1 << 8;
+pub const LD_CONTROL: LogDomain = 1 << 9;
+pub const LD_CIRC: LogDomain = 1 << 10;
+pub const LD_REND: LogDomain = 1 << 11;
+pub const LD_BUG: LogDomain = 1 << 12;
+pub const LD_DIR: LogDomain = 1 << 13;
+pub const LD_DIRSERV: LogDomain = 1 << 14;
+pub const LD_OR: LogDomain = 1 << 15;
+pub const LD_EDGE: LogDomain = 1 << 16;
+pub const LD_EXIT: LogDomain = LD_EDGE;
+pub const LD_ACCT: LogDomain = 1 << 17;
+pub const LD_HIST: LogDomain = 1 << 18;
+pub const LD_HANDSHAKE: LogDomain = 1 << 19;
+pub const LD_HEARTBEAT: LogDomain = 1 << 20;
+pub const LD_CHANNEL: LogDomain = 1 << 21;
+pub const LD_SCHED: LogDomain = 1 << 22;
+pub const LD_GUARD: LogDomain = 1 << 23;
+pub const LD_CONSDIFF: LogDomain = 1 << 24;
+pub const LD_DOS: LogDomain = 1 << 25;
+/// Don't prefix the message with the name of the function which logged it.
+pub const LD_NOFUNCNAME: LogDomain = 1 << 30;
+/// Don't send the message to any controller which asked for log events.
+pub const LD_NOCB: LogDomain = 1 << 31;
+
+extern "C" {
+    static log_global_min_severity_: c_int;
+
+    fn log_fn_(severity: c_int, domain: LogDomain, funcname: *const c_char,
+               format: *const c_char, ...);
+}
+
+/// Whether a message of `severity` would be logged anywhere, so that it is
+/// worth formatting.
+pub fn log_is_enabled(severity: LogSeverity) -> bool {
+    severity as c_int <= unsafe { log_global_min_severity_ }
+}
+
+/// Log `message`, on behalf of the function `funcname`.  Use the `log_*!`
+/// macros instead, which only format their message if it will be logged.
+///
+/// A NUL in `message` is logged as `\0`, rather than ending it.
+pub fn log_message(severity: LogSeverity, domain: LogDomain, funcname: &str, message: &str) {
+    let message: Cow<str> = if message.contains('\0') {
+        Cow::Owned(message.replace('\0', "\\0"))
+    } else {
+        Cow::Borrowed(message)
+    };
+
+    send_log(severity, domain, funcname, &message);
+}
+
+fn send_log(severity: LogSeverity, domain: LogDomain, funcname: &str, message: &str) {
+    // Neither of these contains a NUL, unless a function name somehow does.
+    let funcname: CString = CString::new(funcname).unwrap_or_default();
+    let message: CString = match CString::new(message) {
+        Ok(n) => n,
+        Err(_) => return,
+    };
+
+    unsafe {
+        log_fn_(severity as c_int, domain, funcname.as_ptr(),
+                b"%s\0".as_ptr() as *const c_char, message.as_ptr());
+    }
+}
+
+/// Log a message with `severity` in `domain`, formatted like `format!()`,
+/// on behalf of the function `funcname`, which should be the name of the
+/// function this is used in, as `__FUNCTION__` is in C.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate tor_log;
+/// #
+/// # // Stand in for tor's logging, since we aren't linked with it.
+/// # #[no_mangle]
+/// # pub static log_global_min_severity_: i32 = 5;
+/// # #[no_mangle]
+/// # pub extern "C" fn log_fn_(_: i32, _: u32, _: *const i8, _: *const i8, _: *const i8) {}
+///
+/// use tor_log::LogSeverity;
+/// use tor_log::LD_GENERAL;
+///
+/// # fn main() {
+/// log_fn!(LogSeverity::Notice, LD_GENERAL, main, "Bootstrapped {}%", 100);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! log_fn {
+    ($severity:expr, $domain:expr, $funcname:ident, $($arg:tt)+) => ({
+        let severity: $crate::LogSeverity = $severity;
+
+        if $crate::log_is_enabled(severity) {
+            $crate::log_message(severity, $domain, stringify!($funcname), &format!($($arg)+));
+        }
+    })
+}
+
+/// Log a message for when tor can no longer proceed, like C's `log_err()`.
+#[macro_export]
+macro_rules! log_err {
+    ($domain:expr, $funcname:ident, $($arg:tt)+) => (
+        log_fn!($crate::LogSeverity::Err, $domain, $funcname, $($arg)+)
+    )
+}
+
+/// Log a message for when something has gone wrong, like C's `log_warn()`.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate tor_log;
+/// #
+/// # // Stand in for tor's logging, since we aren't linked with it.
+/// # #[no_mangle]
+/// # pub static log_global_min_severity_: i32 = 5;
+/// # #[no_mangle]
+/// # pub extern "C" fn log_fn_(_: i32, _: u32, _: *const i8, _: *const i8, _: *const i8) {}
+///
+/// use tor_log::LD_PROTOCOL;
+///
+/// # fn main() {
+/// let list: &str = "Link=1-";
+///
+/// log_warn!(LD_PROTOCOL, main, "Unparseable protocol list {:?}", list);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! log_warn {
+    ($domain:expr, $funcname:ident, $($arg:tt)+) => (
+        log_fn!($crate::LogSeverity::Warn, $domain, $funcname, $($arg)+)
+    )
+}
+
+/// Log a message which the user will probably care about, but which isn't an
+/// error, like C's `log_notice()`.
+#[macro_export]
+macro_rules! log_notice {
+    ($domain:expr, $funcname:ident, $($arg:tt)+) => (
+        log_fn!($crate::LogSeverity::Notice, $domain, $funcname, $($arg)+)
+    )
+}
+
+/// Log a message which may appear frequently in normal operation, like C's
+/// `log_info()`.
+#[macro_export]
+macro_rules! log_info {
+    ($domain:expr, $funcname:ident, $($arg:tt)+) => (
+        log_fn!($crate::LogSeverity::Info, $domain, $funcname, $($arg)+)
+    )
+}
+
+/// Log a message of interest only to developers, like C's `log_debug()`.
+#[macro_export]
+macro_rules! log_debug {
+    ($domain:expr, $funcname:ident, $($arg:tt)+) => (
+        log_fn!($crate::LogSeverity::Debug, $domain, $funcname, $($arg)+)
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_severity_order() {
+        assert!(LogSeverity::Err < LogSeverity::Warn);
+        assert!(LogSeverity::Info < LogSeverity::Debug);
+        assert_eq!(4, LogSeverity::Warn as c_int);
+    }
+}
@@ -0,0 +1,312 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Capturing messages, so that tests can check what was logged, like
+//! src/test/log_test_helpers.c does for C.
+//!
+//! This crate stands in for tor's C logging, which tests aren't linked with,
+//! by defining `log_global_min_severity_` and `log_fn_` itself.  It is only
+//! for tests: use it as a dev-dependency, and link it with
+//! `extern crate tor_log_testing;` in any test which logs, even if it
+//! doesn't check what was logged.
+//!
+//! Messages are only captured on the thread which set up capturing, and only
+//! one thread captures at a time, so that tests running at the same time
+//! don't see each other's messages.
+//!
+//! # Examples
+//!
+//! ```
+//! #[macro_use]
+//! extern crate tor_log;
+//! extern crate tor_log_testing;
+//!
+//! use tor_log::LogSeverity;
+//! use tor_log::LD_NET;
+//! use tor_log_testing::*;
+//!
+//! # fn main() {
+//! setup_capture_of_logs(LogSeverity::Info);
+//!
+//! log_warn!(LD_NET, main, "Something went wrong");
+//! assert!(saved_log_has_message("Something went wrong"));
+//! assert_eq!(1, saved_log_n_entries());
+//!
+//! teardown_capture_of_logs();
+//! # }
+//! ```
+
+extern crate tor_log;
+extern crate libc;
+
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::ptr;
+use std::sync::atomic::AtomicI32;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::Once;
+
+use libc::c_char;
+use libc::c_int;
+
+use tor_log::LogDomain;
+use tor_log::LogSeverity;
+
+/// The least severe severity which is logged, which `tor_log` reads to
+/// decide whether a message is worth formatting: that of the thread
+/// capturing messages, or 0, so that nothing is logged, if none is.
+///
+/// C_RUST_COUPLED: src/common/log.c `log_global_min_severity_`
+#[no_mangle]
+pub static log_global_min_severity_: AtomicI32 = AtomicI32::new(0);
+
+/// Capture a message which `tor_log` sends to C, which always formats it
+/// with `"%s"`, so that it is the only argument after `format`.
+///
+/// # Safety
+///
+/// `funcname` and `message` must be NUL-terminated strings.
+///
+/// C_RUST_COUPLED: src/common/log.c `log_fn_()`
+#[no_mangle]
+pub unsafe extern "C" fn log_fn_(severity: c_int, domain: LogDomain, funcname: *const c_char,
+                                 _format: *const c_char, message: *const c_char) {
+    let severity: LogSeverity = match severity {
+        3 => LogSeverity::Err,
+        4 => LogSeverity::Warn,
+        5 => LogSeverity::Notice,
+        6 => LogSeverity::Info,
+        _ => LogSeverity::Debug,
+    };
+
+    save_log(severity, domain, &CStr::from_ptr(funcname).to_string_lossy(),
+             &CStr::from_ptr(message).to_string_lossy());
+}
+
+/// A message which was captured.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SavedLogEntry {
+    pub severity: LogSeverity,
+    pub domain: LogDomain,
+    pub funcname: String,
+    pub message: String,
+}
+
+/// The messages captured on this thread, and the least severe severity to
+/// capture, if capturing has been set up.
+struct Capture {
+    level: LogSeverity,
+    saved: Vec<SavedLogEntry>,
+    /// Held while this thread captures, so that no other thread does.
+    _turn: MutexGuard<'static, ()>,
+}
+
+thread_local!(static CAPTURE: RefCell<Option<Capture>> = RefCell::new(None));
+
+static TURN_INIT: Once = Once::new();
+
+/// Held by the thread which is capturing messages.  Use `turn_lock()`
+/// rather than this.
+static mut TURN: *const Mutex<()> = ptr::null();
+
+/// Get the lock held by the thread which is capturing messages, making it
+/// the first time we're called.
+fn turn_lock() -> &'static Mutex<()> {
+    // `TURN` is only written once, by the `call_once()` closure, and only
+    // read after `call_once()` has returned.
+    unsafe {
+        TURN_INIT.call_once(|| {
+            TURN = Box::into_raw(Box::new(Mutex::new(())));
+        });
+        &*TURN
+    }
+}
+
+/// Capture every message logged on this thread at `level` or more severe,
+/// until `teardown_capture_of_logs()`.  If another thread is capturing
+/// messages, wait until it stops.
+///
+/// # Panics
+///
+/// If capturing is already set up on this thread.
+pub fn setup_capture_of_logs(level: LogSeverity) {
+    CAPTURE.with(|c| {
+        assert!(c.borrow().is_none(), "Capturing of logs was already set up");
+
+        // A test which panicked while capturing has still stopped capturing.
+        let turn: MutexGuard<'static, ()> = turn_lock().lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        log_global_min_severity_.store(level as c_int, Ordering::SeqCst);
+        *c.borrow_mut() = Some(Capture { level, saved: Vec::new(), _turn: turn });
+    });
+}
+
+/// Stop capturing messages on this thread, and forget those captured.  It is
+/// safe to call this more than once.
+pub fn teardown_capture_of_logs() {
+    CAPTURE.with(|c| {
+        if c.borrow().is_some() {
+            log_global_min_severity_.store(0, Ordering::SeqCst);
+        }
+        *c.borrow_mut() = None;
+    });
+}
+
+/// Capture a message, if messages of its severity are being captured.
+fn save_log(severity: LogSeverity, domain: LogDomain, funcname: &str, message: &str) {
+    CAPTURE.with(|c| {
+        if let Some(ref mut capture) = *c.borrow_mut() {
+            if severity <= capture.level {
+                capture.saved.push(SavedLogEntry {
+                    severity,
+                    domain,
+                    funcname: funcname.to_string(),
+                    message: message.to_string(),
+                });
+            }
+        }
+    })
+}
+
+/// Get every message captured on this thread, oldest first.
+pub fn saved_logs() -> Vec<SavedLogEntry> {
+    CAPTURE.with(|c| match *c.borrow() {
+        Some(ref capture) => capture.saved.clone(),
+        None => Vec::new(),
+    })
+}
+
+/// Forget the messages captured so far on this thread, but keep capturing.
+pub fn clean_saved_logs() {
+    CAPTURE.with(|c| {
+        if let Some(ref mut capture) = *c.borrow_mut() {
+            capture.saved.clear();
+        }
+    })
+}
+
+/// Whether any message captured was exactly `message`.
+pub fn saved_log_has_message(message: &str) -> bool {
+    saved_logs().iter().any(|e| e.message == message)
+}
+
+/// Whether any message captured contained `message`.
+pub fn saved_log_has_message_containing(message: &str) -> bool {
+    saved_logs().iter().any(|e| e.message.contains(message))
+}
+
+/// Whether any message captured was of `severity`.
+pub fn saved_log_has_severity(severity: LogSeverity) -> bool {
+    saved_logs().iter().any(|e| e.severity == severity)
+}
+
+/// Whether any message was captured.
+pub fn saved_log_has_entry() -> bool {
+    saved_log_n_entries() > 0
+}
+
+/// How many messages were captured.
+pub fn saved_log_n_entries() -> usize {
+    saved_logs().len()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tor_log::*;
+
+    fn not_formatted() -> String {
+        panic!("A message which won't be logged shouldn't be formatted");
+    }
+
+    #[test]
+    fn test_capture_only_when_set_up() {
+        log_message(LogSeverity::Warn, LD_NET, "f", "before");
+        assert!(!saved_log_has_entry());
+
+        setup_capture_of_logs(LogSeverity::Notice);
+        log_message(LogSeverity::Warn, LD_NET, "f", "during");
+        log_message(LogSeverity::Info, LD_NET, "f", "too verbose");
+        assert_eq!(1, saved_log_n_entries());
+        assert!(saved_log_has_message_containing("dur"));
+        assert!(!saved_log_has_message("dur"));
+        assert!(saved_log_has_severity(LogSeverity::Warn));
+
+        clean_saved_logs();
+        assert!(!saved_log_has_entry());
+        log_message(LogSeverity::Err, LD_NET, "f", "still capturing");
+        assert!(saved_log_has_entry());
+
+        teardown_capture_of_logs();
+        teardown_capture_of_logs();
+        log_message(LogSeverity::Warn, LD_NET, "f", "after");
+        assert!(saved_logs().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "already set up")]
+    fn test_setup_twice() {
+        setup_capture_of_logs(LogSeverity::Notice);
+        setup_capture_of_logs(LogSeverity::Notice);
+    }
+
+    #[test]
+    fn test_log_macros() {
+        setup_capture_of_logs(LogSeverity::Info);
+
+        log_warn!(LD_PROTOCOL, test_log_macros, "Unparseable protocol list {:?}", "Link=1-");
+        log_notice!(LD_NET | LD_NOFUNCNAME, test_log_macros, "Notice");
+        log_info!(LD_DIR, test_log_macros, "Info {}", 6);
+        log_debug!(LD_GENERAL, test_log_macros, "Not captured {}", not_formatted());
+
+        let logs: Vec<SavedLogEntry> = saved_logs();
+
+        assert_eq!(3, logs.len());
+        assert_eq!(SavedLogEntry {
+            severity: LogSeverity::Warn,
+            domain: LD_PROTOCOL,
+            funcname: "test_log_macros".to_string(),
+            message: "Unparseable protocol list \"Link=1-\"".to_string(),
+        }, logs[0]);
+        assert_eq!(LD_NET | LD_NOFUNCNAME, logs[1].domain);
+        assert_eq!(LogSeverity::Info, logs[2].severity);
+        assert!(saved_log_has_message("Info 6"));
+        assert!(!saved_log_has_severity(LogSeverity::Debug));
+
+        teardown_capture_of_logs();
+    }
+
+    #[test]
+    fn test_log_message_with_nul() {
+        setup_capture_of_logs(LogSeverity::Warn);
+
+        log_message(LogSeverity::Warn, LD_BUG, "f", "a\0b");
+        assert!(saved_log_has_message("a\\0b"));
+
+        teardown_capture_of_logs();
+    }
+
+    #[test]
+    fn test_log_ratelim_macros() {
+        static LIMIT: RateLimit = RateLimit::new(3600);
+
+        setup_capture_of_logs(LogSeverity::Notice);
+
+        for i in 0..3 {
+            log_warn_ratelim!(&LIMIT, LD_NET, test_log_ratelim_macros, "Event {}", i);
+        }
+        // Not counted, since it wouldn't be logged anyway.
+        log_info_ratelim!(&LIMIT, LD_NET, test_log_ratelim_macros, "Event 3");
+
+        assert_eq!(1, saved_log_n_entries());
+        assert!(saved_log_has_message("Event 0"));
+        assert_eq!(Some(" [2 similar message(s) suppressed in last 3600 seconds]".to_string()),
+                   LIMIT.rate_limit_log(i64::MAX));
+
+        teardown_capture_of_logs();
+    }
+}
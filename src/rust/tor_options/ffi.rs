@@ -0,0 +1,187 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! FFI functions, only to be called from C.
+//!
+//! C tells us when its options change, and we fetch the ones we use, as a
+//! `RawOptions`, through its accessor.
+
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use libc::c_double;
+use libc::c_int;
+
+use options::*;
+
+/// The options which Rust uses, as C copies them out of its `or_options_t`.
+///
+/// C_RUST_COUPLED: src/or/config_rust.h `rust_options_t`
+#[repr(C)]
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RawOptions {
+    pub ChannelType: c_int,
+    pub UsingQuic: c_int,
+    pub GlobalSchedulerUSec: c_int,
+    pub AutotuneWriteUSec: c_int,
+    pub AutotuneRefillUSec: c_int,
+    pub AutotuneFillLimitUSec: c_int,
+    pub AutotuneWriteBWOverride: u64,
+    pub DualSwitchAtExit: c_int,
+    pub DualEwmaAlpha: c_double,
+    pub DualEwmaBeta: c_double,
+    pub DualThresholdLight: c_double,
+    pub DualThresholdHeavy: c_double,
+    pub DualThresholdInactive: c_double,
+    pub DualUseTrafficTracker: c_int,
+    pub IMUXScheduleType: c_int,
+    pub IMUXInitConnections: c_int,
+    pub IMUXMaxConnections: c_int,
+    pub IMUXSeparateBulkConnection: c_int,
+    pub IMUXSeparateWebConnection: c_int,
+    pub IMUXConnLimitThreshold: c_double,
+    pub IMUXTrafficType: c_int,
+}
+
+extern "C" {
+    fn config_get_rust_options(out: *mut RawOptions);
+}
+
+/// Translate a number of microseconds from C into a `Duration`.  A negative
+/// number, which C's config parser rejects for these options anyway, is
+/// treated as zero.
+fn usec(c_usec: c_int) -> Duration {
+    Duration::from_micros(u64::try_from(c_usec).unwrap_or(0))
+}
+
+/// Translate a count from C, treating a negative one as zero.
+fn count(c_count: c_int) -> u32 {
+    u32::try_from(c_count).unwrap_or(0)
+}
+
+impl<'a> From<&'a RawOptions> for Options {
+    fn from(raw: &'a RawOptions) -> Options {
+        Options {
+            channel_type: ChannelType::from_c(raw.ChannelType as u32),
+            using_quic: raw.UsingQuic != 0,
+            global_scheduler_interval: usec(raw.GlobalSchedulerUSec),
+            autotune: AutotuneOptions {
+                write_interval: usec(raw.AutotuneWriteUSec),
+                refill_interval: usec(raw.AutotuneRefillUSec),
+                fill_limit: usec(raw.AutotuneFillLimitUSec),
+                write_bandwidth_override: match raw.AutotuneWriteBWOverride {
+                    0 => None,
+                    n => Some(n),
+                },
+            },
+            dual: DualOptions {
+                switch_at_exit: raw.DualSwitchAtExit != 0,
+                ewma_alpha: raw.DualEwmaAlpha,
+                ewma_beta: raw.DualEwmaBeta,
+                threshold_light: raw.DualThresholdLight,
+                threshold_heavy: raw.DualThresholdHeavy,
+                threshold_inactive: raw.DualThresholdInactive,
+                use_traffic_tracker: raw.DualUseTrafficTracker != 0,
+            },
+            imux: ImuxOptions {
+                schedule_type: ImuxScheduleType::from_c(raw.IMUXScheduleType),
+                init_connections: count(raw.IMUXInitConnections),
+                max_connections: count(raw.IMUXMaxConnections),
+                separate_bulk_connection: raw.IMUXSeparateBulkConnection != 0,
+                separate_web_connection: raw.IMUXSeparateWebConnection != 0,
+                conn_limit_threshold: raw.IMUXConnLimitThreshold,
+                traffic_type: TrafficType::from_c(raw.IMUXTrafficType),
+            },
+        }
+    }
+}
+
+ffi_fn! {
+    /// Called by C's `options_act()` whenever tor's options have been
+    /// (re)loaded, to take a new snapshot of them, and call the hooks
+    /// registered with `on_options_changed()`.
+    pub extern "C" fn tor_options_changed() {
+        let mut raw: RawOptions = RawOptions::default();
+
+        // C fills in every field of `raw`.
+        unsafe { config_get_rust_options(&mut raw) };
+        set_options(Options::from(&raw));
+    }
+}
+
+/// The options as they are by default, as `RawOptions`.
+///
+/// C_RUST_COUPLED: src/or/config.c `option_vars_`
+#[cfg(test)]
+pub(crate) fn default_raw_options() -> RawOptions {
+    RawOptions {
+        ChannelType: 1,
+        DualSwitchAtExit: 1,
+        DualEwmaAlpha: 0.18,
+        DualEwmaBeta: 0.18,
+        DualThresholdLight: 1.4,
+        DualThresholdHeavy: 0.3,
+        DualUseTrafficTracker: 1,
+        IMUXScheduleType: 1,
+        IMUXInitConnections: 1,
+        IMUXMaxConnections: 100,
+        IMUXSeparateWebConnection: 1,
+        IMUXConnLimitThreshold: 0.9,
+        IMUXTrafficType: 1,
+        ..RawOptions::default()
+    }
+}
+
+/// The options as they are by default.
+#[cfg(test)]
+pub(crate) fn test_options() -> Options {
+    Options::from(&default_raw_options())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_default_options() {
+        let options: Options = test_options();
+
+        assert_eq!(Some(ChannelType::Tls), options.channel_type);
+        assert!(!options.using_quic);
+        assert_eq!(Duration::from_secs(0), options.autotune.write_interval);
+        assert_eq!(None, options.autotune.write_bandwidth_override);
+        assert!(options.dual.switch_at_exit);
+        assert_eq!(0.18, options.dual.ewma_alpha);
+        assert_eq!(Some(ImuxScheduleType::RoundRobinCircuit), options.imux.schedule_type);
+        assert_eq!(100, options.imux.max_connections);
+        assert!(!options.imux.separate_bulk_connection);
+        assert!(options.imux.separate_web_connection);
+        assert_eq!(Some(TrafficType::Web), options.imux.traffic_type);
+    }
+
+    #[test]
+    fn test_from_raw_options() {
+        let raw: RawOptions = RawOptions {
+            ChannelType: 7,
+            UsingQuic: 1,
+            GlobalSchedulerUSec: 2500,
+            AutotuneWriteUSec: -1,
+            AutotuneWriteBWOverride: 1 << 20,
+            IMUXScheduleType: 7,
+            IMUXMaxConnections: -5,
+            IMUXTrafficType: 0,
+            ..default_raw_options()
+        };
+        let options: Options = Options::from(&raw);
+
+        assert_eq!(None, options.channel_type);
+        assert!(options.using_quic);
+        assert_eq!(Duration::from_micros(2500), options.global_scheduler_interval);
+        assert_eq!(Duration::from_secs(0), options.autotune.write_interval);
+        assert_eq!(Some(1 << 20), options.autotune.write_bandwidth_override);
+        assert_eq!(Some(ImuxScheduleType::Kist), options.imux.schedule_type);
+        assert_eq!(0, options.imux.max_connections);
+        assert_eq!(None, options.imux.traffic_type);
+    }
+}
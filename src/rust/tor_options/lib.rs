@@ -0,0 +1,23 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Read-only access to tor's configuration options from Rust.
+//!
+//! Rust code can't look inside C's `or_options_t`.  Instead, whenever
+//! `options_act()` (re)loads tor's configuration, C calls
+//! `tor_options_changed()`, which copies the options that Rust code uses
+//! into a new `Options`, through `config_get_rust_options()`.
+//! `get_options()` gets the latest such snapshot, and code which needs to act
+//! when the options change can register a hook with `on_options_changed()`.
+//!
+//! To make another option available to Rust, add it to `rust_options_t` in
+//! src/or/config_rust.h, to `RawOptions` in ffi.rs, and to `Options`.
+
+extern crate libc;
+#[macro_use]
+extern crate tor_util;
+
+mod options;
+pub mod ffi;
+
+pub use options::*;
@@ -0,0 +1,348 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! The snapshot of tor's options, and the hooks called when it changes.
+
+use std::ptr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Once;
+use std::sync::PoisonError;
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// The kinds of channel which may be used between relays.
+///
+/// C_RUST_COUPLED: src/or/or.h `channel_type_t`
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ChannelType {
+    /// A single TLS connection.
+    Tls = 1,
+    /// A pair of TLS connections.
+    Dual = 2,
+    /// A TLS connection per circuit.
+    Pctcp = 3,
+    /// Several TLS connections which circuits are multiplexed over.
+    Imux = 4,
+}
+
+impl ChannelType {
+    /// Translate the value of a C `channel_type_t` into a `ChannelType`.
+    ///
+    /// # Returns
+    ///
+    /// `None` for `CHANNEL_TYPE_UNKNOWN`, or any other value we don't
+    /// recognise.
+    pub fn from_c(c_channel_type: u32) -> Option<ChannelType> {
+        match c_channel_type {
+            1 => Some(ChannelType::Tls),
+            2 => Some(ChannelType::Dual),
+            3 => Some(ChannelType::Pctcp),
+            4 => Some(ChannelType::Imux),
+            _ => None,
+        }
+    }
+}
+
+/// How an IMUX channel chooses which of its connections to send a cell on.
+///
+/// C_RUST_COUPLED: src/or/channelimux.h `channel_imux_schedule_type`
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ImuxScheduleType {
+    /// Each circuit sticks to one connection, taken round-robin.
+    RoundRobinCircuit = 1,
+    /// Each cell goes on the next connection, round-robin.
+    RoundRobinCell = 2,
+    /// The connection with the most room to write.
+    BestSocket = 3,
+    /// By an EWMA of each connection's traffic.
+    Ewma = 4,
+    /// A connection per circuit, like a PCTCP channel.
+    Pctcp = 5,
+    /// A connection of its own for web traffic.
+    SingleWeb = 6,
+    /// As `BestSocket`, with writes scheduled by KIST.
+    Kist = 7,
+}
+
+impl ImuxScheduleType {
+    /// Translate the value of a C `channel_imux_schedule_type` into an
+    /// `ImuxScheduleType`, or `None` if we don't recognise it.
+    pub fn from_c(c_schedule_type: i32) -> Option<ImuxScheduleType> {
+        match c_schedule_type {
+            1 => Some(ImuxScheduleType::RoundRobinCircuit),
+            2 => Some(ImuxScheduleType::RoundRobinCell),
+            3 => Some(ImuxScheduleType::BestSocket),
+            4 => Some(ImuxScheduleType::Ewma),
+            5 => Some(ImuxScheduleType::Pctcp),
+            6 => Some(ImuxScheduleType::SingleWeb),
+            7 => Some(ImuxScheduleType::Kist),
+            _ => None,
+        }
+    }
+}
+
+/// The kind of traffic which a client carries over IMUX channels.
+///
+/// C_RUST_COUPLED: src/or/or.h `TRAFFIC_TYPE_WEB`, `TRAFFIC_TYPE_BULK`
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TrafficType {
+    Web = 1,
+    Bulk = 2,
+}
+
+impl TrafficType {
+    /// Translate a C `TRAFFIC_TYPE_*` value into a `TrafficType`.
+    ///
+    /// # Returns
+    ///
+    /// `None` for `TRAFFIC_TYPE_UNKNOWN`, or any other value we don't
+    /// recognise.
+    pub fn from_c(c_traffic_type: i32) -> Option<TrafficType> {
+        match c_traffic_type {
+            1 => Some(TrafficType::Web),
+            2 => Some(TrafficType::Bulk),
+            _ => None,
+        }
+    }
+}
+
+/// The options for autotuning how fast we write.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AutotuneOptions {
+    /// `AutotuneWriteUSec`: how often we try to write to the kernel.
+    pub write_interval: Duration,
+    /// `AutotuneRefillUSec`: how often we add to our bucket.
+    pub refill_interval: Duration,
+    /// `AutotuneFillLimitUSec`: our bucket holds as much as we can send in
+    /// this time.
+    pub fill_limit: Duration,
+    /// `AutotuneWriteBWOverride`: our bandwidth, in bytes per second, if it
+    /// was set rather than detected.
+    pub write_bandwidth_override: Option<u64>,
+}
+
+/// The options for DUAL channels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DualOptions {
+    /// `DualSwitchAtExit`: whether only the exit switches between the two
+    /// connections, rather than every relay.
+    pub switch_at_exit: bool,
+    /// `DualEwmaAlpha`: the EWMA parameters.
+    pub ewma_alpha: f64,
+    /// `DualEwmaBeta`
+    pub ewma_beta: f64,
+    /// `DualThresholdLight`: the thresholds for when a circuit moves between
+    /// being light, heavy and inactive.
+    pub threshold_light: f64,
+    /// `DualThresholdHeavy`
+    pub threshold_heavy: f64,
+    /// `DualThresholdInactive`
+    pub threshold_inactive: f64,
+    /// `DualUseTrafficTracker`
+    pub use_traffic_tracker: bool,
+}
+
+/// The options for IMUX channels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImuxOptions {
+    /// `IMUXScheduleType`, or `None` if it isn't one we recognise.
+    pub schedule_type: Option<ImuxScheduleType>,
+    /// `IMUXInitConnections`: how many connections a channel opens at first.
+    pub init_connections: u32,
+    /// `IMUXMaxConnections`: the most connections a channel may have open.
+    pub max_connections: u32,
+    /// `IMUXSeparateBulkConnection`
+    pub separate_bulk_connection: bool,
+    /// `IMUXSeparateWebConnection`
+    pub separate_web_connection: bool,
+    /// `IMUXConnLimitThreshold`
+    pub conn_limit_threshold: f64,
+    /// `IMUXTrafficType`, or `None` if it isn't one we recognise.
+    pub traffic_type: Option<TrafficType>,
+}
+
+/// A snapshot of the options which Rust code uses, from when tor's
+/// configuration was last (re)loaded.  An option which chooses between
+/// several behaviours is `None` here when it is set to a value which C
+/// doesn't recognise either.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    /// `ChannelType`, or `None` if it isn't one we recognise.
+    pub channel_type: Option<ChannelType>,
+    /// `UsingQuic`: whether our connections are carried over QUIC sockets.
+    pub using_quic: bool,
+    /// `GlobalSchedulerUSec`
+    pub global_scheduler_interval: Duration,
+    pub autotune: AutotuneOptions,
+    pub dual: DualOptions,
+    pub imux: ImuxOptions,
+}
+
+/// A function to call when tor's options change, with the previous options
+/// (or `None` the first time they are loaded), and the new ones.
+pub type OptionsChangedHook = fn(old: Option<&Options>, new: &Options);
+
+/// The options which tor is running with, and who to tell when they change.
+struct State {
+    /// The latest snapshot of the options, or `None` until they are loaded.
+    options: RwLock<Option<Arc<Options>>>,
+    /// The hooks registered with `on_options_changed()`, in the order they
+    /// were registered.
+    hooks: Mutex<Vec<OptionsChangedHook>>,
+}
+
+/// Guards the initialisation of `STATE`.
+static STATE_INIT: Once = Once::new();
+
+/// The options and hooks.  Use `state()` rather than this.
+static mut STATE: *const State = ptr::null();
+
+/// Get the options and hooks, making them the first time we're called.
+fn state() -> &'static State {
+    // `STATE` is only written once, by the `call_once()` closure, and only
+    // read after `call_once()` has returned.
+    unsafe {
+        STATE_INIT.call_once(|| {
+            let state: State = State {
+                options: RwLock::new(None),
+                hooks: Mutex::new(Vec::new()),
+            };
+
+            STATE = Box::into_raw(Box::new(state));
+        });
+        &*STATE
+    }
+}
+
+/// Get the options which tor is running with, or `None` if they haven't been
+/// loaded yet.
+///
+/// The snapshot doesn't change when the options do, so hold on to it only
+/// as long as it's acceptable to use out-of-date options.
+pub fn get_options() -> Option<Arc<Options>> {
+    // No code which holds the lock can panic, but if it did, the options
+    // would still be whole.
+    state().options.read().unwrap_or_else(PoisonError::into_inner).clone()
+}
+
+/// Call `hook` whenever tor's options change, after the new ones are
+/// available from `get_options()`.  Hooks are called in the order they were
+/// registered, and can't be unregistered.
+///
+/// # Examples
+///
+/// ```
+/// use tor_options::*;
+///
+/// fn channel_type_changed(old: Option<&Options>, new: &Options) {
+///     if old.map(|o| o.channel_type) != Some(new.channel_type) {
+///         // Reconfigure our channels.
+///     }
+/// }
+///
+/// on_options_changed(channel_type_changed);
+/// ```
+pub fn on_options_changed(hook: OptionsChangedHook) {
+    state().hooks.lock().unwrap_or_else(PoisonError::into_inner).push(hook);
+}
+
+/// Replace the options which tor is running with, and call every hook
+/// registered with `on_options_changed()`.
+///
+/// tor's C code does this through `tor_options_changed()` whenever it
+/// (re)loads its configuration, so this is only for code which isn't linked
+/// with C, such as tests.
+pub fn set_options(options: Options) {
+    let new: Arc<Options> = Arc::new(options);
+    let old: Option<Arc<Options>> = {
+        let mut current = state().options.write().unwrap_or_else(PoisonError::into_inner);
+
+        current.replace(new.clone())
+    };
+    // Copied, so that a hook can register another without deadlocking.
+    let hooks: Vec<OptionsChangedHook> =
+        state().hooks.lock().unwrap_or_else(PoisonError::into_inner).clone();
+
+    for hook in hooks {
+        hook(old.as_deref(), &new);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    use ffi::test_options;
+
+    #[test]
+    fn test_from_c() {
+        assert_eq!(None, ChannelType::from_c(0));
+        assert_eq!(Some(ChannelType::Tls), ChannelType::from_c(1));
+        assert_eq!(Some(ChannelType::Dual), ChannelType::from_c(2));
+        assert_eq!(Some(ChannelType::Pctcp), ChannelType::from_c(3));
+        assert_eq!(Some(ChannelType::Imux), ChannelType::from_c(4));
+        assert_eq!(None, ChannelType::from_c(5));
+
+        assert_eq!(None, ImuxScheduleType::from_c(0));
+        assert_eq!(Some(ImuxScheduleType::RoundRobinCircuit), ImuxScheduleType::from_c(1));
+        assert_eq!(Some(ImuxScheduleType::Kist), ImuxScheduleType::from_c(7));
+        assert_eq!(None, ImuxScheduleType::from_c(8));
+        assert_eq!(None, ImuxScheduleType::from_c(-1));
+
+        assert_eq!(None, TrafficType::from_c(0));
+        assert_eq!(Some(TrafficType::Web), TrafficType::from_c(1));
+        assert_eq!(Some(TrafficType::Bulk), TrafficType::from_c(2));
+        assert_eq!(None, TrafficType::from_c(3));
+    }
+
+    static HOOK_CALLS: AtomicUsize = AtomicUsize::new(0);
+    static CHANGED_CHANNEL_TYPES: AtomicUsize = AtomicUsize::new(0);
+
+    fn count_calls(_old: Option<&Options>, new: &Options) {
+        // The new options are available by the time hooks are called.
+        assert_eq!(Some(new), get_options().as_deref());
+        HOOK_CALLS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn count_changed_channel_types(old: Option<&Options>, new: &Options) {
+        if let Some(old) = old {
+            if old.channel_type != new.channel_type {
+                CHANGED_CHANNEL_TYPES.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    // The only test which sets the options or registers hooks, since the
+    // other tests in this crate run at the same time.
+    #[test]
+    fn test_set_options_calls_hooks() {
+        assert!(get_options().is_none());
+
+        on_options_changed(count_calls);
+        on_options_changed(count_changed_channel_types);
+
+        let mut options: Options = test_options();
+
+        set_options(options.clone());
+        assert_eq!(Some(&options), get_options().as_deref());
+        assert_eq!(1, HOOK_CALLS.load(Ordering::SeqCst));
+        assert_eq!(0, CHANGED_CHANNEL_TYPES.load(Ordering::SeqCst));
+
+        let first: Arc<Options> = get_options().unwrap();
+
+        options.channel_type = Some(ChannelType::Imux);
+        options.imux.max_connections = 5;
+        set_options(options.clone());
+        assert_eq!(Some(&options), get_options().as_deref());
+        assert_eq!(2, HOOK_CALLS.load(Ordering::SeqCst));
+        assert_eq!(1, CHANGED_CHANNEL_TYPES.load(Ordering::SeqCst));
+
+        // A snapshot which was already taken doesn't change.
+        assert_eq!(Some(ChannelType::Tls), first.channel_type);
+        assert_eq!(100, first.imux.max_connections);
+    }
+}
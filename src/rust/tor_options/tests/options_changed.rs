@@ -0,0 +1,66 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Tests that `tor_options_changed()` fetches the options from C, as
+//! `options_act()` calls it to.
+
+extern crate libc;
+extern crate tor_log;
+extern crate tor_log_testing;
+extern crate tor_options;
+
+use std::sync::atomic::AtomicI32;
+use std::sync::atomic::Ordering;
+
+use libc::c_int;
+
+use tor_log::LogSeverity;
+use tor_log_testing::*;
+use tor_options::*;
+use tor_options::ffi::*;
+
+/// What C's `ChannelType` option is set to.
+static CHANNEL_TYPE: AtomicI32 = AtomicI32::new(1);
+
+/// C's accessor for the options, which fills in only the ones these tests
+/// look at.
+#[no_mangle]
+pub unsafe extern "C" fn config_get_rust_options(out: *mut RawOptions) {
+    *out = RawOptions {
+        ChannelType: CHANNEL_TYPE.load(Ordering::SeqCst) as c_int,
+        IMUXMaxConnections: 100,
+        IMUXTrafficType: 2,
+        ..RawOptions::default()
+    };
+}
+
+fn panic_on_imux(_old: Option<&Options>, new: &Options) {
+    if new.channel_type == Some(ChannelType::Imux) {
+        panic!("Can't switch to IMUX");
+    }
+}
+
+#[test]
+fn options_changed_fetches_options() {
+    assert!(get_options().is_none());
+    on_options_changed(panic_on_imux);
+
+    tor_options_changed();
+
+    let options = get_options().unwrap();
+
+    assert_eq!(Some(ChannelType::Tls), options.channel_type);
+    assert_eq!(100, options.imux.max_connections);
+    assert_eq!(Some(TrafficType::Bulk), options.imux.traffic_type);
+
+    // A hook which panics doesn't unwind into C, and the new options are
+    // still taken.
+    setup_capture_of_logs(LogSeverity::Warn);
+    CHANNEL_TYPE.store(4, Ordering::SeqCst);
+    tor_options_changed();
+
+    assert_eq!(Some(ChannelType::Imux), get_options().unwrap().channel_type);
+    assert!(saved_log_has_message("Caught a panic in Rust, returning an error: \
+                                   Can't switch to IMUX"));
+    teardown_capture_of_logs();
+}
@@ -1,5 +1,29 @@
 extern crate tor_util;
 extern crate protover;
+extern crate tor_options;
+#[cfg(feature = "global_allocator")]
+extern crate tor_allocate;
 
-pub use tor_util::*;
-pub use protover::*;
+// The functions which tor's C code calls, named rather than glob-imported,
+// since each of these crates has an `ffi` module.
+pub use tor_util::ffi::rust_welcome_string;
+pub use protover::ffi::protover_all_supported;
+pub use protover::ffi::protover_describe_parse_error;
+pub use protover::ffi::protocol_list_supports_protocol;
+pub use protover::ffi::protocol_list_supports_protocol_or_later;
+pub use protover::ffi::protover_get_supported_protocols;
+pub use protover::ffi::protover_check_supported_protocols_override;
+pub use protover::ffi::protover_set_supported_protocols_override;
+pub use protover::ffi::protover_get_supported_protocols_for_transport;
+pub use protover::ffi::protocol_list_supports_channel_type;
+pub use protover::ffi::protover_check_requirements;
+pub use protover::ffi::protover_compute_vote;
+pub use protover::ffi::protover_is_supported_here;
+pub use protover::ffi::protover_compute_for_old_tor;
+pub use tor_options::ffi::tor_options_changed;
+
+/// Make every allocation in Rust through tor's allocator.  Not in tests,
+/// since they aren't linked with tor's C code.
+#[cfg(all(feature = "global_allocator", not(test)))]
+#[global_allocator]
+static ALLOCATOR: tor_allocate::TorAllocator = tor_allocate::TorAllocator;
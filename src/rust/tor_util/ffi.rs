@@ -6,21 +6,26 @@
 //!
 
 use libc::c_char;
-use tor_allocate::allocate_and_copy_string;
+use std::ptr;
+use tor_allocate::TorOwnedCString;
 
-/// Returns a short string to announce Rust support during startup.
-///
-/// # Examples
-/// ```c
-/// char *rust_str = rust_welcome_string();
-/// printf("%s", rust_str);
-/// tor_free(rust_str);
-/// ```
-#[no_mangle]
-pub extern "C" fn rust_welcome_string() -> *mut c_char {
-    let rust_welcome = String::from(
-        "Tor is running with Rust integration. Please report \
-         any bugs you encounter.",
-    );
-    allocate_and_copy_string(&rust_welcome)
+ffi_fn! {
+    /// Returns a short string to announce Rust support during startup.
+    ///
+    /// # Examples
+    /// ```c
+    /// char *rust_str = rust_welcome_string();
+    /// printf("%s", rust_str);
+    /// tor_free(rust_str);
+    /// ```
+    pub extern "C" fn rust_welcome_string() -> *mut c_char [ptr::null_mut()] {
+        let rust_welcome = TorOwnedCString::new(
+            "Tor is running with Rust integration. Please report \
+             any bugs you encounter.",
+        );
+        match rust_welcome {
+            Ok(n) => n.into_raw(),
+            Err(_) => ptr::null_mut(),
+        }
+    }
 }
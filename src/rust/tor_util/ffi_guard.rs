@@ -0,0 +1,205 @@
+// Copyright (c) 2018, The Tor Project, Inc.
+// See LICENSE for licensing information
+
+//! Keeping panics from unwinding across the FFI boundary into C.
+//!
+//! Unwinding into C is undefined behaviour.  tor is built with `panic =
+//! "abort"`, so that a panic kills the process rather than unwinding, but
+//! debug and test builds unwind.  The `ffi_fn!` macro defines an `extern
+//! "C"` function whose body runs under `catch_panic()`, so that in those
+//! builds a panic is logged as a bug, and the function returns an error
+//! value instead.
+
+use std::any::Any;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+
+use tor_log::LogSeverity;
+use tor_log::LD_BUG;
+use tor_log::log_message;
+
+/// Get the message from a panic's payload, which is a `&str` or a `String`
+/// if it came from `panic!()`, `unwrap()`, `expect()`, or `assert!()`.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&'static str>() {
+        return message;
+    }
+    if let Some(message) = payload.downcast_ref::<String>() {
+        return message;
+    }
+    "(no message)"
+}
+
+/// Log, as a bug, that `funcname` panicked with `payload`.
+fn log_panic(funcname: &'static str, payload: &(dyn Any + Send)) {
+    log_message(LogSeverity::Warn, LD_BUG, funcname,
+                &format!("Caught a panic in Rust, returning an error: {}",
+                         panic_message(payload)));
+}
+
+/// Call `f`, but if it panics, log a bug on behalf of the function
+/// `funcname`, and return `on_panic()` instead.
+///
+/// Whatever `f` was in the middle of is abandoned, so this is only for the
+/// outermost Rust function called from C, where the alternative is undefined
+/// behaviour.  Use `ffi_fn!` to define such functions.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate tor_util;
+/// # extern crate tor_log_testing;
+/// use tor_util::ffi_guard::catch_panic;
+///
+/// # fn main() {
+/// let halved: u32 = catch_panic("halve", || 0, || 10 / 2);
+/// assert_eq!(5, halved);
+/// # }
+/// ```
+pub fn catch_panic<F, E, R>(funcname: &'static str, on_panic: E, f: F) -> R
+    where F: FnOnce() -> R,
+          E: FnOnce() -> R,
+{
+    // Any state f() was mutating may be inconsistent after a panic, but we
+    // don't look at it again here, and C never could have.
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result,
+        Err(payload) => {
+            log_panic(funcname, &*payload);
+            on_panic()
+        },
+    }
+}
+
+/// Define an `extern "C"` function for C to call, with `#[no_mangle]`, whose
+/// body can't unwind into C.
+///
+/// After the return type, the value to return if the body panics is given
+/// in brackets.  It is only evaluated if there is a panic.  A panic is
+/// logged as a bug (unless tor is built with `panic = "abort"`, when it
+/// aborts as usual).
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate tor_util;
+/// extern crate libc;
+/// # extern crate tor_log_testing;
+///
+/// use libc::c_int;
+///
+/// ffi_fn! {
+///     /// Returns `a` divided by `b`, or -1 if `b` is zero.
+///     pub extern "C" fn divide(a: c_int, b: c_int) -> c_int [-1] {
+///         a / b
+///     }
+/// }
+///
+/// # fn main() {
+/// assert_eq!(2, divide(4, 2));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! ffi_fn {
+    (
+        $(#[$attr:meta])*
+        pub extern "C" fn $name:ident($($arg:ident: $argty:ty),* $(,)*) -> $ret:ty
+            [$on_panic:expr] $body:block
+    ) => (
+        $(#[$attr])*
+        #[no_mangle]
+        pub extern "C" fn $name($($arg: $argty),*) -> $ret {
+            $crate::ffi_guard::catch_panic(stringify!($name),
+                                           || $on_panic,
+                                           move || $body)
+        }
+    );
+    (
+        $(#[$attr:meta])*
+        pub extern "C" fn $name:ident($($arg:ident: $argty:ty),* $(,)*) $body:block
+    ) => (
+        $(#[$attr])*
+        #[no_mangle]
+        pub extern "C" fn $name($($arg: $argty),*) {
+            $crate::ffi_guard::catch_panic(stringify!($name),
+                                           || (),
+                                           move || $body)
+        }
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use libc::c_int;
+    use tor_log_testing::*;
+
+    /// Run `f`, and get what it logged.
+    fn logged<F: FnOnce()>(f: F) -> Vec<String> {
+        setup_capture_of_logs(LogSeverity::Warn);
+        f();
+
+        let logged: Vec<String> = saved_logs().iter().map(|e| {
+            assert_eq!(LD_BUG, e.domain);
+            format!("{}(): {}", e.funcname, e.message)
+        }).collect();
+
+        teardown_capture_of_logs();
+        logged
+    }
+
+    ffi_fn! {
+        /// Divide, or return -1 on a division by zero.
+        pub extern "C" fn test_divide(a: c_int, b: c_int,) -> c_int [-1] {
+            if a == 0 {
+                return 0;
+            }
+            a / b
+        }
+    }
+
+    ffi_fn! {
+        pub extern "C" fn test_unwrap_none() {
+            let nothing: Option<u8> = None;
+            nothing.expect("nothing here");
+        }
+    }
+
+    #[test]
+    fn test_ffi_fn_returns_normally() {
+        assert!(logged(|| {
+            assert_eq!(3, test_divide(9, 3));
+            assert_eq!(0, test_divide(0, 0));
+        }).is_empty());
+    }
+
+    #[test]
+    fn test_ffi_fn_catches_panic() {
+        assert_eq!(vec!["test_divide(): Caught a panic in Rust, returning an error: \
+                         attempt to divide by zero".to_string()],
+                   logged(|| assert_eq!(-1, test_divide(1, 0))));
+    }
+
+    #[test]
+    fn test_ffi_fn_without_return_value() {
+        assert_eq!(vec!["test_unwrap_none(): Caught a panic in Rust, returning an error: \
+                         nothing here".to_string()],
+                   logged(|| test_unwrap_none()));
+    }
+
+    #[test]
+    fn test_catch_panic_calls_on_panic_only_on_panic() {
+        let on_panic = || -> String { panic!("on_panic() shouldn't be called") };
+
+        assert!(logged(|| assert_eq!("ok", catch_panic("f", on_panic, || "ok".to_string())))
+                .is_empty());
+    }
+
+    #[test]
+    fn test_panic_message_with_nul() {
+        assert_eq!(vec!["f(): Caught a panic in Rust, returning an error: a\\0b 1".to_string()],
+                   logged(|| assert_eq!(7, catch_panic("f", || 7, || panic!("a\0b {}", 1)))));
+    }
+}
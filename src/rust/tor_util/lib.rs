@@ -6,6 +6,11 @@
 
 extern crate libc;
 extern crate tor_allocate;
+extern crate tor_log;
+#[cfg(test)]
+extern crate tor_log_testing;
 
+#[macro_use]
+pub mod ffi_guard;
 pub mod ffi;
 pub mod strings;
@@ -0,0 +1,547 @@
+/* Copyright (c) 2018, The Tor Project, Inc. */
+/* See LICENSE for licensing information */
+
+/**
+ * \file test-protover-parity.c
+ * \brief Check that the C and Rust implementations of protover agree.
+ *
+ * We build the C implementation from protover.c here, with its functions
+ * renamed to start with "c_", and link against the Rust implementation in
+ * the tor_rust library.  Then we run both on a corpus of interesting inputs
+ * and on many generated ones, and report every input for which they give
+ * different return values or output strings.
+ *
+ * Run it with "make test-protover-parity", or directly as
+ *   test-protover-parity [-v] [-s seed] [-n iterations]
+ * to try another seed, more or fewer generated inputs, or to see every
+ * result rather than only the differences.
+ **/
+
+#define PROTOVER_PARITY_TEST
+
+#define protover_all_supported c_protover_all_supported
+#define protover_check_requirements c_protover_check_requirements
+#define protover_describe_parse_error c_protover_describe_parse_error
+#define protover_is_supported_here c_protover_is_supported_here
+#define protover_get_supported_protocols c_protover_get_supported_protocols
+#define protover_get_supported_protocols_for_transport \
+  c_protover_get_supported_protocols_for_transport
+#define protover_check_supported_protocols_override \
+  c_protover_check_supported_protocols_override
+#define protover_set_supported_protocols_override \
+  c_protover_set_supported_protocols_override
+#define protover_compute_vote c_protover_compute_vote
+#define protover_compute_for_old_tor c_protover_compute_for_old_tor
+#define protocol_list_supports_protocol c_protocol_list_supports_protocol
+#define protocol_list_supports_protocol_or_later \
+  c_protocol_list_supports_protocol_or_later
+#define protocol_list_supports_channel_type \
+  c_protocol_list_supports_channel_type
+#define protover_free_all c_protover_free_all
+
+#include "protover.c"
+
+#undef protover_all_supported
+#undef protover_check_requirements
+#undef protover_describe_parse_error
+#undef protover_is_supported_here
+#undef protover_get_supported_protocols
+#undef protover_get_supported_protocols_for_transport
+#undef protover_check_supported_protocols_override
+#undef protover_set_supported_protocols_override
+#undef protover_compute_vote
+#undef protover_compute_for_old_tor
+#undef protocol_list_supports_protocol
+#undef protocol_list_supports_protocol_or_later
+#undef protocol_list_supports_channel_type
+#undef protover_free_all
+
+#include <stdio.h>
+
+/* The Rust implementations, from src/rust/protover/ffi.rs. */
+int protover_all_supported(const char *s, char **missing);
+const char *protover_get_supported_protocols(void);
+char *protover_compute_vote(const smartlist_t *list_of_proto_strings,
+                            int threshold);
+const char *protover_compute_for_old_tor(const char *version);
+int protocol_list_supports_protocol(const char *list, protocol_type_t tp,
+                                    uint32_t version);
+int protocol_list_supports_protocol_or_later(const char *list,
+                                             protocol_type_t tp,
+                                             uint32_t version);
+void protover_free_all(void);
+
+/** Protocol lists which are worth trying on every run: the inputs from
+ * test_protover.c, and some edge cases. */
+static const char *corpus[] = {
+  "",
+  " ",
+  "=",
+  "Link",
+  "Link=",
+  "Link= ",
+  "Link=1",
+  "Link=1 ",
+  " Link=1",
+  "Link=1  Relay=2",
+  "Link=1-5",
+  "Link=1-6",
+  "Link=5-6",
+  "Link=1,1",
+  "Link=1,2",
+  "Link=1-2,2-3",
+  "Link=3-4,1-2",
+  "Link=1,9-8,3",
+  "Link=1,fred",
+  "Link=1,fred,3",
+  "Link=fred",
+  "Link=1-x",
+  "Link=0",
+  "Link=0-0",
+  "Link=-1",
+  "Link=+1",
+  "Link=01",
+  "Link=1-01",
+  "Link=1,,2",
+  "Link=1,",
+  "Link=,1",
+  "Link=1-3,345-666",
+  "Link=1-3,5-12 Quokka=9000-9001",
+  "Link=1,999 Relay=2",
+  "Link=1-4 Wombat=9",
+  "Link=3-4 Desc=2",
+  "Link=4-6 LinkAuth=3",
+  "Link=4 =3 Desc=9",
+  "Link=4 Haprauxymatyve Desc=9",
+  "Link=4 Haprauxymatyve=7 Desc=9",
+  "Link=4 Z=3 Desc=9",
+  "Link=1 Link=2",
+  "Link=4294967294",
+  "Link=4294967295",
+  "Link=4294967296",
+  "Link=1-4294967294",
+  "Cons=1-2 Link=1-4",
+  "Cons=1-2 Link=1-x",
+  "ChanType=1-4",
+  "ChanType=4 Link=1",
+  "Faux=-0",
+  "Faux=-1",
+  "Faux=-1-3",
+  "Faux=0--0",
+  "Faux=1--1",
+  "Faux=10-5",
+  "Fribble",
+  "Fribble=",
+  "Foo=1,3 Bar=3 Baz= Quux=9-12,14,15-16,900",
+  "Foo=1,3 Bar=3 Baz= Quux=9-12,14,15-16,900 Zn=0,4294967294",
+  "Foo=1,3 Bar=3 Baz= Quux=9-12,14,15-16,900 Zn=0,4294967295",
+  "Sleen=1-500",
+  "Sleen=1-65536",
+  "Sleen=1-65537",
+  "Sleen=1-65536,100000",
+  "Sleen=0-2147483648",
+  "Sleen=0-4294967294",
+  "Sleen=4294967294",
+  "Zn=4294967293-4294967295",
+  "Zn=4294967295-1",
+  "Cons=1-2 Desc=1-2 DirCache=1-2 HSDir=1-2 HSIntro=3-4 HSRend=1-2 "
+    "Link=1-5 LinkAuth=1,3 Microdesc=1-2 Relay=1-2",
+  "Cons=1-2 Desc=1-2 DirCache=1-2 HSDir=1-2 HSIntro=3-4 HSRend=1-2 "
+    "Link=1-5 LinkAuth=1,3 Microdesc=1-2 Relay=1-2 Padding=1",
+  NULL
+};
+
+/** Platform versions which are worth trying on every run. */
+static const char *version_corpus[] = {
+  "",
+  "0.1.2.19",
+  "0.2.4.18-rc",
+  "0.2.4.19",
+  "0.2.5.15",
+  "0.2.7.4-rc",
+  "0.2.7.5",
+  "0.2.9.1-alpha",
+  "0.2.9.2-alpha",
+  "0.2.9.3-alpha",
+  "0.3.2.10",
+  "Tor 0.2.4.19",
+  "Tor 0.2.8.12 on Linux",
+  "Tor 0.2.9.3-alpha (git-abcdef0123456789)",
+  "Tor 0.3.3.5-rc",
+  "Tor",
+  "Tor ",
+  "Tor x.y.z",
+  "Arti 0.1.0",
+  "tor 0.2.4.19",
+  NULL
+};
+
+/** Every version we try with protocol_list_supports_protocol(). */
+static const uint32_t versions[] = {
+  0, 1, 2, 3, 4, 5, 6, 9, 500, 65536, 4294967294u, 4294967295u
+};
+
+/** Command-line options. */
+static int opt_verbose = 0;
+static uint64_t opt_seed = U64_LITERAL(0x7e57ab1e5eed);
+static int opt_iterations = 20000;
+
+/** The state of our random number generator.  We don't use the crypto RNG
+ * since we want runs to be repeatable. */
+static uint64_t rng_state;
+
+static uint64_t
+rng_next(void)
+{
+  rng_state ^= rng_state << 13;
+  rng_state ^= rng_state >> 7;
+  rng_state ^= rng_state << 17;
+  return rng_state;
+}
+
+/** Return a random number from 0 up to but not including <b>n</b>. */
+static unsigned
+rng_below(unsigned n)
+{
+  return (unsigned)(rng_next() % n);
+}
+
+static int n_checks = 0;
+static int n_differences = 0;
+
+/** Return true iff the nullable strings <b>a</b> and <b>b</b> differ. */
+static int
+strings_differ(const char *a, const char *b)
+{
+  if (!a || !b)
+    return a != b;
+  return strcmp(a, b) != 0;
+}
+
+/** Record that <b>function</b> gave the results <b>c_result</b> and
+ * <b>rust_result</b> for <b>input</b>, and report them if they differ. */
+static void
+check(const char *function, const char *input,
+      const char *c_result, const char *rust_result)
+{
+  ++n_checks;
+  if (strings_differ(c_result, rust_result)) {
+    ++n_differences;
+    printf("%s(%s):\n  C:    %s\n  Rust: %s\n", function, input,
+           c_result ? c_result : "NULL", rust_result ? rust_result : "NULL");
+  } else if (opt_verbose) {
+    printf("%s(%s): %s\n", function, input, c_result ? c_result : "NULL");
+  }
+}
+
+/** Describe an int return value and a nullable output string. */
+static char *
+describe_result(int result, const char *output)
+{
+  char *s = NULL;
+  if (output)
+    tor_asprintf(&s, "%d \"%s\"", result, output);
+  else
+    tor_asprintf(&s, "%d NULL", result);
+  return s;
+}
+
+/** Describe a nullable input string. */
+static char *
+describe_input(const char *s)
+{
+  return s ? tor_strdup(escaped(s)) : tor_strdup("NULL");
+}
+
+/** Return true iff <b>s</b> lists so many versions that the C
+ * protover_all_supported(), which checks them one at a time, would take far
+ * too long on it. */
+static int
+too_many_versions(const char *s)
+{
+  smartlist_t *entries = s ? parse_protocol_list(s) : NULL;
+  uint64_t n_versions = 0;
+
+  if (!entries)
+    return 0;
+
+  SMARTLIST_FOREACH_BEGIN(entries, const proto_entry_t *, ent) {
+    SMARTLIST_FOREACH(ent->ranges, const proto_range_t *, range,
+                      n_versions += (uint64_t)range->high - range->low + 1);
+  } SMARTLIST_FOREACH_END(ent);
+
+  SMARTLIST_FOREACH(entries, proto_entry_t *, ent, proto_entry_free(ent));
+  smartlist_free(entries);
+  return n_versions > (uint64_t)MAX_PROTOCOLS_TO_EXPAND;
+}
+
+static void
+check_all_supported(const char *s)
+{
+  char *c_missing = NULL, *rust_missing = NULL;
+  int c_result, rust_result;
+  char *input, *c_desc, *rust_desc;
+
+  if (too_many_versions(s))
+    return;
+
+  c_result = c_protover_all_supported(s, &c_missing);
+  rust_result = protover_all_supported(s, &rust_missing);
+  input = describe_input(s);
+  c_desc = describe_result(c_result, c_missing);
+  rust_desc = describe_result(rust_result, rust_missing);
+
+  check("protover_all_supported", input, c_desc, rust_desc);
+
+  tor_free(c_missing);
+  tor_free(rust_missing);
+  tor_free(input);
+  tor_free(c_desc);
+  tor_free(rust_desc);
+}
+
+static void
+check_supports_protocol(const char *s)
+{
+  char *input = NULL;
+  char c_desc[32], rust_desc[32];
+  int tp;
+  unsigned i;
+
+  for (tp = PRT_LINK; tp <= PRT_CHANTYPE; ++tp) {
+    for (i = 0; i < ARRAY_LENGTH(versions); ++i) {
+      tor_asprintf(&input, "%s, %s, %u", escaped(s),
+                   protocol_type_to_str(tp), (unsigned)versions[i]);
+
+      tor_snprintf(c_desc, sizeof(c_desc), "%d",
+                   c_protocol_list_supports_protocol(s, tp, versions[i]));
+      tor_snprintf(rust_desc, sizeof(rust_desc), "%d",
+                   protocol_list_supports_protocol(s, tp, versions[i]));
+      check("protocol_list_supports_protocol", input, c_desc, rust_desc);
+
+      tor_snprintf(c_desc, sizeof(c_desc), "%d",
+                   c_protocol_list_supports_protocol_or_later(s, tp,
+                                                              versions[i]));
+      tor_snprintf(rust_desc, sizeof(rust_desc), "%d",
+                   protocol_list_supports_protocol_or_later(s, tp,
+                                                            versions[i]));
+      check("protocol_list_supports_protocol_or_later", input,
+            c_desc, rust_desc);
+
+      tor_free(input);
+    }
+  }
+}
+
+static void
+check_compute_vote(const smartlist_t *votes, int threshold)
+{
+  smartlist_t *quoted = smartlist_new();
+  char *joined, *input = NULL;
+  char *c_result = c_protover_compute_vote(votes, threshold);
+  char *rust_result = protover_compute_vote(votes, threshold);
+
+  SMARTLIST_FOREACH(votes, const char *, vote,
+                    smartlist_add_strdup(quoted, escaped(vote)));
+  joined = smartlist_join_strings(quoted, ", ", 0, NULL);
+  tor_asprintf(&input, "[%s], %d", joined, threshold);
+
+  check("protover_compute_vote", input, c_result, rust_result);
+
+  SMARTLIST_FOREACH(quoted, char *, q, tor_free(q));
+  smartlist_free(quoted);
+  tor_free(joined);
+  tor_free(input);
+  tor_free(c_result);
+  tor_free(rust_result);
+}
+
+static void
+check_compute_for_old_tor(const char *version)
+{
+  char *input = describe_input(version);
+
+  check("protover_compute_for_old_tor", input,
+        c_protover_compute_for_old_tor(version),
+        protover_compute_for_old_tor(version));
+
+  tor_free(input);
+}
+
+/** Check that both implementations have the same protocols, with the same
+ * protocol_type_t for each, and support the same versions of them. */
+static void
+check_protocol_types(void)
+{
+  char *list = NULL, *input = NULL;
+  char c_desc[32], rust_desc[32];
+  unsigned i;
+  int tp;
+
+  for (i = 0; i < N_PROTOCOL_NAMES; ++i) {
+    tor_asprintf(&list, "%s=1", PROTOCOL_NAMES[i].name);
+    for (tp = PRT_LINK; tp <= PRT_CHANTYPE; ++tp) {
+      tor_asprintf(&input, "%s, %s, 1", escaped(list),
+                   protocol_type_to_str(tp));
+      tor_snprintf(c_desc, sizeof(c_desc), "%d",
+                   c_protocol_list_supports_protocol(list, tp, 1));
+      tor_snprintf(rust_desc, sizeof(rust_desc), "%d",
+                   protocol_list_supports_protocol(list, tp, 1));
+      check("protocol_list_supports_protocol", input, c_desc, rust_desc);
+      tor_free(input);
+    }
+    tor_free(list);
+  }
+
+  check("protover_get_supported_protocols", "",
+        c_protover_get_supported_protocols(),
+        protover_get_supported_protocols());
+}
+
+/** Run every check on a protocol list <b>s</b>. */
+static void
+check_protocol_list(const char *s)
+{
+  check_all_supported(s);
+  check_supports_protocol(s);
+}
+
+/** Pieces from which we build random protocol lists. */
+static const char *names[] = {
+  "Link", "LinkAuth", "Relay", "HSDir", "Cons", "Padding", "ChanType",
+  "Wombat", "Z", "", "link", "Link2",
+};
+static const char *numbers[] = {
+  "0", "1", "2", "3", "4", "5", "6", "9", "63", "64", "65535", "65536",
+  "4294967294", "4294967295", "4294967296", "-1", "", "x", "01",
+};
+static const char *separators[] = { " ", " ", " ", " ", "  ", "\t", "" };
+
+/** Generate a random protocol list, mostly from sensible pieces, but with
+ * enough junk to exercise the parsers. */
+static char *
+generate_protocol_list(void)
+{
+  smartlist_t *chunks = smartlist_new();
+  unsigned n_entries = rng_below(5);
+  unsigned i, j;
+  char *result;
+
+  for (i = 0; i < n_entries; ++i) {
+    unsigned n_ranges = rng_below(4);
+
+    if (i)
+      smartlist_add_strdup(chunks,
+                           separators[rng_below(ARRAY_LENGTH(separators))]);
+    smartlist_add_strdup(chunks, names[rng_below(ARRAY_LENGTH(names))]);
+    if (rng_below(20))
+      smartlist_add_strdup(chunks, "=");
+
+    for (j = 0; j < n_ranges; ++j) {
+      if (j)
+        smartlist_add_strdup(chunks, ",");
+      if (rng_below(3)) {
+        /* Mostly small versions, so that ranges overlap. */
+        smartlist_add_asprintf(chunks, "%u", rng_below(8));
+      } else {
+        smartlist_add_strdup(chunks,
+                             numbers[rng_below(ARRAY_LENGTH(numbers))]);
+      }
+      if (rng_below(2)) {
+        smartlist_add_asprintf(chunks, "-%u", rng_below(8));
+      }
+    }
+  }
+
+  result = smartlist_join_strings(chunks, "", 0, NULL);
+  SMARTLIST_FOREACH(chunks, char *, c, tor_free(c));
+  smartlist_free(chunks);
+  return result;
+}
+
+static void
+usage(void)
+{
+  puts("Usage: test-protover-parity [-v] [-s seed] [-n iterations]\n"
+       "  Compare the C and Rust implementations of protover.");
+}
+
+int
+main(int argc, char **argv)
+{
+  smartlist_t *votes = smartlist_new();
+  int i, j;
+
+  for (i = 1; i < argc; ++i) {
+    if (!strcmp(argv[i], "-v")) {
+      opt_verbose = 1;
+    } else if (!strcmp(argv[i], "-s") && i+1<argc) {
+      opt_seed = tor_parse_uint64(argv[++i], 10, 0, UINT64_MAX, NULL, NULL);
+    } else if (!strcmp(argv[i], "-n") && i+1<argc) {
+      opt_iterations = atoi(argv[++i]);
+    } else if (!strcmp(argv[i], "-h")) {
+      usage();
+      return 0;
+    } else {
+      usage();
+      return 1;
+    }
+  }
+  /* xorshift never leaves zero. */
+  rng_state = opt_seed ? opt_seed : 1;
+
+  init_logging(1);
+  /* Voting in C uses a strmap, which needs the siphash key. */
+  if (crypto_global_init(0, NULL, NULL) < 0) {
+    printf("Couldn't initialize crypto subsystem; exiting.\n");
+    return 1;
+  }
+
+  check_protocol_types();
+
+  /* The corpus, on its own and in votes. */
+  /* protocol_list_supports_protocol() in C doesn't accept NULL. */
+  check_all_supported(NULL);
+  check_compute_for_old_tor(NULL);
+  for (i = 0; corpus[i]; ++i) {
+    check_protocol_list(corpus[i]);
+    for (j = 0; corpus[j]; ++j) {
+      smartlist_add(votes, (char *)corpus[i]);
+      smartlist_add(votes, (char *)corpus[j]);
+      check_compute_vote(votes, 1);
+      check_compute_vote(votes, 2);
+      smartlist_clear(votes);
+    }
+  }
+  for (i = 0; version_corpus[i]; ++i) {
+    check_compute_for_old_tor(version_corpus[i]);
+  }
+  check_compute_vote(votes, 0);
+  check_compute_vote(votes, 1);
+
+  /* Generated inputs. */
+  for (i = 0; i < opt_iterations; ++i) {
+    char *s = generate_protocol_list();
+    int n_votes = rng_below(6);
+
+    check_protocol_list(s);
+    tor_free(s);
+
+    for (j = 0; j < n_votes; ++j) {
+      smartlist_add(votes, generate_protocol_list());
+    }
+    check_compute_vote(votes, rng_below(n_votes + 2));
+    SMARTLIST_FOREACH(votes, char *, v, tor_free(v));
+    smartlist_clear(votes);
+  }
+
+  smartlist_free(votes);
+  c_protover_free_all();
+  protover_free_all();
+  crypto_global_cleanup();
+
+  printf("%d checks, %d differences (seed "U64_FORMAT")\n",
+         n_checks, n_differences, U64_PRINTF_ARG(opt_seed));
+  return n_differences ? 1 : 0;
+}
+
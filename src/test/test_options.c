@@ -434,6 +434,7 @@ get_options_test_data(const char *conf)
   config_free_lines(cl);
   result->opt->LogTimeGranularity = 1;
   result->opt->TokenBucketRefillInterval = 1;
+  result->opt->ChannelType = CHANNEL_TYPE_TLS;
   rv = config_get_lines(TEST_OPTIONS_OLD_VALUES, &cl, 1);
   tt_int_op(rv, OP_EQ, 0);
   rv = config_assign(&options_format, result->def_opt, cl, 0, &msg);
@@ -1322,6 +1323,31 @@ test_options_validate__token_bucket(void *ignored)
   tor_free(msg);
 }
 
+static void
+test_options_validate__channel_type(void *ignored)
+{
+  (void)ignored;
+  int ret;
+  char *msg;
+  options_test_data_t *tdata = get_options_test_data("");
+
+  tdata->opt->ChannelType = CHANNEL_TYPE_UNKNOWN;
+  ret = options_validate(tdata->old_opt, tdata->opt, tdata->def_opt, 0, &msg);
+  tt_int_op(ret, OP_EQ, -1);
+  tt_str_op(msg, OP_EQ, "ChannelType must be between 1 and 4 inclusive.");
+  tor_free(msg);
+
+  tdata->opt->ChannelType = CHANNEL_TYPE_IMUX + 1;
+  ret = options_validate(tdata->old_opt, tdata->opt, tdata->def_opt, 0, &msg);
+  tt_int_op(ret, OP_EQ, -1);
+  tt_str_op(msg, OP_EQ, "ChannelType must be between 1 and 4 inclusive.");
+  tor_free(msg);
+
+ done:
+  free_options_test_data(tdata);
+  tor_free(msg);
+}
+
 static void
 test_options_validate__recommended_packages(void *ignored)
 {
@@ -4245,6 +4271,7 @@ struct testcase_t options_tests[] = {
   LOCAL_VALIDATE_TEST(exclude_nodes),
   LOCAL_VALIDATE_TEST(node_families),
   LOCAL_VALIDATE_TEST(token_bucket),
+  LOCAL_VALIDATE_TEST(channel_type),
   LOCAL_VALIDATE_TEST(recommended_packages),
   LOCAL_VALIDATE_TEST(fetch_dir),
   LOCAL_VALIDATE_TEST(conn_limit),
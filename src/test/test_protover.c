@@ -5,6 +5,7 @@
 
 #include "orconfig.h"
 #include "test.h"
+#include "log_test_helpers.h"
 
 #include "protover.h"
 
@@ -305,6 +306,29 @@ test_protover_all_supported(void *arg)
   tor_free(msg);
 }
 
+static void
+test_protover_describe_parse_error(void *arg)
+{
+  (void)arg;
+  char *msg = NULL;
+
+  tt_ptr_op(protover_describe_parse_error(NULL), OP_EQ, NULL);
+  tt_ptr_op(protover_describe_parse_error(""), OP_EQ, NULL);
+  tt_ptr_op(protover_describe_parse_error("Link=1-4 Wombat=9"), OP_EQ, NULL);
+
+  msg = protover_describe_parse_error("Cons=1-2 Link=1-x");
+  tt_assert(msg);
+#ifdef HAVE_RUST
+  tt_str_op(msg, OP_EQ, "In protocol \"Link\": The protover string was "
+            "unparseable at byte 14: \"1-x\".");
+#else
+  tt_str_op(msg, OP_EQ, "The protover string was unparseable.");
+#endif
+
+ done:
+  tor_free(msg);
+}
+
 static void
 test_protover_list_supports_protocol_returns_true(void *arg)
 {
@@ -331,6 +355,196 @@ test_protover_list_supports_protocol_for_unsupported_returns_false(void *arg)
   ;
 }
 
+/* Make sure that each protocol_type_t is the protocol it is named for, in
+ * whichever implementation we were built with: Rust has its own table of
+ * them. */
+static void
+test_protover_protocol_types(void *arg)
+{
+  static const struct {
+    protocol_type_t type;
+    const char *list;
+  } protocols[] = {
+    { PRT_LINK, "Link=1" },
+    { PRT_LINKAUTH, "LinkAuth=1" },
+    { PRT_RELAY, "Relay=1" },
+    { PRT_DIRCACHE, "DirCache=1" },
+    { PRT_HSDIR, "HSDir=1" },
+    { PRT_HSINTRO, "HSIntro=1" },
+    { PRT_HSREND, "HSRend=1" },
+    { PRT_DESC, "Desc=1" },
+    { PRT_MICRODESC, "Microdesc=1" },
+    { PRT_CONS, "Cons=1" },
+    { PRT_PADDING, "Padding=1" },
+    { PRT_FLOWCTRL, "FlowCtrl=1" },
+    { PRT_QUIC, "Quic=1" },
+    { PRT_IMUX, "Imux=1" },
+    { PRT_CHANTYPE, "ChanType=1" },
+  };
+  unsigned i, j;
+  (void)arg;
+
+  /* PRT_CHANTYPE is the last protocol_type_t. */
+  tt_int_op(ARRAY_LENGTH(protocols), OP_EQ, PRT_CHANTYPE + 1);
+
+  for (i = 0; i < ARRAY_LENGTH(protocols); ++i) {
+    for (j = 0; j < ARRAY_LENGTH(protocols); ++j) {
+      tt_int_op(i == j, OP_EQ,
+                protocol_list_supports_protocol(protocols[i].list,
+                                                protocols[j].type, 1));
+    }
+  }
+
+ done:
+  ;
+}
+
+static void
+test_protover_list_supports_channel_type(void *arg)
+{
+  (void)arg;
+
+  /* No list, or no ChanType in the list, means TLS only. */
+  tt_int_op(1, OP_EQ, protocol_list_supports_channel_type(NULL,
+                                                          CHANNEL_TYPE_TLS));
+  tt_int_op(0, OP_EQ, protocol_list_supports_channel_type(NULL,
+                                                          CHANNEL_TYPE_IMUX));
+  tt_int_op(1, OP_EQ, protocol_list_supports_channel_type("Link=1-5",
+                                                          CHANNEL_TYPE_TLS));
+  tt_int_op(0, OP_EQ, protocol_list_supports_channel_type("Link=1-5",
+                                                          CHANNEL_TYPE_DUAL));
+
+  tt_int_op(1, OP_EQ, protocol_list_supports_channel_type("ChanType=4 Link=1",
+                                                          CHANNEL_TYPE_IMUX));
+  tt_int_op(0, OP_EQ, protocol_list_supports_channel_type("ChanType=4 Link=1",
+                                                          CHANNEL_TYPE_TLS));
+  tt_int_op(0, OP_EQ, protocol_list_supports_channel_type("ChanType=1-4",
+                                                        CHANNEL_TYPE_UNKNOWN));
+  tt_int_op(0, OP_EQ, protocol_list_supports_channel_type("Link=1-x",
+                                                          CHANNEL_TYPE_TLS));
+
+ done:
+  ;
+}
+
+static void
+test_protover_check_requirements(void *arg)
+{
+  (void)arg;
+  char *warning = NULL;
+
+  /* Nothing required or recommended. */
+  tt_int_op(0, OP_EQ, protover_check_requirements(NULL, NULL, NULL, NULL,
+                                                  1, &warning));
+  tt_ptr_op(warning, OP_EQ, NULL);
+
+  /* Only the relay lines apply to a relay. */
+  tt_int_op(0, OP_EQ, protover_check_requirements("Link=999", NULL,
+                                                  "Link=1", "Link=1-2",
+                                                  0, &warning));
+  tt_ptr_op(warning, OP_EQ, NULL);
+
+  /* Missing a recommended protocol means we should warn. */
+  tt_int_op(0, OP_EQ, protover_check_requirements("Link=1", "Wombat=1",
+                                                  NULL, NULL, 1, &warning));
+  tt_assert(strstr(warning, "will eventually stop working as a client"));
+  tt_assert(strstr(warning, "The missing protocols are: Wombat=1"));
+  tor_free(warning);
+
+  /* Missing a required protocol means we should exit. */
+  tt_int_op(1, OP_EQ, protover_check_requirements(NULL, NULL,
+                                                  "Link=1,999 Relay=2",
+                                                  "Wombat=1", 0, &warning));
+  tt_assert(strstr(warning, "will not work as a relay"));
+  tt_assert(strstr(warning, "The missing protocols are: Link=999"));
+  tor_free(warning);
+
+  /* A line we can't parse is warned about, and requires nothing. */
+  setup_full_capture_of_logs(LOG_WARN);
+  tt_int_op(0, OP_EQ, protover_check_requirements("Link=1-x", "Link=1-x",
+                                                  NULL, NULL, 1, &warning));
+  expect_log_msg_containing("unparseable protocol list");
+  tt_ptr_op(warning, OP_EQ, NULL);
+  teardown_capture_of_logs();
+
+  /* The requirements are evaluated even if we don't want a warning. */
+  tt_int_op(1, OP_EQ, protover_check_requirements("Link=999", NULL,
+                                                  NULL, NULL, 1, NULL));
+
+ done:
+  teardown_capture_of_logs();
+  tor_free(warning);
+}
+
+static void
+test_protover_supported_protocols_for_transport(void *arg)
+{
+  (void)arg;
+  char *supported = NULL;
+
+  supported = protover_get_supported_protocols_for_transport(
+                                               CHANNEL_TYPE_UNKNOWN, 0);
+  tt_str_op(supported, OP_EQ, protover_get_supported_protocols());
+  tor_free(supported);
+
+  supported = protover_get_supported_protocols_for_transport(
+                                               CHANNEL_TYPE_IMUX, 1);
+  tt_assert(!strcmpstart(supported, "ChanType=1-4 Cons=1-2 "));
+  tt_assert(strstr(supported, " Microdesc=1-2 Quic=1 Relay=1-2"));
+  tt_int_op(1, OP_EQ, protocol_list_supports_channel_type(supported,
+                                                          CHANNEL_TYPE_IMUX));
+  tor_free(supported);
+
+  /* Whichever channel type we open, we accept all of them. */
+  supported = protover_get_supported_protocols_for_transport(
+                                               CHANNEL_TYPE_TLS, 0);
+  tt_assert(!strcmpstart(supported, "ChanType=1-4 Cons=1-2 "));
+  tt_int_op(1, OP_EQ, protocol_list_supports_channel_type(supported,
+                                                          CHANNEL_TYPE_DUAL));
+
+ done:
+  tor_free(supported);
+}
+
+static void
+test_protover_supported_protocols_override(void *arg)
+{
+  (void)arg;
+  char *compiled_in = tor_strdup(protover_get_supported_protocols());
+  char *msg = NULL;
+
+  tt_int_op(1, OP_EQ, protover_is_supported_here(PRT_LINK, 5));
+
+  tt_ptr_op(NULL, OP_EQ, protover_check_supported_protocols_override(
+                                                "Cons=1-2 Link=1-4"));
+  msg = protover_check_supported_protocols_override("Link=1-6");
+  tt_assert(msg);
+  tor_free(msg);
+  msg = protover_check_supported_protocols_override("Wombat=1");
+  tt_assert(msg);
+  tor_free(msg);
+
+  tt_int_op(0, OP_EQ,
+            protover_set_supported_protocols_override("Cons=1-2 Link=1-4"));
+  tt_str_op(protover_get_supported_protocols(), OP_EQ, "Cons=1-2 Link=1-4");
+  tt_int_op(0, OP_EQ, protover_is_supported_here(PRT_LINK, 5));
+  tt_int_op(1, OP_EQ, protover_is_supported_here(PRT_LINK, 4));
+  tt_int_op(0, OP_EQ, protover_is_supported_here(PRT_RELAY, 1));
+
+  /* A bad override leaves the current one in place. */
+  tt_int_op(-1, OP_EQ, protover_set_supported_protocols_override("Link=5-6"));
+  tt_str_op(protover_get_supported_protocols(), OP_EQ, "Cons=1-2 Link=1-4");
+
+  tt_int_op(0, OP_EQ, protover_set_supported_protocols_override(NULL));
+  tt_str_op(protover_get_supported_protocols(), OP_EQ, compiled_in);
+  tt_int_op(1, OP_EQ, protover_is_supported_here(PRT_LINK, 5));
+
+ done:
+  protover_set_supported_protocols_override(NULL);
+  tor_free(compiled_in);
+  tor_free(msg);
+}
+
 static void
 test_protover_supports_version(void *arg)
 {
@@ -356,10 +570,33 @@ test_protover_supports_version(void *arg)
 
   tt_assert(!protocol_list_supports_protocol_or_later("Link=4-6 LinkAuth=3",
                                                       PRT_DESC, 2));
+
+  /* An unparseable list doesn't support anything. */
+  tt_assert(!protocol_list_supports_protocol("Link=1-x", PRT_LINK, 1));
+  tt_assert(!protocol_list_supports_protocol_or_later("Link=1-x",
+                                                      PRT_LINK, 1));
  done:
  ;
 }
 
+static void
+test_protover_compute_for_old_tor(void *arg)
+{
+  (void)arg;
+
+  tt_str_op(protover_compute_for_old_tor("Tor 0.2.9.3-alpha"), OP_EQ, "");
+  tt_str_op(protover_compute_for_old_tor("Tor 0.2.3.25"), OP_EQ, "");
+  tt_str_op(protover_compute_for_old_tor("Tor 0.2.8.12"), OP_EQ,
+            "Cons=1-2 Desc=1-2 DirCache=1 HSDir=1 HSIntro=3 HSRend=1 "
+            "Link=1-4 LinkAuth=1 Microdesc=1-2 Relay=1-2");
+  tt_str_op(protover_compute_for_old_tor("Tor 0.2.4.19"), OP_EQ,
+            "Cons=1 Desc=1 DirCache=1 HSDir=1 HSIntro=3 HSRend=1 "
+            "Link=1-4 LinkAuth=1 Microdesc=1 Relay=1-2");
+
+ done:
+  ;
+}
+
 /* This could be MAX_PROTOCOLS_TO_EXPAND, but that's not exposed by protover */
 #define MAX_PROTOCOLS_TO_TEST 1024
 
@@ -591,10 +828,17 @@ struct testcase_t protover_tests[] = {
   PV_TEST(parse_fail, 0),
   PV_TEST(vote, 0),
   PV_TEST(all_supported, 0),
+  PV_TEST(describe_parse_error, 0),
   PV_TEST(list_supports_protocol_for_unsupported_returns_false, 0),
   PV_TEST(list_supports_protocol_returns_true, 0),
+  PV_TEST(protocol_types, 0),
+  PV_TEST(list_supports_channel_type, 0),
+  PV_TEST(check_requirements, 0),
   PV_TEST(supports_version, 0),
+  PV_TEST(compute_for_old_tor, 0),
   PV_TEST(supported_protocols, 0),
+  PV_TEST(supported_protocols_for_transport, 0),
+  PV_TEST(supported_protocols_override, TT_FORK),
   PV_TEST(vote_roundtrip, 0),
   END_OF_TESTCASES
 };